@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::common::{Boundary, FractalResult, Model};
+use crate::compute::{ComputeError, ComputeMonitor, Progress};
+use crate::graph::GraphResult;
+
+// the sites a configuration lives on, and what toppling each of them sends to the others. grains sent to the sink or
+// lost by the kernel are left out
+struct Network {
+	edges: Vec<Vec<(usize, u64)>>,
+	thresholds: Vec<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Site {
+	Cell { x: i64, y: i64 },
+	Vertex { index: usize, label: String },
+}
+
+impl fmt::Display for Site {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Site::Cell { x, y } => write!(f, "({}, {})", x, y),
+			Site::Vertex { label, .. } => write!(f, "vertex '{}'", label),
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct ForbiddenSite {
+	pub site: Site,
+	pub grains: u64,
+	// what the other unburnt sites, and the site itself through its loops, would send it by toppling once
+	pub received: u64,
+}
+
+// the outcome of Dhar's burning algorithm
+#[derive(Clone, Debug)]
+pub struct Burning {
+	pub site_count: usize,
+	// the sites the fire never reached. together they're a forbidden sub-configuration: each holds fewer grains than
+	// the others would send it by toppling once, which never happens in a recurrent configuration
+	pub forbidden: Vec<ForbiddenSite>,
+	// whether each cell of the result's array stayed unburnt, for highlighting them. empty for a graph
+	pub unburnt_mask: Vec<bool>,
+}
+
+const MAX_REPORTED_SITES: usize = 10;
+
+impl Burning {
+	pub fn is_recurrent(&self) -> bool {
+		self.forbidden.is_empty()
+	}
+
+	pub fn report(&self) -> String {
+		if self.is_recurrent() {
+			return format!("Recurrent: all {} sites burnt", self.site_count);
+		}
+
+		let mut lines = vec![format!(
+			"Not recurrent: {} of {} sites never burnt, and form a forbidden sub-configuration:",
+			self.forbidden.len(), self.site_count,
+		)];
+		for forbidden in self.forbidden.iter().take(MAX_REPORTED_SITES) {
+			lines.push(format!("  {} holds {}, but the rest of it would send {}", forbidden.site, forbidden.grains, forbidden.received));
+		}
+		if self.forbidden.len() > MAX_REPORTED_SITES {
+			lines.push(format!("  and {} more", self.forbidden.len() - MAX_REPORTED_SITES));
+		}
+		lines.join("\n")
+	}
+}
+
+const REPORT_INTERVAL: usize = 1 << 16;
+
+// adds to every site what the sink would send it by toppling, which is what the sites send the sink plus what the
+// kernel loses, and lets the fire spread from there. every site of a recurrent configuration topples exactly once, and
+// the configuration comes back. the sites that don't are the unburnt ones
+fn burn<S: Fn(usize) -> Site>(network: &Network, configuration: &[u64], site_name: S, monitor: Option<&ComputeMonitor>) -> Result<Vec<bool>, ComputeError> {
+	let site_count = network.thresholds.len();
+	let mut incoming = vec![0u64; site_count];
+	for edges in &network.edges {
+		for &(target, amount) in edges {
+			incoming[target] += amount;
+		}
+	}
+	if (0..site_count).any(|site| incoming[site] > network.thresholds[site]) {
+		return Err(ComputeError::InvalidParameter("the burning algorithm needs every site to take at least as many grains to topple as it can receive from the others".into()));
+	}
+	if let Some(site) = (0..site_count).find(|&site| configuration[site] >= network.thresholds[site]) {
+		return Err(ComputeError::InvalidParameter(format!("the configuration isn't stable: {} holds {} grains", site_name(site), configuration[site])));
+	}
+
+	let mut values: Vec<u64> = (0..site_count).map(|site| configuration[site] + network.thresholds[site] - incoming[site]).collect();
+	let mut queued: Vec<bool> = (0..site_count).map(|site| values[site] >= network.thresholds[site]).collect();
+	let mut queue: VecDeque<usize> = (0..site_count).filter(|&site| queued[site]).collect();
+
+	// the fire has to be able to reach every site from the ones next to the sink, or a site could stay unburnt in a
+	// recurrent configuration too
+	let mut reached = vec![false; site_count];
+	let mut frontier: Vec<usize> = (0..site_count).filter(|&site| network.thresholds[site] > incoming[site]).collect();
+	for &site in &frontier {
+		reached[site] = true;
+	}
+	while let Some(site) = frontier.pop() {
+		for &(target, _) in &network.edges[site] {
+			if !reached[target] {
+				reached[target] = true;
+				frontier.push(target);
+			}
+		}
+	}
+	if reached.iter().any(|&reached| !reached) {
+		return Err(ComputeError::InvalidParameter("the burning algorithm needs every site to be reachable from the ones next to the sink".into()));
+	}
+
+	let mut burnt = vec![false; site_count];
+	let mut burnt_count = 0;
+	while let Some(site) = queue.pop_front() {
+		burnt[site] = true;
+		values[site] -= network.thresholds[site];
+		for &(target, amount) in &network.edges[site] {
+			values[target] += amount;
+			if !queued[target] && values[target] >= network.thresholds[target] {
+				queued[target] = true;
+				queue.push_back(target);
+			}
+		}
+
+		burnt_count += 1;
+		if let Some(monitor) = monitor {
+			if burnt_count % REPORT_INTERVAL == 0 {
+				if monitor.is_cancelled() {
+					return Err(ComputeError::Cancelled);
+				}
+				monitor.report(Progress {
+					iterations: burnt_count,
+					side_length: (site_count as f64).sqrt() as usize,
+					redistributions: 1,
+					estimated_completion: burnt_count as f64 / site_count as f64,
+				});
+			}
+		}
+	}
+	Ok(burnt)
+}
+
+// what the unburnt sites would send each other by toppling once
+fn forbidden_sites<S: Fn(usize) -> Site>(network: &Network, configuration: &[u64], burnt: &[bool], site_name: S) -> Vec<ForbiddenSite> {
+	let mut received = vec![0u64; burnt.len()];
+	for source in (0..burnt.len()).filter(|&source| !burnt[source]) {
+		for &(target, amount) in &network.edges[source] {
+			received[target] += amount;
+		}
+	}
+	(0..burnt.len()).filter(|&index| !burnt[index]).map(|index| ForbiddenSite {
+		site: site_name(index),
+		grains: configuration[index],
+		received: received[index],
+	}).collect()
+}
+
+// checks whether a stable pile on a domain with a sink boundary is recurrent
+pub fn burn_grid(result: &FractalResult, monitor: Option<&ComputeMonitor>) -> Result<Burning, ComputeError> {
+	let size = match result.boundary {
+		Boundary::Sink { size } => size,
+		_ => return Err(ComputeError::InvalidParameter("only configurations on a domain with a sink boundary can be recurrent".into())),
+	};
+	if result.model != Model::Abelian {
+		return Err(ComputeError::InvalidParameter(format!("the burning algorithm only applies to the abelian model, not the {} model", result.model.name())));
+	}
+
+	let start = result.boundary.domain_start();
+	let site_position = |site: usize| ((site % size) as i64 + start, (site / size) as i64 + start);
+	let mut network = Network { edges: Vec::with_capacity(size * size), thresholds: vec![u64::from(result.topple_rule.threshold); size * size] };
+	let mut configuration = Vec::with_capacity(size * size);
+	for site in 0..size * size {
+		let (x, y) = site_position(site);
+		network.edges.push(result.topple_rule.kernel_for(x, y).iter()
+			.filter(|neighbor| result.boundary.contains(x + neighbor.dx as i64, y + neighbor.dy as i64))
+			.map(|neighbor| (((y - start + neighbor.dy as i64) as usize) * size + (x - start + neighbor.dx as i64) as usize, u64::from(neighbor.amount)))
+			.collect());
+		configuration.push(match result.world_to_index(x, y) {
+			Some(index) => u64::from(result.sand_data[index]),
+			None => u64::from(result.background_height),
+		});
+	}
+
+	let site_name = |site: usize| {
+		let (x, y) = site_position(site);
+		Site::Cell { x, y }
+	};
+	let burnt = burn(&network, &configuration, site_name, monitor)?;
+	let unburnt_mask = (0..result.sand_data.len()).map(|index| {
+		let (x, y) = result.index_to_world(index);
+		result.boundary.contains(x, y) && !burnt[((y - start) as usize) * size + (x - start) as usize]
+	}).collect();
+
+	Ok(Burning {
+		site_count: size * size,
+		forbidden: forbidden_sites(&network, &configuration, &burnt, site_name),
+		unburnt_mask,
+	})
+}
+
+// checks whether the chips on a graph with a sink are recurrent
+pub fn burn_graph(result: &GraphResult, monitor: Option<&ComputeMonitor>) -> Result<Burning, ComputeError> {
+	let graph = &result.graph;
+	let sink = graph.sink.ok_or_else(|| ComputeError::InvalidParameter("only configurations on a graph with a sink can be recurrent".into()))?;
+
+	// chips have to be able to reach the sink from every vertex
+	let mut reaches_sink = vec![false; graph.vertex_count()];
+	let mut in_edges: Vec<Vec<usize>> = vec![Vec::new(); graph.vertex_count()];
+	for vertex in (0..graph.vertex_count()).filter(|&vertex| vertex != sink) {
+		for &(target, _) in &graph.edges[vertex] {
+			in_edges[target].push(vertex);
+		}
+	}
+	reaches_sink[sink] = true;
+	let mut stack = vec![sink];
+	while let Some(vertex) = stack.pop() {
+		for &source in &in_edges[vertex] {
+			if !reaches_sink[source] {
+				reaches_sink[source] = true;
+				stack.push(source);
+			}
+		}
+	}
+	if let Some(vertex) = reaches_sink.iter().position(|&reaches| !reaches) {
+		return Err(ComputeError::InvalidParameter(format!("chips can't reach the sink from vertex '{}'", graph.labels[vertex])));
+	}
+
+	let vertices: Vec<usize> = (0..graph.vertex_count()).filter(|&vertex| vertex != sink).collect();
+	let site = |vertex: usize| if vertex < sink { vertex } else { vertex - 1 };
+	let network = Network {
+		edges: vertices.iter().map(|&vertex| {
+			graph.edges[vertex].iter().filter(|&&(target, _)| target != sink).map(|&(target, multiplicity)| (site(target), u64::from(multiplicity))).collect()
+		}).collect(),
+		thresholds: vertices.iter().map(|&vertex| graph.out_degree(vertex)).collect(),
+	};
+	let configuration: Vec<u64> = vertices.iter().map(|&vertex| result.chips[vertex]).collect();
+
+	let site_name = |site: usize| {
+		let vertex = vertices[site];
+		Site::Vertex { index: vertex, label: graph.labels[vertex].clone() }
+	};
+	let burnt = burn(&network, &configuration, site_name, monitor)?;
+	Ok(Burning {
+		site_count: vertices.len(),
+		forbidden: forbidden_sites(&network, &configuration, &burnt, site_name),
+		unburnt_mask: Vec::new(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::{Symmetry, ToppleRule};
+	use crate::compute::{identity, Engine};
+	use crate::compute::tests::stabilize_with;
+	use crate::graph::{complete, stabilize_graph};
+
+	#[test]
+	fn identity_is_recurrent() {
+		let burning = burn_grid(&identity(3).unwrap(), None).unwrap();
+		assert!(burning.is_recurrent());
+		assert_eq!(burning.site_count, 9);
+		assert!(!burning.unburnt_mask.contains(&true));
+	}
+
+	#[test]
+	fn empty_grid_is_not_recurrent() {
+		let empty = stabilize_with::<u32>(&[], &ToppleRule::von_neumann(), Boundary::Sink { size: 3 }, Symmetry::None, 0, Engine::Dense).unwrap();
+		let burning = burn_grid(&empty, None).unwrap();
+		assert!(!burning.is_recurrent());
+		// even the corners only get two grains from the sink
+		assert_eq!(burning.forbidden.len(), 9);
+		let center = burning.forbidden.iter().find(|forbidden| forbidden.site == Site::Cell { x: 0, y: 0 }).unwrap();
+		assert_eq!((center.grains, center.received), (0, 4));
+		assert_eq!(burning.unburnt_mask.iter().filter(|&&unburnt| unburnt).count(), 9);
+	}
+
+	#[test]
+	fn graphs_need_enough_chips() {
+		// every vertex of a complete graph has one edge to the sink and fires at its degree
+		let full = stabilize_graph(complete(4).with_sink("0").unwrap(), &[], 2, None).unwrap();
+		assert!(burn_graph(&full, None).unwrap().is_recurrent());
+
+		let empty = stabilize_graph(complete(4).with_sink("0").unwrap(), &[], 0, None).unwrap();
+		let burning = burn_graph(&empty, None).unwrap();
+		assert!(!burning.is_recurrent());
+		assert_eq!(burning.site_count, 3);
+		assert_eq!(burning.forbidden.len(), 3);
+
+		let without_sink = stabilize_graph(complete(4), &[], 2, None).unwrap();
+		assert!(matches!(burn_graph(&without_sink, None), Err(ComputeError::InvalidParameter(_))));
+	}
+}
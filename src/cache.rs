@@ -1,17 +1,26 @@
 use std::fs;
+use std::io::{BufReader, BufWriter};
 
 use bincode::{serialize_into, deserialize_from};
-use crate::common::{InitialCell, FractalResult};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_derive::{Serialize, Deserialize};
+use crate::common::{Boundary, InitialCell, FractalResult, Model, Symmetry, ToppleRule};
 
 const CACHE_FILE: &'static str = "fractaldata.cache";
+const CHECKPOINT_FILE: &'static str = "fractaldata.checkpoint";
 
-pub fn load_from_cache(initial_configuration: &[InitialCell]) -> Option<FractalResult> {
+pub fn load_from_cache(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, model: Model, boundary: Boundary, background_height: u32) -> Option<FractalResult> {
 	if let Ok(mut file) = fs::File::open(CACHE_FILE) {
-		let fractal_data: FractalResult = deserialize_from(&mut file).unwrap();
-		if fractal_data.initial_configuration == initial_configuration {
-			return Some(fractal_data);
-		} else {
-			fs::remove_file(CACHE_FILE).unwrap();
+		// a cache file written by an older version won't deserialize, so treat it the same as a mismatch
+		let cached: Option<FractalResult> = deserialize_from(&mut file).ok();
+		match cached {
+			Some(fractal_data) if fractal_data.initial_configuration == initial_configuration
+				&& fractal_data.topple_rule == *topple_rule
+				&& fractal_data.model == model
+				&& fractal_data.boundary == boundary
+				&& fractal_data.background_height == background_height => return Some(fractal_data),
+			_ => fs::remove_file(CACHE_FILE).unwrap(),
 		}
 	}
 
@@ -26,4 +35,69 @@ pub fn save_to_cache(fractal_data: &FractalResult) -> Result<(),()> {
 	} else {
 		Err(())
 	}
-}
\ No newline at end of file
+}
+
+// identifies the computation a checkpoint belongs to
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+pub struct CheckpointKey {
+	pub initial_configuration: Vec<InitialCell>,
+	pub topple_rule: ToppleRule,
+	pub model: Model,
+	pub boundary: Boundary,
+	pub background_height: u32,
+	pub symmetry: Symmetry,
+	// size in bytes of the type the grains are stored in
+	pub grain_size: usize,
+}
+
+// the state of a computation part of the way through. it's saved from slices of the arrays, and loaded back into vectors
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint<G, C, O> {
+	pub grains: G,
+	pub count_data: C,
+	pub odometer_data: O,
+	pub side_length: usize,
+	pub origin_x: i64,
+	pub origin_y: i64,
+	pub next_check: usize,
+	pub total_iterations: usize,
+	pub total_redistributions: u64,
+	pub absorbed_grains: u64,
+}
+
+pub fn save_checkpoint<G: Serialize, C: Serialize, O: Serialize>(key: &CheckpointKey, checkpoint: &Checkpoint<G, C, O>) -> Result<(),()> {
+	// write to a separate file first, so that dying part of the way through doesn't destroy the previous checkpoint
+	let temporary_file = format!("{}.tmp", CHECKPOINT_FILE);
+	let mut file = BufWriter::new(fs::File::create(&temporary_file).map_err(|_| ())?);
+
+	serialize_into(&mut file, key).map_err(|_| ())?;
+	serialize_into(&mut file, checkpoint).map_err(|_| ())?;
+	let file = file.into_inner().map_err(|_| ())?;
+	file.sync_all().map_err(|_| ())?;
+
+	fs::rename(&temporary_file, CHECKPOINT_FILE).map_err(|_| ())
+}
+
+pub fn load_checkpoint<T: DeserializeOwned>(key: &CheckpointKey) -> Option<Checkpoint<Vec<T>, Vec<u32>, Vec<u64>>> {
+	let mut file = BufReader::new(fs::File::open(CHECKPOINT_FILE).ok()?);
+
+	// the key is written separately, so that the grains are only read once it's clear what type they're stored in
+	let saved_key: CheckpointKey = deserialize_from(&mut file).ok()?;
+	if saved_key != *key {
+		return None;
+	}
+	deserialize_from(&mut file).ok()
+}
+
+// whether a computation can be picked up from a checkpoint, without reading the checkpoint's grains
+pub fn has_checkpoint(key: &CheckpointKey) -> bool {
+	let mut file = match fs::File::open(CHECKPOINT_FILE) {
+		Ok(file) => BufReader::new(file),
+		Err(_) => return false,
+	};
+	deserialize_from::<_, CheckpointKey>(&mut file).map_or(false, |saved_key| saved_key == *key)
+}
+
+pub fn remove_checkpoint() {
+	let _ = fs::remove_file(CHECKPOINT_FILE);
+}
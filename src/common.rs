@@ -1,20 +1,343 @@
 
+use std::cmp::max;
+use std::fs;
+use std::io::{self, BufWriter, Write};
 use serde_derive::{Serialize, Deserialize};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct InitialCell {
-	pub x: usize,
-	pub y: usize,
-	pub value: u32,
+	pub x: i64,
+	pub y: i64,
+	pub value: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ToppleNeighbor {
+	pub dx: isize,
+	pub dy: isize,
+	pub amount: u32,
+}
+
+// every lattice is stored in a square array:
+// - hexagonal cells use axial coordinates, so the cell at (x, y) also touches (x+1, y-1) and (x-1, y+1)
+// - triangular cells alternate between pointing up and pointing down, depending on the parity of x + y
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lattice {
+	Square,
+	Hexagonal,
+	Triangular,
+}
+
+// a cell holding at least `threshold` grains topples, sending `amount` grains to each neighbor in the kernel.
+// if the kernel sends out fewer grains than the threshold, the difference is lost.
+// cells where x + y is odd use `odd_kernel` instead, if there is one
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ToppleRule {
+	pub lattice: Lattice,
+	pub threshold: u32,
+	pub kernel: Vec<ToppleNeighbor>,
+	pub odd_kernel: Option<Vec<ToppleNeighbor>>,
+}
+
+impl ToppleRule {
+	pub fn von_neumann() -> Self {
+		Self::extended_von_neumann(1)
+	}
+
+	pub fn moore() -> Self {
+		let mut kernel = Vec::new();
+		for dy in -1..=1 {
+			for dx in -1..=1 {
+				if dx != 0 || dy != 0 {
+					kernel.push(ToppleNeighbor { dx, dy, amount: 1 });
+				}
+			}
+		}
+		Self { lattice: Lattice::Square, threshold: kernel.len() as u32, kernel, odd_kernel: None }
+	}
+
+	// every cell within the given manhattan distance receives one grain
+	pub fn extended_von_neumann(radius: usize) -> Self {
+		let radius = radius as isize;
+
+		let mut kernel = Vec::new();
+		for dy in -radius..=radius {
+			for dx in -radius..=radius {
+				if (dx != 0 || dy != 0) && dx.abs() + dy.abs() <= radius {
+					kernel.push(ToppleNeighbor { dx, dy, amount: 1 });
+				}
+			}
+		}
+		Self { lattice: Lattice::Square, threshold: kernel.len() as u32, kernel, odd_kernel: None }
+	}
+
+	pub fn hexagonal() -> Self {
+		let offsets = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, -1), (-1, 1)];
+		let kernel: Vec<ToppleNeighbor> = offsets.iter().map(|&(dx, dy)| ToppleNeighbor { dx, dy, amount: 1 }).collect();
+		Self { lattice: Lattice::Hexagonal, threshold: kernel.len() as u32, kernel, odd_kernel: None }
+	}
+
+	// up-pointing triangles touch the triangle below them, down-pointing triangles touch the triangle above them
+	pub fn triangular() -> Self {
+		let up_offsets = [(1, 0), (-1, 0), (0, 1)];
+		let down_offsets = [(1, 0), (-1, 0), (0, -1)];
+		let kernel: Vec<ToppleNeighbor> = up_offsets.iter().map(|&(dx, dy)| ToppleNeighbor { dx, dy, amount: 1 }).collect();
+		let odd_kernel: Vec<ToppleNeighbor> = down_offsets.iter().map(|&(dx, dy)| ToppleNeighbor { dx, dy, amount: 1 }).collect();
+		Self { lattice: Lattice::Triangular, threshold: kernel.len() as u32, kernel, odd_kernel: Some(odd_kernel) }
+	}
+
+	pub fn weighted(threshold: u32, kernel: Vec<ToppleNeighbor>) -> Option<Self> {
+		let rule = Self { lattice: Lattice::Square, threshold, kernel, odd_kernel: None };
+		if rule.is_valid() {
+			Some(rule)
+		} else {
+			None
+		}
+	}
+
+	pub fn is_valid(&self) -> bool {
+		let kernel_is_valid = |kernel: &[ToppleNeighbor]| {
+			kernel.len() > 0
+				&& kernel.iter().all(|neighbor| neighbor.dx != 0 || neighbor.dy != 0)
+				&& kernel.iter().map(|neighbor| u64::from(neighbor.amount)).sum::<u64>() <= u64::from(self.threshold)
+		};
+
+		self.threshold > 0 && self.kernels().all(kernel_is_valid)
+	}
+
+	pub fn kernels(&self) -> impl Iterator<Item=&[ToppleNeighbor]> {
+		std::iter::once(self.kernel.as_slice()).chain(self.odd_kernel.as_ref().map(|kernel| kernel.as_slice()))
+	}
+
+	pub fn kernel_for(&self, x: i64, y: i64) -> &[ToppleNeighbor] {
+		match &self.odd_kernel {
+			Some(odd_kernel) if (x + y).rem_euclid(2) == 1 => odd_kernel,
+			_ => &self.kernel,
+		}
+	}
+
+	// the most grains a single topple can send out, across all kernels
+	pub fn grains_sent(&self) -> u64 {
+		self.kernels().map(|kernel| kernel.iter().map(|neighbor| u64::from(neighbor.amount)).sum()).max().unwrap_or(0)
+	}
+
+	// how far away from the toppling cell grains can land
+	pub fn radius(&self) -> usize {
+		self.kernels().flat_map(|kernel| kernel.iter()).map(|neighbor| max(neighbor.dx.abs(), neighbor.dy.abs()) as usize).max().unwrap_or(0)
+	}
+}
+
+impl Default for ToppleRule {
+	fn default() -> Self {
+		Self::von_neumann()
+	}
+}
+
+// fixed-size domains cover world coordinates -size/2 .. size - size/2 in both directions
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Boundary {
+	// an infinite plane. the array grows to fit the pile
+	Plane,
+	// grains that leave the domain are lost
+	Sink { size: usize },
+	// grains that leave one side of the domain come back in on the opposite side
+	Torus { size: usize },
+	// grains that leave the domain are mirrored back in, as if the walls were halfway between cells
+	Reflecting { size: usize },
+}
+
+impl Boundary {
+	pub fn domain_size(&self) -> Option<usize> {
+		match *self {
+			Boundary::Plane => None,
+			Boundary::Sink { size } | Boundary::Torus { size } | Boundary::Reflecting { size } => Some(size),
+		}
+	}
+
+	pub fn domain_start(&self) -> i64 {
+		-(self.domain_size().unwrap_or(0) as i64 / 2)
+	}
+
+	pub fn contains(&self, x: i64, y: i64) -> bool {
+		match self.domain_size() {
+			None => true,
+			Some(size) => {
+				let start = self.domain_start();
+				let end = start + size as i64;
+				x >= start && x < end && y >= start && y < end
+			}
+		}
+	}
+}
+
+// which symmetry the computation took advantage of. results are always expanded back to the full pile
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Symmetry {
+	None,
+	Quadrant,
+}
+
+// how cells topple. the abelian pile stabilizes to the same configuration whatever order cells topple in, but the
+// stochastic models don't, so their results depend on the update order as well as on the seed
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Model {
+	Abelian,
+	// real-valued mass, see compute_divisible
+	Divisible,
+	// a toppling cell sends `threshold` grains one at a time, each to a neighbor chosen at random in proportion to the
+	// kernel amounts
+	Manna { seed: u64 },
+	// cells topple like the abelian pile, but each cell's threshold is drawn at random from threshold and threshold + 1,
+	// and drawn again after every topple
+	Oslo { seed: u64 },
+	// particles instead of grains, see rotor::rotor_aggregation
+	RotorRouter,
+}
+
+impl Model {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Model::Abelian => "abelian",
+			Model::Divisible => "divisible",
+			Model::Manna { .. } => "Manna",
+			Model::Oslo { .. } => "Oslo",
+			Model::RotorRouter => "rotor-router",
+		}
+	}
+
+	pub fn seed(&self) -> Option<u64> {
+		match *self {
+			Model::Manna { seed } | Model::Oslo { seed } => Some(seed),
+			Model::Abelian | Model::Divisible | Model::RotorRouter => None,
+		}
+	}
+
+	pub fn is_stochastic(&self) -> bool {
+		self.seed().is_some()
+	}
+
+	pub fn update_order(&self) -> &'static str {
+		match self {
+			Model::Abelian => "any order gives the same result. cells holding several times the threshold topple that many times at once",
+			Model::Divisible => "synchronous: every cell above 1 + tolerance at the start of an iteration topples once, sending out all of its excess",
+			Model::Manna { .. } | Model::Oslo { .. } => "synchronous: every cell that can topple at the start of an iteration topples exactly once, \
+				and the grains it sends out only count from the next iteration on. the random choices of a topple depend only on \
+				the seed, the cell's coordinates and the number of times it toppled before",
+			Model::RotorRouter => "any order gives the same cluster and rotors. particles that pile up on a cell are sent on all at once, \
+				as many as would have followed the rotor round one at a time",
+		}
+	}
+}
+
+// the per-cell values of a result that can be rendered or exported
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataField {
+	Sand,
+	ToppleSteps,
+	Odometer,
+	// where each cell's rotor points, for the rotor-router model
+	Rotor,
+}
+
+impl DataField {
+	pub fn name(&self) -> &'static str {
+		match self {
+			DataField::Sand => "sand",
+			DataField::ToppleSteps => "topple_steps",
+			DataField::Odometer => "odometer",
+			DataField::Rotor => "rotor",
+		}
+	}
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FractalResult {
 	pub initial_configuration: Vec<InitialCell>,
+	pub topple_rule: ToppleRule,
+	// along with its seed, for the stochastic models
+	pub model: Model,
+	pub symmetry: Symmetry,
+	pub boundary: Boundary,
+	// grains every cell held before the sources were added
+	pub background_height: u32,
 	pub sand_data: Vec<u8>,
+	// the real-valued mass of each cell of a divisible sandpile. empty for the abelian pile
+	pub mass_data: Vec<f64>,
+	// the position of each cell's rotor in its cycle, see rotor::rotor_cycle. empty except for the rotor-router model
+	pub rotor_data: Vec<u32>,
+	// number of iterations in which each cell toppled
 	pub count_data: Vec<u32>,
+	// total number of times each cell toppled, counting every topple of a cell that held several times the threshold.
+	// this is the odometer function that the scaling limit describes. empty for the divisible sandpile
+	pub odometer_data: Vec<u64>,
+	// the total mass each cell of a divisible sandpile toppled away, which is its odometer. empty for the other models
+	pub odometer_mass: Vec<f64>,
 	pub side_length: usize,
 
-	pub total_redistributions: i64,
+	// world coordinates of the cell at array index 0
+	pub origin_x: i64,
+	pub origin_y: i64,
+
+	// the sum of count_data
+	pub total_redistributions: u64,
+	// the sum of the odometer
+	pub total_topples: u64,
+	// grains that fell off the edge of a sink boundary
+	pub absorbed_grains: u64,
+	// the same for the mass of a divisible sandpile
+	pub absorbed_mass: f64,
 	pub total_iterations: usize,
-}
\ No newline at end of file
+}
+
+impl FractalResult {
+	pub fn world_to_index(&self, x: i64, y: i64) -> Option<usize> {
+		let array_x = x - self.origin_x;
+		let array_y = y - self.origin_y;
+		let side_length = self.side_length as i64;
+
+		if array_x >= 0 && array_x < side_length && array_y >= 0 && array_y < side_length {
+			Some((array_y * side_length + array_x) as usize)
+		} else {
+			None
+		}
+	}
+
+	pub fn index_to_world(&self, index: usize) -> (i64, i64) {
+		let array_x = (index % self.side_length) as i64;
+		let array_y = (index / self.side_length) as i64;
+
+		(array_x + self.origin_x, array_y + self.origin_y)
+	}
+
+	// the sand and odometer of a divisible sandpile are its mass
+	pub fn field_value(&self, field: DataField, index: usize) -> f64 {
+		match field {
+			DataField::Sand if !self.mass_data.is_empty() => self.mass_data[index],
+			DataField::Sand => f64::from(self.sand_data[index]),
+			DataField::ToppleSteps => f64::from(self.count_data[index]),
+			DataField::Odometer if !self.odometer_mass.is_empty() => self.odometer_mass[index],
+			DataField::Odometer => self.odometer_data[index] as f64,
+			DataField::Rotor => self.rotor_data.get(index).map_or(0.0, |&rotor| f64::from(rotor)),
+		}
+	}
+
+	// writes one row of the array per line, preceded by a header with the world x coordinate of each column.
+	// each line starts with the world y coordinate of its row
+	pub fn write_field_csv(&self, field: DataField, path: &str) -> io::Result<()> {
+		let mut file = BufWriter::new(fs::File::create(path)?);
+		write!(file, "y\\x")?;
+		for x in 0..self.side_length {
+			write!(file, ",{}", self.origin_x + x as i64)?;
+		}
+		writeln!(file)?;
+
+		for y in 0..self.side_length {
+			write!(file, "{}", self.origin_y + y as i64)?;
+			for x in 0..self.side_length {
+				write!(file, ",{}", self.field_value(field, y * self.side_length + x))?;
+			}
+			writeln!(file)?;
+		}
+		file.flush()
+	}
+}
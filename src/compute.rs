@@ -1,101 +1,1460 @@
 use std::sync::Arc;
 use std::cmp::{min, max};
 use rayon::prelude::*;
-use crate::common::{InitialCell, FractalResult};
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Sub, SubAssign};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::cache::{self, Checkpoint, CheckpointKey};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::sparse::ActiveTiles;
+use crate::timeline::{Schedule, TimelineRecorder};
+use crate::driven::{run_driven, DrivenResult, DrivenSettings, DropSite};
+use crate::reference::{self, Verification};
+use crate::cubic::{run_cubic, InitialVoxel, VolumeResult};
+use crate::graph::{self, stabilize_graph, Graph, GraphResult, InitialVertex};
+use crate::group::{self, SandpileGroup};
+use crate::burning::{self, Burning};
+use crate::periodic::{self, Orbit};
+use crate::rotor;
+use crate::random::Random;
+use crate::common::{Boundary, InitialCell, FractalResult, Lattice, Model, Symmetry, ToppleNeighbor, ToppleRule};
 
-const TOPPLE_AMOUNT: u32 = 4;
-const TOPPLE_WIDTH: usize = 3;
-const MARGIN: usize = TOPPLE_WIDTH / 2;
+// the margin is the radius of the topple kernel. each parallel chunk is split into a margin, two margins' worth of rows
+// that get processed, and another margin, so that neighboring chunks never write to the same rows in the same pass
+pub(crate) fn rows_per_chunk(margin: usize) -> usize {
+	margin * 4
+}
+
+pub(crate) fn required_size_multiple(margin: usize) -> usize {
+	rows_per_chunk(margin) / 2
+}
+
+// the type used to store grain counts while computing. u32 is used whenever the total number of grains fits,
+// since no cell can ever hold more grains than the total
+pub trait Grains: Copy + Send + Sync + Default + Ord + Hash + From<u32> + Into<u64> + Serialize + DeserializeOwned
+	+ Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + Div<Output=Self> + AddAssign + SubAssign
+{
+	fn from_u64(value: u64) -> Self;
+}
+
+impl Grains for u32 {
+	fn from_u64(value: u64) -> Self {
+		value as u32
+	}
+}
+
+impl Grains for u64 {
+	fn from_u64(value: u64) -> Self {
+		value
+	}
+}
+
+#[derive(Debug, Clone)]
+pub enum ComputeError {
+	InvalidParameter(String),
+	NeverStabilizes(String),
+	Cancelled,
+	Io(String),
+}
+
+impl fmt::Display for ComputeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ComputeError::InvalidParameter(message) => write!(f, "Invalid parameter: {}", message),
+			ComputeError::NeverStabilizes(message) => write!(f, "The pile will never stabilize: {}", message),
+			ComputeError::Cancelled => write!(f, "The computation was cancelled"),
+			ComputeError::Io(message) => write!(f, "I/O error: {}", message),
+		}
+	}
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Progress {
+	pub iterations: usize,
+	pub side_length: usize,
+	// topples in the most recent iteration
+	pub redistributions: u64,
+	// between 0 and 1, based on how many of the grains above the stable height have been toppled away so far
+	pub estimated_completion: f64,
+}
+
+// shared between a computation and whoever started it, to follow its progress and cancel it
+#[derive(Default)]
+pub struct ComputeMonitor {
+	progress: Mutex<Progress>,
+	cancelled: AtomicBool,
+}
+
+impl ComputeMonitor {
+	pub fn progress(&self) -> Progress {
+		self.progress.lock().unwrap().clone()
+	}
+
+	// the computation stops with ComputeError::Cancelled at the start of its next iteration
+	pub fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.cancelled.load(Ordering::Relaxed)
+	}
+
+	pub(crate) fn report(&self, progress: Progress) {
+		*self.progress.lock().unwrap() = progress;
+	}
+}
+
+// the sparse engine only processes the parts of the array that can topple. both engines produce identical results
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Engine {
+    Dense,
+    Sparse,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoundaryKind {
+    Plane,
+    Sink,
+    Torus,
+    Reflecting,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum KernelKind {
+    VonNeumann,
+    Moore,
+    Extended,
+    Weighted,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModelKind {
+    Abelian,
+    Manna,
+    Oslo,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GraphKind {
+    File,
+    Torus,
+    Complete,
+    Tree,
+    Sierpinski,
+}
+
+#[derive(Clone)]
+pub struct SourceParams {
+    pub x: String,
+    pub y: String,
+    // only used on the cubic lattice
+    pub z: String,
+    // only used for chip-firing on a graph, where sources are placed on a vertex by its label
+    pub vertex: String,
+    pub value: String,
+}
+
+impl Default for SourceParams {
+    fn default() -> Self {
+        Self {
+            x: "0".into(),
+            y: "0".into(),
+            z: "0".into(),
+            vertex: "0".into(),
+            value: "2000".into(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ComputeParams {
+    pub sources: Vec<SourceParams>,
+    pub lattice: Lattice,
+    // the kernel settings only apply to the square lattice
+    pub kernel: KernelKind,
+    pub kernel_radius: String,
+    // semicolon-separated list of "dx,dy,amount" entries, used by the weighted kernel
+    pub kernel_stencil: String,
+    // if left empty, the weighted kernel's threshold is the number of grains it sends out
+    pub kernel_threshold: String,
+    // only simulate one quadrant when the configuration allows it
+    pub symmetric: bool,
+    pub engine: Engine,
+    pub boundary: BoundaryKind,
+    // side length of the fixed-size domain used by every boundary except the plane
+    pub domain_size: String,
+    // number of grains every cell holds before the sources are added
+    pub background_height: String,
+    // file to record snapshots of the pile to while it stabilizes. nothing is recorded if it's left empty
+    pub timeline_path: String,
+    // iterations between snapshots, or snapshots per doubling of the iteration count if they're spaced logarithmically
+    pub timeline_interval: String,
+    pub timeline_logarithmic: bool,
+    // seconds between checkpoints that an interrupted computation can resume from. 0 or empty turns them off
+    pub checkpoint_interval: String,
+    // driven mode drops grains one at a time, either on the first source or on random cells of the domain
+    pub drop_count: String,
+    pub warmup_drops: String,
+    pub random_drops: bool,
+    pub drop_seed: String,
+    // check the result against the reference engine after computing it
+    pub verify: bool,
+    // the divisible sandpile has settled once no cell holds more than 1 + tolerance. 0 iterations means no limit
+    pub divisible_tolerance: String,
+    pub divisible_max_iterations: String,
+    // the stochastic models draw their random numbers from the seed. the manna model sends out its own threshold's worth of
+    // grains, using the kernel amounts as weights
+    pub model: ModelKind,
+    pub model_seed: String,
+    pub manna_threshold: String,
+    // chip-firing graphs are read from a file or generated. the size is the side of the torus, the number of vertices of
+    // the complete graph, the depth of the tree or the level of the Sierpinski gasket
+    pub graph: GraphKind,
+    pub graph_path: String,
+    pub graph_directed: bool,
+    pub graph_size: String,
+    pub tree_branching: String,
+    // the label of the sink vertex. the graph has no sink if it's left empty
+    pub graph_sink: String,
+    // parallel chip-firing runs on the lattice's torus or reflecting domain, or on the graph. it gives up if no
+    // configuration has repeated after this many steps
+    pub period_on_graph: bool,
+    pub period_max_steps: String,
+}
+
+
+impl Default for ComputeParams {
+    fn default() -> Self {
+        Self {
+            sources: vec![SourceParams::default()],
+            lattice: Lattice::Square,
+            kernel: KernelKind::VonNeumann,
+            kernel_radius: "2".into(),
+            kernel_stencil: "1,0,2;-1,0,2;0,1,1;0,-1,1".into(),
+            kernel_threshold: "".into(),
+            symmetric: false,
+            engine: Engine::Dense,
+            boundary: BoundaryKind::Plane,
+            domain_size: "200".into(),
+            background_height: "0".into(),
+            timeline_path: "".into(),
+            timeline_interval: "100".into(),
+            timeline_logarithmic: false,
+            checkpoint_interval: "600".into(),
+            drop_count: "10000".into(),
+            warmup_drops: "10000".into(),
+            random_drops: true,
+            drop_seed: "1".into(),
+            verify: false,
+            divisible_tolerance: "1e-6".into(),
+            divisible_max_iterations: "1000000".into(),
+            model: ModelKind::Abelian,
+            model_seed: "1".into(),
+            manna_threshold: "2".into(),
+            graph: GraphKind::Torus,
+            graph_path: "".into(),
+            graph_directed: false,
+            graph_size: "20".into(),
+            tree_branching: "2".into(),
+            graph_sink: "".into(),
+            period_on_graph: false,
+            period_max_steps: "1000000".into(),
+        }
+    }
+}
+
+impl ComputeParams {
+    pub fn initial_configuration(&self) -> Result<Vec<InitialCell>, ComputeError> {
+        self.sources.iter().map(|source| {
+            Ok(InitialCell {
+                x: parse_or_default(&source.x, "source X")?,
+                y: parse_or_default(&source.y, "source Y")?,
+                value: parse_or_default(&source.value, "source count")?,
+            })
+        }).collect()
+    }
+
+    pub fn initial_voxels(&self) -> Result<Vec<InitialVoxel>, ComputeError> {
+        self.sources.iter().map(|source| {
+            Ok(InitialVoxel {
+                x: parse_or_default(&source.x, "source X")?,
+                y: parse_or_default(&source.y, "source Y")?,
+                z: parse_or_default(&source.z, "source Z")?,
+                value: parse_or_default(&source.value, "source count")?,
+            })
+        }).collect()
+    }
+
+    pub fn topple_rule(&self) -> Result<ToppleRule, ComputeError> {
+        match self.lattice {
+            Lattice::Hexagonal => return Ok(ToppleRule::hexagonal()),
+            Lattice::Triangular => return Ok(ToppleRule::triangular()),
+            Lattice::Square => {},
+        }
+
+        match self.kernel {
+            KernelKind::VonNeumann => Ok(ToppleRule::von_neumann()),
+            KernelKind::Moore => Ok(ToppleRule::moore()),
+            KernelKind::Extended => {
+                let radius: usize = parse_or_default(&self.kernel_radius, "kernel radius")?;
+                if radius == 0 {
+                    return Err(ComputeError::InvalidParameter("kernel radius must be at least 1".into()));
+                }
+                Ok(ToppleRule::extended_von_neumann(radius))
+            },
+            KernelKind::Weighted => {
+                let kernel = self.kernel_stencil.split(';').filter(|entry| entry.trim().len() > 0).map(|entry| {
+                    let parts: Vec<&str> = entry.split(',').map(|part| part.trim()).collect();
+                    if parts.len() != 3 {
+                        return Err(ComputeError::InvalidParameter(format!("stencil entry '{}' should be 'dx,dy,amount'", entry)));
+                    }
+                    Ok(ToppleNeighbor {
+                        dx: parse_or_default(parts[0], "stencil dx")?,
+                        dy: parse_or_default(parts[1], "stencil dy")?,
+                        amount: parse_or_default(parts[2], "stencil amount")?,
+                    })
+                }).collect::<Result<Vec<_>, _>>()?;
+
+                let grains_sent: u64 = kernel.iter().map(|neighbor| u64::from(neighbor.amount)).sum();
+                let threshold = if self.kernel_threshold.len() > 0 {
+                    parse_or_default(&self.kernel_threshold, "kernel threshold")?
+                } else {
+                    grains_sent as u32
+                };
+
+                ToppleRule::weighted(threshold, kernel)
+                    .ok_or_else(|| ComputeError::InvalidParameter("the stencil must be non-empty, must not include (0,0), and must not send out more grains than the threshold".into()))
+            },
+        }
+    }
+}
+
+impl ComputeParams {
+    pub fn boundary(&self) -> Result<Boundary, ComputeError> {
+        if self.boundary == BoundaryKind::Plane {
+            return Ok(Boundary::Plane);
+        }
+
+        let size = self.domain_size()?;
+        Ok(match self.boundary {
+            BoundaryKind::Plane => Boundary::Plane,
+            BoundaryKind::Sink => Boundary::Sink { size },
+            BoundaryKind::Torus => Boundary::Torus { size },
+            BoundaryKind::Reflecting => Boundary::Reflecting { size },
+        })
+    }
+
+    pub fn domain_size(&self) -> Result<usize, ComputeError> {
+        let size: usize = parse_or_default(&self.domain_size, "domain size")?;
+        if size == 0 {
+            return Err(ComputeError::InvalidParameter("domain size must be at least 1".into()));
+        }
+        Ok(size)
+    }
+
+    pub fn background_height(&self) -> Result<u32, ComputeError> {
+        parse_or_default(&self.background_height, "background height")
+    }
+
+    pub fn checkpoint_interval(&self) -> Result<Option<Duration>, ComputeError> {
+        let seconds: u64 = parse_or_default(&self.checkpoint_interval, "checkpoint interval")?;
+        Ok(if seconds > 0 { Some(Duration::from_secs(seconds)) } else { None })
+    }
+
+    pub fn timeline_schedule(&self) -> Result<Schedule, ComputeError> {
+        let interval: usize = parse_or_default(&self.timeline_interval, "timeline interval")?;
+        if interval == 0 {
+            return Err(ComputeError::InvalidParameter("timeline interval must be at least 1".into()));
+        }
+        Ok(if self.timeline_logarithmic { Schedule::Logarithmic(interval) } else { Schedule::Every(interval) })
+    }
+
+    pub fn divisible_tolerance(&self) -> Result<f64, ComputeError> {
+        let tolerance: f64 = parse_or_default(&self.divisible_tolerance, "tolerance")?;
+        if !(tolerance > 0.0) {
+            return Err(ComputeError::InvalidParameter("tolerance must be greater than 0".into()));
+        }
+        Ok(tolerance)
+    }
+
+    pub fn divisible_max_iterations(&self) -> Result<usize, ComputeError> {
+        parse_or_default(&self.divisible_max_iterations, "maximum iterations")
+    }
+
+    pub fn model(&self) -> Result<Model, ComputeError> {
+        Ok(match self.model {
+            ModelKind::Abelian => Model::Abelian,
+            ModelKind::Manna => Model::Manna { seed: parse_or_default(&self.model_seed, "seed")? },
+            ModelKind::Oslo => Model::Oslo { seed: parse_or_default(&self.model_seed, "seed")? },
+        })
+    }
+
+    // the topple rule with the manna model's threshold in place of the kernel's own
+    pub fn model_topple_rule(&self) -> Result<ToppleRule, ComputeError> {
+        let mut topple_rule = self.topple_rule()?;
+        if self.model == ModelKind::Manna {
+            topple_rule.threshold = parse_or_default(&self.manna_threshold, "Manna threshold")?;
+            if topple_rule.threshold == 0 {
+                return Err(ComputeError::InvalidParameter("Manna threshold must be at least 1".into()));
+            }
+            if topple_rule.kernels().any(|kernel| kernel.iter().all(|neighbor| neighbor.amount == 0)) {
+                return Err(ComputeError::InvalidParameter("the Manna model needs a kernel with at least one non-zero amount".into()));
+            }
+        }
+        Ok(topple_rule)
+    }
+
+    pub fn graph(&self) -> Result<Graph, ComputeError> {
+        let size = || parse_or_default::<usize>(&self.graph_size, "graph size");
+        let graph = match self.graph {
+            GraphKind::File => {
+                if self.graph_path.len() == 0 {
+                    return Err(ComputeError::InvalidParameter("a graph file needs to be chosen".into()));
+                }
+                graph::load_graph(&self.graph_path, self.graph_directed)?
+            },
+            GraphKind::Torus => {
+                let size = size()?;
+                if size == 0 {
+                    return Err(ComputeError::InvalidParameter("torus size must be at least 1".into()));
+                }
+                check_vertex_count(size.checked_mul(size))?;
+                graph::torus(size, size)
+            },
+            GraphKind::Complete => {
+                let size = size()?;
+                check_vertex_count(Some(size))?;
+                // the number of edges grows with the square of the number of vertices
+                if size > 5000 {
+                    return Err(ComputeError::InvalidParameter("complete graphs can have at most 5000 vertices".into()));
+                }
+                graph::complete(size)
+            },
+            GraphKind::Tree => {
+                let branching: usize = parse_or_default(&self.tree_branching, "tree branching")?;
+                if branching == 0 {
+                    return Err(ComputeError::InvalidParameter("tree branching must be at least 1".into()));
+                }
+                let depth = size()?;
+                // stops as soon as the count gets too large, so that a deep chain isn't counted one level at a time
+                let vertices = (0..depth).try_fold((1usize, 1usize), |(level, total), _| {
+                    let level = level.checked_mul(branching)?;
+                    total.checked_add(level).filter(|&total| total <= graph::MAX_GRAPH_VERTICES).map(|total| (level, total))
+                });
+                check_vertex_count(vertices.map(|(_, total)| total))?;
+                graph::tree(branching, depth)
+            },
+            GraphKind::Sierpinski => {
+                let level = size()?;
+                if level > 12 {
+                    return Err(ComputeError::InvalidParameter("Sierpinski gasket level must be at most 12".into()));
+                }
+                graph::sierpinski(level)
+            },
+        };
+
+        if graph.vertex_count() == 0 {
+            return Err(ComputeError::InvalidParameter("the graph has no vertices".into()));
+        }
+        let sink = self.graph_sink.trim();
+        if sink.len() > 0 { graph.with_sink(sink) } else { Ok(graph) }
+    }
+
+    pub fn initial_vertices(&self, graph: &Graph) -> Result<Vec<InitialVertex>, ComputeError> {
+        self.sources.iter().map(|source| {
+            let label = source.vertex.trim();
+            Ok(InitialVertex {
+                vertex: graph.vertex(label).ok_or_else(|| ComputeError::InvalidParameter(format!("there is no vertex '{}' to place a source on", label)))?,
+                value: parse_or_default(&source.value, "source count")?,
+            })
+        }).collect()
+    }
+
+    pub fn period_max_steps(&self) -> Result<usize, ComputeError> {
+        let max_steps: usize = parse_or_default(&self.period_max_steps, "step limit")?;
+        if max_steps == 0 {
+            return Err(ComputeError::InvalidParameter("step limit must be at least 1".into()));
+        }
+        Ok(max_steps)
+    }
+
+    pub fn driven_settings(&self) -> Result<DrivenSettings, ComputeError> {
+        let site = if self.random_drops {
+            DropSite::Random
+        } else {
+            let source = self.initial_configuration()?.into_iter().next()
+                .ok_or_else(|| ComputeError::InvalidParameter("a fixed drop site needs a source".into()))?;
+            DropSite::Fixed { x: source.x, y: source.y }
+        };
+
+        Ok(DrivenSettings {
+            drops: parse_or_default(&self.drop_count, "drop count")?,
+            warmup: parse_or_default(&self.warmup_drops, "warmup drops")?,
+            site,
+            seed: parse_or_default(&self.drop_seed, "drop seed")?,
+        })
+    }
+}
+
+// `vertex_count` is None if counting the vertices overflowed
+fn check_vertex_count(vertex_count: Option<usize>) -> Result<(), ComputeError> {
+    match vertex_count {
+        Some(count) if count <= graph::MAX_GRAPH_VERTICES => Ok(()),
+        _ => Err(ComputeError::InvalidParameter(format!("generated graphs can have at most {} vertices", graph::MAX_GRAPH_VERTICES))),
+    }
+}
+
+fn parse_or_default<T: std::str::FromStr + Default>(text: &str, name: &str) -> Result<T, ComputeError> {
+    if text.len() > 0 {
+        text.parse::<T>().map_err(|_| ComputeError::InvalidParameter(format!("couldn't parse {} '{}'", name, text)))
+    } else {
+        Ok(T::default())
+    }
+}
+
+pub async fn compute_fractal(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<FractalResult>, ComputeError> {
+    use crate::compute;
+
+    let initial_configuration = params.initial_configuration()?;
+    let model = params.model()?;
+    let topple_rule = params.model_topple_rule()?;
+    let boundary = params.boundary()?;
+    let background_height = params.background_height()?;
+    let checkpoint_interval = params.checkpoint_interval()?;
+    let records_timeline = !params.timeline_path.is_empty();
+
+    // a cached result has no timeline, so it can't be used when one is being recorded
+    let cached = if !records_timeline { cache::load_from_cache(&initial_configuration, &topple_rule, model, boundary, background_height) } else { None };
+    let fractal_data = if let Some(data) = cached {
+        data
+    } else {
+        let begin = Instant::now();
+        let source_grains = initial_configuration.iter().try_fold(0u64, |total, entry| total.checked_add(entry.value))
+            .ok_or_else(|| ComputeError::InvalidParameter("the total number of grains doesn't fit in 64 bits".into()))?;
+
+        if let Some(entry) = initial_configuration.iter().find(|entry| !boundary.contains(entry.x, entry.y)) {
+            return Err(ComputeError::InvalidParameter(format!("source ({}, {}) is outside the domain", entry.x, entry.y)));
+        }
+
+        let (max_area, total_grains) = stabilization_limits(&topple_rule, model, boundary, background_height, source_grains)?;
+        // random choices break the symmetry
+        let symmetry = if params.symmetric && boundary == Boundary::Plane && !model.is_stochastic() && supports_quadrant_symmetry(&initial_configuration, &topple_rule) { Symmetry::Quadrant } else { Symmetry::None };
+        let use_u32 = total_grains <= u64::from(std::u32::MAX);
+
+        // an earlier run of the same computation that got interrupted is picked up from its last checkpoint
+        let checkpoint_key = CheckpointKey {
+            initial_configuration: initial_configuration.clone(),
+            topple_rule: topple_rule.clone(),
+            model,
+            boundary,
+            background_height,
+            symmetry,
+            grain_size: if use_u32 { std::mem::size_of::<u32>() } else { std::mem::size_of::<u64>() },
+        };
+
+        let recorder = if records_timeline {
+            // creating the timeline empties its file, so a resumed run would lose the frames recorded before its checkpoint
+            if checkpoint_interval.is_some() && cache::has_checkpoint(&checkpoint_key) {
+                return Err(ComputeError::InvalidParameter("a timeline can't be recorded while resuming from a checkpoint. clear the checkpoint interval to start over".into()));
+            }
+            let recorder = TimelineRecorder::create(&params.timeline_path, params.timeline_schedule()?, &topple_rule, model, boundary, background_height)
+                .map_err(|error| ComputeError::Io(format!("couldn't create timeline file '{}': {}", params.timeline_path, error)))?;
+            Some(Mutex::new(recorder))
+        } else {
+            None
+        };
+
+        let settings = Settings {
+            model,
+            symmetry,
+            background_height,
+            max_area,
+            monitor: Some(&monitor),
+            recorder: recorder.as_ref(),
+            checkpoints: checkpoint_interval.map(|interval| (&checkpoint_key, interval)),
+            ..Settings::new(&topple_rule, boundary, params.engine)
+        };
+        let result = if use_u32 {
+            compute::compute_fractal_data::<u32>(&initial_configuration, &settings)?
+        } else {
+            compute::compute_fractal_data::<u64>(&initial_configuration, &settings)?
+        };
+        if let Some(recorder) = recorder {
+            recorder.into_inner().unwrap().finish().map_err(|error| ComputeError::Io(format!("couldn't write timeline file: {}", error)))?;
+        }
+        let end = Instant::now();
+        let _duration = end.duration_since(begin);
+        match cache::save_to_cache(&result) {
+            Err(()) => println!("Failed to save fractal data to cache file"),
+            _ => {},
+        };
+        if checkpoint_interval.is_some() {
+            cache::remove_checkpoint();
+        }
+
+        result
+    };
+
+    Ok(Arc::new(fractal_data))
+}
+
+// stabilizes the sources as a divisible sandpile. its limit shape is a disc, which makes it a baseline for the shape of the
+// abelian pile. the sparse engine, timelines and checkpoints only apply to the abelian pile
+pub async fn compute_divisible(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<FractalResult>, ComputeError> {
+    let initial_configuration = params.initial_configuration()?;
+    let topple_rule = params.topple_rule()?;
+    let boundary = params.boundary()?;
+    let background_height = params.background_height()?;
+    let tolerance = params.divisible_tolerance()?;
+    let max_iterations = params.divisible_max_iterations()?;
+
+    if let Some(entry) = initial_configuration.iter().find(|entry| !boundary.contains(entry.x, entry.y)) {
+        return Err(ComputeError::InvalidParameter(format!("source ({}, {}) is outside the domain", entry.x, entry.y)));
+    }
+    if boundary == Boundary::Plane && background_height >= 1 {
+        return Err(ComputeError::NeverStabilizes(format!("a background height of {} leaves no room for the excess mass on the plane", background_height)));
+    }
+    // without a sink or a lossy kernel, the mass has to fit on the domain at no more than 1 per cell
+    if let Boundary::Torus { size } | Boundary::Reflecting { size } = boundary {
+        let total_mass = initial_configuration.iter().map(|entry| entry.value as f64).sum::<f64>() + f64::from(background_height) * (size * size) as f64;
+        if topple_rule.grains_sent() == u64::from(topple_rule.threshold) && total_mass > (size * size) as f64 {
+            return Err(ComputeError::NeverStabilizes(format!("a mass of {} can't settle on a {}x{} domain", total_mass, size, size)));
+        }
+    }
+
+    let symmetry = if params.symmetric && boundary == Boundary::Plane && supports_quadrant_symmetry(&initial_configuration, &topple_rule) { Symmetry::Quadrant } else { Symmetry::None };
+    let settings = Settings { model: Model::Divisible, symmetry, background_height, monitor: Some(&monitor), ..Settings::new(&topple_rule, boundary, Engine::Dense) };
+
+    let (data, side_length, origin_x, origin_y) = initial_array(&initial_configuration, &settings, f64::from(background_height), |value| value as f64);
+    let result = stabilize_divisible(data, side_length, origin_x, origin_y, &settings, tolerance, max_iterations)?;
+    Ok(Arc::new(FractalResult { initial_configuration, ..result }))
+}
+
+// computes a pile like compute_fractal, then stabilizes it again with the reference engine and compares the two
+pub async fn compute_verified(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<(Arc<FractalResult>, Arc<Verification>), ComputeError> {
+    let result = compute_fractal(params, Arc::clone(&monitor)).await?;
+    let verification = reference::verify(&result, Some(&monitor))?;
+    Ok((result, Arc::new(verification)))
+}
+
+// computes the identity element of the sandpile group of an n x n grid with a sink boundary, using the current topple rule,
+// engine and domain size
+pub async fn compute_identity(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<FractalResult>, ComputeError> {
+    let size = params.domain_size()?;
+    let topple_rule = params.topple_rule()?;
+
+    identity_with_rule(size, &topple_rule, params.engine, Some(&monitor)).map(Arc::new)
+}
+
+// the identity element of the sandpile group of an n x n grid with a sink boundary, under the standard rule
+pub fn identity(n: usize) -> Result<FractalResult, ComputeError> {
+    identity_with_rule(n, &ToppleRule::von_neumann(), Engine::Dense, None)
+}
+
+// the identity is stab(2·max - stab(2·max)), where max is the largest stable configuration,
+// with threshold - 1 grains on every cell
+pub fn identity_with_rule(n: usize, topple_rule: &ToppleRule, engine: Engine, monitor: Option<&ComputeMonitor>) -> Result<FractalResult, ComputeError> {
+    if n == 0 {
+        return Err(ComputeError::InvalidParameter("grid size must be at least 1".into()));
+    }
+    check_stable_values_fit(topple_rule)?;
+
+    let settings = Settings { monitor, ..Settings::new(topple_rule, Boundary::Sink { size: n }, engine) };
+    let double_max = u64::from(topple_rule.threshold - 1) * 2;
+    if double_max * (n * n) as u64 <= u64::from(std::u32::MAX) {
+        stabilize_domain(&zero_configuration::<u32>(&settings)?, &settings)
+    } else {
+        stabilize_domain(&zero_configuration::<u64>(&settings)?, &settings)
+    }
+}
+
+// the inverse of a configuration in the sandpile group of a domain with a sink boundary: the recurrent configuration
+// that gives the identity when added to it
+pub fn inverse(result: &FractalResult, engine: Engine, monitor: Option<&ComputeMonitor>) -> Result<FractalResult, ComputeError> {
+    if result.model != Model::Abelian {
+        return Err(ComputeError::InvalidParameter("only piles of the abelian model have an inverse".into()));
+    }
+    let size = match result.boundary {
+        Boundary::Sink { size } => size,
+        _ => return Err(ComputeError::InvalidParameter("the sandpile group is only finite on a domain with a sink boundary".into())),
+    };
+    check_stable_values_fit(&result.topple_rule)?;
+
+    let settings = Settings { monitor, ..Settings::new(&result.topple_rule, result.boundary, engine) };
+    let quadruple_max = u64::from(result.topple_rule.threshold - 1) * 4;
+    if quadruple_max * (size * size) as u64 <= u64::from(std::u32::MAX) {
+        inverse_data::<u32>(result, &settings)
+    } else {
+        inverse_data::<u64>(result, &settings)
+    }
+}
+
+fn inverse_data<T: Grains>(result: &FractalResult, settings: &Settings) -> Result<FractalResult, ComputeError> {
+    let zero = zero_configuration::<T>(settings)?;
+
+    // 2·zero - c is equivalent to -c, and at least as large as max, so it stabilizes to a recurrent configuration
+    let difference: Vec<T> = zero.iter().zip(&result.sand_data).map(|(&zero, &value)| zero + zero - T::from(u32::from(value))).collect();
+    stabilize_domain(&difference, settings)
+}
+
+// 2·max - stab(2·max) on the domain of a sink boundary. it's equivalent to the empty configuration, since stabilizing
+// doesn't change the equivalence class, and every cell holds at least max grains
+fn zero_configuration<T: Grains>(settings: &Settings) -> Result<Vec<T>, ComputeError> {
+    let double_max = (settings.topple_rule.threshold - 1) * 2;
+    let stabilized = compute_fractal_data::<T>(&[], &Settings { background_height: double_max, ..*settings })?;
+
+    Ok(stabilized.sand_data.iter().map(|&value| T::from(double_max - u32::from(value))).collect())
+}
+
+// the results of the group operations are built from stable configurations, which are read back from sand_data
+fn check_stable_values_fit(topple_rule: &ToppleRule) -> Result<(), ComputeError> {
+    if topple_rule.threshold > 256 {
+        return Err(ComputeError::InvalidParameter("stable configurations can only be combined for thresholds up to 256".into()));
+    }
+    Ok(())
+}
+
+// adds two stable configurations cell by cell and stabilizes the sum, which is the group operation A ⊕ B
+pub fn add_results(a: &FractalResult, b: &FractalResult, engine: Engine, monitor: Option<&ComputeMonitor>) -> Result<FractalResult, ComputeError> {
+    if a.topple_rule != b.topple_rule || a.boundary != b.boundary {
+        return Err(ComputeError::InvalidParameter("both configurations need to have the same topple rule and boundary".into()));
+    }
+    add_and_stabilize(&[a, b], &[], engine, monitor)
+}
+
+// adds extra grains to a stable configuration and stabilizes it again
+pub fn add_grains(result: &FractalResult, grains: &[InitialCell], engine: Engine, monitor: Option<&ComputeMonitor>) -> Result<FractalResult, ComputeError> {
+    add_and_stabilize(&[result], grains, engine, monitor)
+}
+
+// drops grains one at a time onto an n x n grid with a sink boundary, using the domain size, and collects statistics about the avalanches they cause
+pub async fn compute_driven(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<DrivenResult>, ComputeError> {
+    let topple_rule = params.topple_rule()?;
+    let size = params.domain_size()?;
+    let background_height = params.background_height()?;
+    let settings = params.driven_settings()?;
+
+    run_driven(&topple_rule, Boundary::Sink { size }, background_height, &settings, Some(&monitor)).map(Arc::new)
+}
+
+// stabilizes the sources, including their z coordinates, on the cubic lattice. the lattice, kernel and engine settings
+// don't apply to it
+pub async fn compute_cubic(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<VolumeResult>, ComputeError> {
+    let initial_configuration = params.initial_voxels()?;
+    let boundary = params.boundary()?;
+    let background_height = params.background_height()?;
+
+    run_cubic(&initial_configuration, boundary, background_height, Some(&monitor)).map(Arc::new)
+}
+
+// fires the vertices of a graph until none can fire any more. the lattice, kernel, engine and boundary settings don't
+// apply to it
+pub async fn compute_graph(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<GraphResult>, ComputeError> {
+    let graph = params.graph()?;
+    let initial_configuration = params.initial_vertices(&graph)?;
+    let background_height = params.background_height()?;
+
+    stabilize_graph(graph, &initial_configuration, background_height, Some(&monitor)).map(Arc::new)
+}
+
+// runs parallel chip-firing until it becomes periodic, and keeps the cycle it ends up in. it runs on the graph, or on
+// the lattice's torus or reflecting domain using the sources, kernel and background height
+pub async fn compute_period(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<Arc<Orbit>, ComputeError> {
+    let background_height = params.background_height()?;
+    let max_steps = params.period_max_steps()?;
+
+    if params.period_on_graph {
+        let graph = params.graph()?;
+        let initial_configuration = params.initial_vertices(&graph)?;
+        periodic::graph_orbit(graph, &initial_configuration, background_height, max_steps, Some(&monitor)).map(Arc::new)
+    } else {
+        let initial_configuration = params.initial_configuration()?;
+        let topple_rule = params.topple_rule()?;
+        let boundary = params.boundary()?;
+        periodic::lattice_orbit(&initial_configuration, &topple_rule, boundary, background_height, max_steps, Some(&monitor)).map(Arc::new)
+    }
+}
+
+// grows a rotor-router cluster from the sources, along with the pile from the same sources to compare it to. the cluster
+// uses the kernel and boundary of the pile, but starts out empty whatever the background height
+pub async fn compute_rotor(params: ComputeParams, monitor: Arc<ComputeMonitor>) -> Result<(Arc<FractalResult>, Arc<FractalResult>), ComputeError> {
+    let initial_configuration = params.initial_configuration()?;
+    let topple_rule = params.topple_rule()?;
+    let boundary = params.boundary()?;
+
+    let cluster = rotor::rotor_aggregation(&initial_configuration, &topple_rule, boundary, Some(&monitor))?;
+    let pile = compute_fractal(params, monitor).await?;
+    Ok((pile, Arc::new(cluster)))
+}
+
+// the sandpile group of a result's sink-boundary domain, and the order of the result in it
+pub async fn compute_group(result: Arc<FractalResult>, monitor: Arc<ComputeMonitor>) -> Result<Arc<SandpileGroup>, ComputeError> {
+    group::grid_group(&result, Some(&monitor)).map(Arc::new)
+}
+
+pub async fn compute_graph_group(result: Arc<GraphResult>, monitor: Arc<ComputeMonitor>) -> Result<Arc<SandpileGroup>, ComputeError> {
+    group::graph_group(&result, Some(&monitor)).map(Arc::new)
+}
+
+// runs the burning algorithm on a result's sink-boundary domain, to tell whether it's recurrent
+pub async fn compute_burning(result: Arc<FractalResult>, monitor: Arc<ComputeMonitor>) -> Result<Arc<Burning>, ComputeError> {
+    burning::burn_grid(&result, Some(&monitor)).map(Arc::new)
+}
+
+pub async fn compute_graph_burning(result: Arc<GraphResult>, monitor: Arc<ComputeMonitor>) -> Result<Arc<Burning>, ComputeError> {
+    burning::burn_graph(&result, Some(&monitor)).map(Arc::new)
+}
+
+pub async fn compute_sum(a: Arc<FractalResult>, b: Arc<FractalResult>, engine: Engine, monitor: Arc<ComputeMonitor>) -> Result<Arc<FractalResult>, ComputeError> {
+    add_results(&a, &b, engine, Some(&monitor)).map(Arc::new)
+}
+
+pub async fn compute_inverse(result: Arc<FractalResult>, engine: Engine, monitor: Arc<ComputeMonitor>) -> Result<Arc<FractalResult>, ComputeError> {
+    inverse(&result, engine, Some(&monitor)).map(Arc::new)
+}
+
+fn add_and_stabilize(results: &[&FractalResult], grains: &[InitialCell], engine: Engine, monitor: Option<&ComputeMonitor>) -> Result<FractalResult, ComputeError> {
+    if results.iter().any(|result| result.model != Model::Abelian) {
+        return Err(ComputeError::InvalidParameter("only piles of the abelian model can be added".into()));
+    }
+    let topple_rule = &results[0].topple_rule;
+    let boundary = results[0].boundary;
+    check_stable_values_fit(topple_rule)?;
+
+    if let Some(entry) = grains.iter().find(|entry| !boundary.contains(entry.x, entry.y)) {
+        return Err(ComputeError::InvalidParameter(format!("source ({}, {}) is outside the domain", entry.x, entry.y)));
+    }
+
+    // away from the arrays, the sum holds the sum of the backgrounds
+    let background_height = results.iter().map(|result| result.background_height).sum();
+    let source_grains = results.iter().flat_map(|result| result.sand_data.iter().map(|&value| u64::from(value)))
+        .chain(grains.iter().map(|entry| entry.value))
+        .try_fold(0u64, |total, value| total.checked_add(value))
+        .ok_or_else(|| ComputeError::InvalidParameter("the total number of grains doesn't fit in 64 bits".into()))?;
+    let (max_area, total_grains) = stabilization_limits(topple_rule, Model::Abelian, boundary, background_height, source_grains)?;
+
+    let settings = Settings { background_height, max_area, monitor, ..Settings::new(topple_rule, boundary, engine) };
+    if total_grains <= u64::from(std::u32::MAX) {
+        add_and_stabilize_data::<u32>(results, grains, &settings)
+    } else {
+        add_and_stabilize_data::<u64>(results, grains, &settings)
+    }
+}
+
+// checks that a pile with `source_grains` grains on top of the background can stabilize at all. returns the largest area
+// the pile may cover on the plane, and the number of grains a single cell may have to hold
+fn stabilization_limits(topple_rule: &ToppleRule, model: Model, boundary: Boundary, background_height: u32, source_grains: u64) -> Result<(Option<u64>, u64), ComputeError> {
+    let max_area = match boundary.domain_size() {
+        None => plane_area_limit(topple_rule, model, background_height, source_grains)?,
+        Some(size) => Some((size * size) as u64),
+    };
+    // the grains on the background count towards the total too, since they can pile up in a single cell
+    let total_grains = max_area.unwrap_or(0).saturating_mul(u64::from(background_height)).saturating_add(source_grains);
+
+    // without a sink, grains can only leave through a kernel that sends out fewer grains than its threshold.
+    // if every cell would still have to hold more than the largest stable height, the pile can never stabilize
+    if let Boundary::Torus { size } | Boundary::Reflecting { size } = boundary {
+        let capacity = (size as u64).saturating_mul(size as u64).saturating_mul(u64::from(max_stable_height(topple_rule, model)));
+        if is_conservative(topple_rule, model) && total_grains > capacity {
+            return Err(ComputeError::NeverStabilizes(format!("{} grains can't fit on a {}x{} domain without toppling", total_grains, size, size)));
+        }
+    }
+
+    Ok((if boundary == Boundary::Plane { max_area } else { None }, total_grains))
+}
+
+// whether every topple keeps all of its grains. the manna model sends out exactly its threshold's worth, whatever the kernel
+fn is_conservative(topple_rule: &ToppleRule, model: Model) -> bool {
+    match model {
+        Model::Manna { .. } => true,
+        _ => topple_rule.grains_sent() == u64::from(topple_rule.threshold),
+    }
+}
+
+// the most grains a cell can hold without toppling. a cell in the oslo model can be stable with threshold grains, if it
+// drew threshold + 1
+fn max_stable_height(topple_rule: &ToppleRule, model: Model) -> u32 {
+    match model {
+        Model::Oslo { .. } => topple_rule.threshold,
+        _ => topple_rule.threshold - 1,
+    }
+}
+
+// the size of a domain that no grain can leave, and where toppling every cell once sends each cell at least its threshold.
+// once every cell of such a domain has toppled, the cell whose last topple came first has been sent that much since, so
+// it can't be stable and the pile keeps toppling forever. like the closed parts of a graph, see graph::stabilize_graph
+pub(crate) fn closed_domain_size(topple_rule: &ToppleRule, model: Model, boundary: Boundary) -> Option<usize> {
+    let size = match (model, boundary) {
+        (Model::Abelian, Boundary::Torus { size }) | (Model::Abelian, Boundary::Reflecting { size }) => size,
+        _ => return None,
+    };
+
+    let start = boundary.domain_start();
+    let mut received: Vec<u64> = vec![0; size * size];
+    for y in 0..size {
+        for x in 0..size {
+            for neighbor in topple_rule.kernel_for(start + x as i64, start + y as i64) {
+                let (target_x, target_y) = (x as isize + neighbor.dx, y as isize + neighbor.dy);
+                let (target_x, target_y) = match boundary {
+                    Boundary::Torus { .. } => (target_x.rem_euclid(size as isize), target_y.rem_euclid(size as isize)),
+                    _ => (reflect(target_x, size), reflect(target_y, size)),
+                };
+                received[target_y as usize * size + target_x as usize] += u64::from(neighbor.amount);
+            }
+        }
+    }
+
+    if received.iter().all(|&amount| amount >= u64::from(topple_rule.threshold)) { Some(size) } else { None }
+}
+
+// a stable pile on a conservative kernel spreads its extra grains over at least total / (max stable - background) cells.
+// piles on a background usually stay within a small multiple of that, so one that covers far more is treated as exploding
+const AREA_LIMIT_FACTOR: u64 = 64;
+
+// works out how large a pile on the plane may grow before it's considered to be growing without bound, or None if it
+// can't explode at all. a background at the largest stable height makes any pile on a conservative kernel explode straight away
+fn plane_area_limit(topple_rule: &ToppleRule, model: Model, background_height: u32, source_grains: u64) -> Result<Option<u64>, ComputeError> {
+    let conservative = is_conservative(topple_rule, model);
+    let max_stable = max_stable_height(topple_rule, model);
+
+    if background_height >= topple_rule.threshold {
+        return Err(ComputeError::NeverStabilizes(format!("a background height of {} is unstable everywhere on the plane", background_height)));
+    }
+    if background_height == 0 || !conservative || source_grains == 0 {
+        // kernels that lose grains on every topple always stabilize
+        return Ok(None);
+    }
+    if background_height >= max_stable {
+        return Err(ComputeError::NeverStabilizes(format!("a background height of {} makes the pile grow without bound", background_height)));
+    }
+
+    let headroom = u64::from(max_stable - background_height);
+    Ok(Some(AREA_LIMIT_FACTOR.saturating_mul(source_grains / headroom + 1).saturating_add(1 << 16)))
+}
+
+// everything besides the initial configuration that determines how a pile is computed
+struct Settings<'a> {
+	topple_rule: &'a ToppleRule,
+	model: Model,
+	symmetry: Symmetry,
+	engine: Engine,
+	boundary: Boundary,
+	background_height: u32,
+	// on the plane, the largest area the array may cover before the pile is considered to be exploding
+	max_area: Option<u64>,
+	monitor: Option<&'a ComputeMonitor>,
+	recorder: Option<&'a Mutex<TimelineRecorder>>,
+	// what to save checkpoints as, and how often
+	checkpoints: Option<(&'a CheckpointKey, Duration)>,
+}
+
+impl<'a> Settings<'a> {
+	// the abelian pile without a background, symmetry, limits, progress reports, timeline or checkpoints
+	fn new(topple_rule: &'a ToppleRule, boundary: Boundary, engine: Engine) -> Self {
+		Self {
+			topple_rule,
+			model: Model::Abelian,
+			symmetry: Symmetry::None,
+			engine,
+			boundary,
+			background_height: 0,
+			max_area: None,
+			monitor: None,
+			recorder: None,
+			checkpoints: None,
+		}
+	}
+}
+
+// a single source with a mirror-symmetric kernel produces a pile that's symmetric around the source,
+// so only the quadrant to the bottom right of the source needs to be simulated
+fn supports_quadrant_symmetry(initial_configuration: &[InitialCell], topple_rule: &ToppleRule) -> bool {
+	if initial_configuration.len() != 1 || topple_rule.odd_kernel.is_some() {
+		return false;
+	}
+
+	let mut amounts: HashMap<(isize, isize), u64> = HashMap::new();
+	for neighbor in &topple_rule.kernel {
+		*amounts.entry((neighbor.dx, neighbor.dy)).or_insert(0) += u64::from(neighbor.amount);
+	}
+
+	amounts.iter().all(|(&(dx, dy), amount)| {
+		amounts.get(&(-dx, dy)) == Some(amount) && amounts.get(&(dx, -dy)) == Some(amount)
+	})
+}
+
+fn compute_fractal_data<T: Grains>(initial_configuration: &[InitialCell], settings: &Settings) -> Result<FractalResult, ComputeError> {
+	let (data, side_length, origin_x, origin_y) = initial_array(initial_configuration, settings, T::from(settings.background_height), T::from_u64);
+	let result = stabilize(data, side_length, origin_x, origin_y, settings)?;
+
+	Ok(FractalResult { initial_configuration: initial_configuration.to_vec(), ..result })
+}
+
+// lays the initial configuration out on the background, in an array that's ready to be stabilized. returns the array
+// along with its side length and the world coordinates of its first cell
+fn initial_array<V: Copy + Default + AddAssign, F: Fn(u64) -> V>(initial_configuration: &[InitialCell], settings: &Settings, background: V, value: F) -> (Vec<V>, usize, i64, i64) {
+	let margin = max(settings.topple_rule.radius(), 1);
+
+	match settings.boundary.domain_size() {
+		None => {
+			let mut origin_x = initial_configuration.iter().map(|entry| entry.x).min().unwrap_or(0);
+			let mut origin_y = initial_configuration.iter().map(|entry| entry.y).min().unwrap_or(0);
+
+			let max_x = initial_configuration.iter().map(|entry| entry.x).max().unwrap_or(0);
+			let max_y = initial_configuration.iter().map(|entry| entry.y).max().unwrap_or(0);
+
+			let mut side_length = max(max_x - origin_x, max_y - origin_y) as usize + 1;
+
+			// in quadrant mode, the source sits just past a band of ghost cells that mirror the cells on the other side of the symmetry axes
+			if settings.symmetry == Symmetry::Quadrant {
+				let ghost_size = quadrant_ghost_size(margin);
+				origin_x -= ghost_size as i64;
+				origin_y -= ghost_size as i64;
+				side_length += ghost_size;
+			}
+
+			let mut data: Vec<V> = vec![background; side_length * side_length];
+			for entry in initial_configuration {
+				let array_x = (entry.x - origin_x) as usize;
+				let array_y = (entry.y - origin_y) as usize;
+				data[array_y * side_length + array_x] += value(entry.value);
+			}
+
+			(data, side_length, origin_x, origin_y)
+		},
+		Some(size) => {
+			let start = settings.boundary.domain_start();
+			let mut domain: Vec<V> = vec![background; size * size];
+			for entry in initial_configuration {
+				domain[(entry.y - start) as usize * size + (entry.x - start) as usize] += value(entry.value);
+			}
+
+			pad_domain(&domain, settings)
+		},
+	}
+}
+
+// stabilizes a configuration covering the whole of a fixed-size domain, given row by row
+fn stabilize_domain<T: Grains>(domain: &[T], settings: &Settings) -> Result<FractalResult, ComputeError> {
+	let (data, side_length, origin_x, origin_y) = pad_domain(domain, settings);
+	stabilize(data, side_length, origin_x, origin_y, settings)
+}
+
+// the domain is surrounded by one margin of empty cells, to catch grains that leave it
+fn pad_domain<V: Copy + Default>(domain: &[V], settings: &Settings) -> (Vec<V>, usize, i64, i64) {
+	let size = settings.boundary.domain_size().expect("the boundary should have a fixed-size domain");
+	let margin = max(settings.topple_rule.radius(), 1);
+
+	let origin = settings.boundary.domain_start() - margin as i64;
+	let side_length = next_multiple(size + margin * 2, required_size_multiple(margin));
+
+	let mut data: Vec<V> = vec![V::default(); side_length * side_length];
+	for (domain_row, row) in domain.chunks(size).zip(data.chunks_mut(side_length).skip(margin)) {
+		row[margin..margin + size].copy_from_slice(domain_row);
+	}
+
+	(data, side_length, origin, origin)
+}
+
+// a fixed-size domain run one iteration at a time with the same two-pass update as stabilize, for parallel chip-firing,
+// where every cell holding at least the threshold topples exactly once per iteration. the configurations are kept in
+// the padded arrays the engine works on, so they can be stepped and compared without copying them out
+pub(crate) struct SynchronousDomain<T> {
+	topple_rule: ToppleRule,
+	boundary: Boundary,
+	write_array: Vec<T>,
+	counting_array: Vec<u32>,
+	odometer_array: Vec<u64>,
+	side_length: usize,
+	origin_x: i64,
+	origin_y: i64,
+}
+
+impl<T: Grains> SynchronousDomain<T> {
+	// returns the domain along with its initial configuration
+	pub(crate) fn new(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, background_height: u32) -> (Self, Vec<T>) {
+		let settings = Settings { background_height, ..Settings::new(topple_rule, boundary, Engine::Dense) };
+		let (cells, side_length, origin_x, origin_y) = initial_array(initial_configuration, &settings, T::from(background_height), T::from_u64);
+
+		let domain = Self {
+			topple_rule: topple_rule.clone(),
+			boundary,
+			write_array: cells.clone(),
+			counting_array: vec![0; cells.len()],
+			odometer_array: vec![0; cells.len()],
+			side_length,
+			origin_x,
+			origin_y,
+		};
+		(domain, cells)
+	}
+
+	// moves the configuration on by one iteration, and returns the number of cells that toppled
+	pub(crate) fn step(&mut self, cells: &mut Vec<T>) -> u64 {
+		let side_length = self.side_length;
+		let margin = max(self.topple_rule.radius(), 1);
+		let rows_per_chunk = rows_per_chunk(margin);
+		let kernel = KernelOffsets::<T>::new(&self.topple_rule, side_length);
+		let origin_parity = (self.origin_x + self.origin_y).rem_euclid(2) as usize;
+
+		copy_data(cells, &mut self.write_array);
+		let toppled = process_chunks(cells, &mut self.write_array, &mut self.counting_array, &mut self.odometer_array, side_length, rows_per_chunk, |first_row, input_chunk, output_chunk, counting_chunk, odometer_chunk| {
+			let parity = (origin_parity + first_row) % 2;
+			process_row(input_chunk, output_chunk, counting_chunk, odometer_chunk, side_length, &kernel, margin, parity, margin, side_length - margin, true)
+		});
+
+		if let Some(size) = self.boundary.domain_size() {
+			fold_boundary(&mut self.write_array, side_length, margin, size, self.boundary);
+		}
+		std::mem::swap(cells, &mut self.write_array);
+		toppled
+	}
+
+	// the cells of the domain, row by row
+	pub(crate) fn domain_cells(&self, cells: &[T]) -> Vec<T> {
+		let margin = max(self.topple_rule.radius(), 1);
+		let size = self.boundary.domain_size().expect("the boundary should have a fixed-size domain");
+		crop(cells, self.side_length, margin, size)
+	}
+}
+
+fn add_and_stabilize_data<T: Grains>(results: &[&FractalResult], grains: &[InitialCell], settings: &Settings) -> Result<FractalResult, ComputeError> {
+	let value_at = |x: i64, y: i64| -> T {
+		results.iter().map(|result| {
+			T::from(result.world_to_index(x, y).map_or(result.background_height, |index| u32::from(result.sand_data[index])))
+		}).fold(T::default(), |total, value| total + value)
+	};
+
+	let result = match settings.boundary.domain_size() {
+		None => {
+			// the sum has to cover every array, and every extra grain
+			let min_x = results.iter().map(|result| result.origin_x).chain(grains.iter().map(|entry| entry.x)).min().unwrap_or(0);
+			let min_y = results.iter().map(|result| result.origin_y).chain(grains.iter().map(|entry| entry.y)).min().unwrap_or(0);
+			let max_x = results.iter().map(|result| result.origin_x + result.side_length as i64 - 1).chain(grains.iter().map(|entry| entry.x)).max().unwrap_or(0);
+			let max_y = results.iter().map(|result| result.origin_y + result.side_length as i64 - 1).chain(grains.iter().map(|entry| entry.y)).max().unwrap_or(0);
+			let side_length = max(max_x - min_x, max_y - min_y) as usize + 1;
+
+			let mut data: Vec<T> = (0..side_length * side_length).map(|index| {
+				value_at(min_x + (index % side_length) as i64, min_y + (index / side_length) as i64)
+			}).collect();
+			for entry in grains {
+				data[(entry.y - min_y) as usize * side_length + (entry.x - min_x) as usize] += T::from_u64(entry.value);
+			}
+
+			stabilize(data, side_length, min_x, min_y, settings)?
+		},
+		Some(size) => {
+			let start = settings.boundary.domain_start();
+			let mut domain: Vec<T> = (0..size * size).map(|index| value_at(start + (index % size) as i64, start + (index / size) as i64)).collect();
+			for entry in grains {
+				domain[(entry.y - start) as usize * size + (entry.x - start) as usize] += T::from_u64(entry.value);
+			}
+
+			stabilize_domain(&domain, settings)?
+		},
+	};
+
+	// stabilizing is abelian, so the sum is what the combined initial configurations would have stabilized to
+	let initial_configuration = results.iter().flat_map(|result| result.initial_configuration.iter().cloned()).chain(grains.iter().cloned()).collect();
+	Ok(FractalResult { initial_configuration, ..result })
+}
+
+// topples the cells of `write_array` until none are left that can topple. the array is square, and its first cell is at
+// the given world coordinates. the initial configuration of the result is left empty
+fn stabilize<T: Grains>(mut write_array: Vec<T>, mut side_length: usize, mut origin_x: i64, mut origin_y: i64, settings: &Settings) -> Result<FractalResult, ComputeError> {
+	let Settings { topple_rule, model, symmetry, engine, boundary, background_height, max_area, monitor, recorder, checkpoints } = *settings;
+	let background = T::from(background_height);
+	let threshold = T::from(topple_rule.threshold);
+	let margin = max(topple_rule.radius(), 1);
+	let rows_per_chunk = rows_per_chunk(margin);
+
+	// a cell topples at most once per iteration, so the counts can't exceed total_iterations
+	let mut counting_array: Vec<u32> = vec![0; side_length * side_length];
+	// a cell holding several times the threshold topples that many times in one iteration, so the odometer can grow much faster
+	let mut odometer_array: Vec<u64> = vec![0; side_length * side_length];
+	
+	let mut total_iterations = 0;
+	let mut total_redistributions: u64 = 0;
+	let mut absorbed_grains: u64 = 0;
+	{
+		let mut read_array;
+		let mut next_check;
+		if let Some(checkpoint) = checkpoints.and_then(|(key, _)| cache::load_checkpoint::<T>(key)) {
+			write_array = checkpoint.grains;
+			counting_array = checkpoint.count_data;
+			odometer_array = checkpoint.odometer_data;
+			side_length = checkpoint.side_length;
+			origin_x = checkpoint.origin_x;
+			origin_y = checkpoint.origin_y;
+			total_iterations = checkpoint.total_iterations;
+			total_redistributions = checkpoint.total_redistributions;
+			absorbed_grains = checkpoint.absorbed_grains;
+
+			read_array = write_array.clone();
+			next_check = checkpoint.next_check;
+		} else {
+			read_array = write_array.clone();
+			next_check = match (boundary, symmetry) {
+				(Boundary::Plane, Symmetry::None) => maybe_reallocate(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, &mut origin_x, &mut origin_y, topple_rule, background),
+				(Boundary::Plane, Symmetry::Quadrant) => maybe_grow_quadrant(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, topple_rule, background),
+				_ => 0,
+			};
+		}
+
+		let mut active_tiles = match engine {
+			Engine::Dense => None,
+			Engine::Sparse => Some(ActiveTiles::new(&read_array, side_length, margin, threshold)),
+		};
 
-const ROWS_PER_CHUNK: usize = MARGIN * 4;
-const REQUIRED_SIZE_MULTIPLE: usize = ROWS_PER_CHUNK / 2;
+		let initial_excess = if monitor.is_some() { excess_grains(&read_array, threshold) } else { 0 };
+		let mut completion = 0.0;
+		let mut last_checkpoint = Instant::now();
 
-#[derive(Clone)]
-pub struct ComputeParams {
-    pub initial_size: String,
-}
+		// cells that have toppled never stop having toppled, so the first domain cell that hasn't yet only moves forward
+		let closed_domain = closed_domain_size(topple_rule, model, boundary).is_some();
+		let mut first_untoppled = 0;
 
+		loop
+		{
+			if monitor.map_or(false, |monitor| monitor.is_cancelled()) {
+				return Err(ComputeError::Cancelled);
+			}
 
-impl Default for ComputeParams {
-    fn default() -> Self {
-        Self {
-            initial_size: "2000".into(),
-        }
-    }
-}
+			// the read array holds the current configuration at the start of every iteration
+			if let Some((key, interval)) = checkpoints {
+				if last_checkpoint.elapsed() >= interval {
+					let checkpoint = Checkpoint {
+						grains: &read_array[..],
+						count_data: &counting_array[..],
+						odometer_data: &odometer_array[..],
+						side_length,
+						origin_x,
+						origin_y,
+						next_check,
+						total_iterations,
+						total_redistributions,
+						absorbed_grains,
+					};
+					cache::save_checkpoint(key, &checkpoint).map_err(|()| ComputeError::Io("couldn't save checkpoint file".into()))?;
+					last_checkpoint = Instant::now();
+				}
+			}
 
-pub async fn compute_fractal(params: ComputeParams) -> Arc<FractalResult> {
-    use crate::cache;
-    use crate::compute;
-    use std::time::Instant;
+			if let Some(recorder) = recorder {
+				if recorder.lock().unwrap().wants_frame(total_iterations) {
+					record_frame(recorder, &read_array, total_iterations, side_length, origin_x, origin_y, settings)?;
+				}
+			}
 
-    let initial_size = if params.initial_size.len() > 0 { params.initial_size.parse::<u32>().unwrap() } else { 0 };
+			total_iterations = total_iterations+1;
 
-    let initial_configuration = [
-        InitialCell{x: 0, y: 0, value: initial_size},
-    ];
+			let kernel = KernelOffsets::<T>::new(topple_rule, side_length);
 
-    let fractal_data = if let Some(data) = cache::load_from_cache(&initial_configuration) {
-        data
-    } else {
-        let begin = Instant::now();
-        let result = compute::compute_fractal_data(&initial_configuration);
-        let end = Instant::now();
-        let _duration = end.duration_since(begin);
-        match cache::save_to_cache(&result) {
-            Err(()) => println!("Failed to save fractal data to cache file"),
-            _ => {},
-        };
+			// the parity of the world coordinates of each chunk's first cell, so that lattices with alternating kernels line up
+			let origin_parity = (origin_x + origin_y).rem_euclid(2) as usize;
+			let current_redist = process_chunks(&read_array, &mut write_array, &mut counting_array, &mut odometer_array, side_length, rows_per_chunk, |first_row, input_chunk, output_chunk, counting_chunk, odometer_chunk| {
+				let parity = (origin_parity + first_row) % 2;
+				let mut process = |first_column: usize, last_column: usize| match model {
+					Model::Manna { .. } | Model::Oslo { .. } => process_row_stochastic(input_chunk, output_chunk, counting_chunk, odometer_chunk, side_length, &kernel, margin, parity, first_column, last_column, model, (origin_x, origin_y + first_row as i64)),
+					_ => process_row(input_chunk, output_chunk, counting_chunk, odometer_chunk, side_length, &kernel, margin, parity, first_column, last_column, false),
+				};
+				match &active_tiles {
+					Some(tiles) => tiles.process_band(first_row / (rows_per_chunk / 2), process),
+					None => process(margin, side_length - margin),
+				}
+			});
 
-        result
-    };
+			if let Some(monitor) = monitor {
+				// counting the excess takes a pass over the whole array, so it's only done every so often
+				if total_iterations % EXCESS_INTERVAL == 0 && initial_excess > 0 {
+					let excess = excess_grains(&write_array, threshold);
+					completion = f64::max(completion, 1.0 - excess as f64 / initial_excess as f64);
+				}
+				monitor.report(Progress {
+					iterations: total_iterations,
+					side_length,
+					redistributions: current_redist,
+					estimated_completion: if current_redist == 0 { 1.0 } else { completion },
+				});
+			}
+			
+			if current_redist > 0 {
+				total_redistributions += current_redist;
 
-    Arc::new(fractal_data)
-}
+				if symmetry == Symmetry::Quadrant {
+					mirror_ghost_cells(&mut write_array, side_length, quadrant_ghost_size(margin));
+				}
 
+				if let Some(size) = boundary.domain_size() {
+					absorbed_grains += fold_boundary(&mut write_array, side_length, margin, size, boundary).into();
 
+					// grains can wrap around to the far side of the domain, outside of the tiles the sparse engine knows have changed
+					if let Some(tiles) = &mut active_tiles {
+						tiles.touch_frame(margin * 2, side_length - size);
+					}
 
-fn compute_fractal_data(initial_configuration: &[InitialCell]) -> FractalResult {
+					if closed_domain {
+						while first_untoppled < size * size && counting_array[(margin + first_untoppled / size) * side_length + margin + first_untoppled % size] > 0 {
+							first_untoppled += 1;
+						}
+						if first_untoppled == size * size {
+							return Err(ComputeError::NeverStabilizes(format!("every cell of the {}x{} domain has toppled, so the pile will keep toppling forever", size, size)));
+						}
+					}
+				} else {
+					next_check -= 1;
+				}
 
-	let mut side_length = initial_configuration.iter().map(|entry| max(entry.x, entry.y)).max().unwrap() + 1;
+				if boundary == Boundary::Plane && next_check == 0 {
+					next_check = match symmetry {
+						Symmetry::None => maybe_reallocate(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, &mut origin_x, &mut origin_y, topple_rule, background),
+						Symmetry::Quadrant => maybe_grow_quadrant(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, topple_rule, background),
+					};
 
+					// in quadrant mode the array only holds a quarter of the pile
+					let area = (side_length * side_length) as u64 * if symmetry == Symmetry::Quadrant { 4 } else { 1 };
+					if max_area.map_or(false, |max_area| area > max_area) {
+						return Err(ComputeError::NeverStabilizes(format!("a background height of {} makes the pile grow without bound", background_height)));
+					}
 
-	let mut counting_array: Vec<u32> = vec![0; side_length * side_length];
-	let mut write_array: Vec<u32> = vec![0; side_length * side_length];
+					// the array may have been re-centered, so every tile needs to be checked again
+					if active_tiles.is_some() {
+						active_tiles = Some(ActiveTiles::new(&read_array, side_length, margin, threshold));
+					}
+				} else if let Some(tiles) = &mut active_tiles {
+					tiles.advance(&write_array, &mut read_array, threshold);
+				} else {
+					copy_data(&write_array, &mut read_array);
+				}
+			} else {
+				break;
+			}
+		}
+	}
 
-	for entry in initial_configuration {
-		write_array[entry.y * side_length + entry.x] = entry.value;
+	// the last iteration didn't topple anything, so the final configuration is the same as the one at its start
+	if let Some(recorder) = recorder {
+		record_frame(recorder, &write_array, total_iterations - 1, side_length, origin_x, origin_y, settings)?;
 	}
 
-	
+	let sand_data: Vec<u8> = write_array.into_iter().map(|value| min(value.into(), 255) as u8).collect();
+	let (sand_data, _, _, _) = visible_cells(sand_data, side_length, origin_x, origin_y, symmetry, boundary, margin);
+	let (odometer_array, _, _, _) = visible_cells(odometer_array, side_length, origin_x, origin_y, symmetry, boundary, margin);
+	let (counting_array, side_length, origin_x, origin_y) = visible_cells(counting_array, side_length, origin_x, origin_y, symmetry, boundary, margin);
+
+	if symmetry == Symmetry::Quadrant {
+		// the topples counted so far include the ghost cells, but not the other three quadrants
+		total_redistributions = counting_array.iter().map(|&count| u64::from(count)).sum();
+	}
+
+	Ok(FractalResult {
+		initial_configuration: Vec::new(),
+		topple_rule: topple_rule.clone(),
+		model: model,
+		symmetry: symmetry,
+		boundary: boundary,
+		background_height: background_height,
+		sand_data: sand_data,
+		mass_data: Vec::new(),
+		rotor_data: Vec::new(),
+		count_data: counting_array,
+		total_topples: odometer_array.iter().sum(),
+		odometer_data: odometer_array,
+		odometer_mass: Vec::new(),
+		side_length: side_length,
+		origin_x: origin_x,
+		origin_y: origin_y,
+
+		total_redistributions: total_redistributions,
+		absorbed_grains: absorbed_grains,
+		absorbed_mass: 0.0,
+		total_iterations: total_iterations,
+	})
+}
+
+// the divisible sandpile holds real-valued mass. a cell holding more than 1 + tolerance topples, keeping 1 and sending the
+// excess to its neighbors in proportion to the kernel amounts, so that a kernel that sends out fewer grains than its
+// threshold loses part of the excess. cells only ever get closer to 1, so the pile converges instead of stabilizing exactly
+fn stabilize_divisible(mut write_array: Vec<f64>, mut side_length: usize, mut origin_x: i64, mut origin_y: i64, settings: &Settings, tolerance: f64, max_iterations: usize) -> Result<FractalResult, ComputeError> {
+	let Settings { topple_rule, model, symmetry, boundary, background_height, monitor, .. } = *settings;
+	let background = f64::from(background_height);
+	let cutoff = 1.0 + tolerance;
+	let margin = max(topple_rule.radius(), 1);
+	let rows_per_chunk = rows_per_chunk(margin);
+
+	let mut counting_array: Vec<u32> = vec![0; side_length * side_length];
+	// the mass each cell has toppled away, rather than the number of topples, which are the same as the counts
+	let mut odometer_array: Vec<f64> = vec![0.0; side_length * side_length];
+
 	let mut total_iterations = 0;
-	let mut total_redistributions: i64 = 0;
+	let mut total_redistributions: u64 = 0;
+	let mut absorbed_mass = 0.0;
 	{
 		let mut read_array = write_array.clone();
-		let mut next_check = maybe_reallocate(&mut write_array, &mut read_array, &mut counting_array, &mut side_length);
+		let mut next_check = match (boundary, symmetry) {
+			(Boundary::Plane, Symmetry::None) => maybe_reallocate(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, &mut origin_x, &mut origin_y, topple_rule, background),
+			(Boundary::Plane, Symmetry::Quadrant) => maybe_grow_quadrant(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, topple_rule, background),
+			_ => 0,
+		};
+
+		let initial_excess = if monitor.is_some() { divisible_excess(&read_array, cutoff) } else { 0.0 };
+		let mut completion = 0.0;
 
 		loop
 		{
+			if monitor.map_or(false, |monitor| monitor.is_cancelled()) {
+				return Err(ComputeError::Cancelled);
+			}
+			if max_iterations > 0 && total_iterations == max_iterations {
+				return Err(ComputeError::NeverStabilizes(format!("the mass didn't settle to within {} of 1 after {} iterations", tolerance, max_iterations)));
+			}
+
 			total_iterations = total_iterations+1;
 
-			let mut current_redist = 0;
-			for i in 0..2 {
-				let offset = i * (ROWS_PER_CHUNK / 2);
-				let limit = (side_length - offset) / ROWS_PER_CHUNK;
+			let shares = KernelOffsets::with_amounts(topple_rule, side_length, |amount| f64::from(amount) / f64::from(topple_rule.threshold));
 
-				let read_iter = read_array[(offset * side_length)..].par_chunks(side_length * ROWS_PER_CHUNK).take(limit);
-				let write_iter = write_array[(offset * side_length)..].par_chunks_mut(side_length * ROWS_PER_CHUNK).take(limit);
-				let counting_iter = counting_array[(offset * side_length)..].par_chunks_mut(side_length * ROWS_PER_CHUNK).take(limit);
+			let origin_parity = (origin_x + origin_y).rem_euclid(2) as usize;
+			let current_redist = process_chunks(&read_array, &mut write_array, &mut counting_array, &mut odometer_array, side_length, rows_per_chunk, |first_row, input_chunk, output_chunk, counting_chunk, odometer_chunk| {
+				let parity = (origin_parity + first_row) % 2;
+				process_row_divisible(input_chunk, output_chunk, counting_chunk, odometer_chunk, side_length, &shares, margin, parity, cutoff)
+			});
 
-				current_redist += read_iter.zip(write_iter).zip(counting_iter).map(|((input_chunk, output_chunk), counting_chunk)| process_row(input_chunk, output_chunk, counting_chunk, side_length)).sum::<i32>();
+			if let Some(monitor) = monitor {
+				if total_iterations % EXCESS_INTERVAL == 0 && initial_excess > 0.0 {
+					let excess = divisible_excess(&write_array, cutoff);
+					completion = f64::max(completion, 1.0 - excess / initial_excess);
+				}
+				monitor.report(Progress {
+					iterations: total_iterations,
+					side_length,
+					redistributions: current_redist,
+					estimated_completion: if current_redist == 0 { 1.0 } else { completion },
+				});
 			}
-			
+
 			if current_redist > 0 {
-				total_redistributions += current_redist as i64;
+				total_redistributions += current_redist;
+
+				if symmetry == Symmetry::Quadrant {
+					mirror_ghost_cells(&mut write_array, side_length, quadrant_ghost_size(margin));
+				}
+
+				if let Some(size) = boundary.domain_size() {
+					absorbed_mass += fold_boundary(&mut write_array, side_length, margin, size, boundary);
+				} else {
+					next_check -= 1;
+				}
 
-				next_check -= 1;
-				if next_check == 0 {
-					next_check = maybe_reallocate(&mut write_array, &mut read_array, &mut counting_array, &mut side_length);
+				if boundary == Boundary::Plane && next_check == 0 {
+					next_check = match symmetry {
+						Symmetry::None => maybe_reallocate(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, &mut origin_x, &mut origin_y, topple_rule, background),
+						Symmetry::Quadrant => maybe_grow_quadrant(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, topple_rule, background),
+					};
 				} else {
 					copy_data(&write_array, &mut read_array);
 				}
@@ -105,33 +1464,225 @@ fn compute_fractal_data(initial_configuration: &[InitialCell]) -> FractalResult
 		}
 	}
 
-	FractalResult {
-		initial_configuration: initial_configuration.to_vec(),
-		sand_data: write_array.into_iter().map(|value| value as u8).collect(),
+	let (mass_data, _, _, _) = visible_cells(write_array, side_length, origin_x, origin_y, symmetry, boundary, margin);
+	let (odometer_array, _, _, _) = visible_cells(odometer_array, side_length, origin_x, origin_y, symmetry, boundary, margin);
+	let (counting_array, side_length, origin_x, origin_y) = visible_cells(counting_array, side_length, origin_x, origin_y, symmetry, boundary, margin);
+
+	if symmetry == Symmetry::Quadrant {
+		total_redistributions = counting_array.iter().map(|&count| u64::from(count)).sum();
+	}
+
+	Ok(FractalResult {
+		initial_configuration: Vec::new(),
+		topple_rule: topple_rule.clone(),
+		model: model,
+		symmetry: symmetry,
+		boundary: boundary,
+		background_height: background_height,
+		// the settled cells hold up to 1 + tolerance, so anything within the tolerance of 1 counts as full
+		sand_data: mass_data.iter().map(|&mass| (mass + tolerance).floor().min(255.0) as u8).collect(),
+		mass_data: mass_data,
+		rotor_data: Vec::new(),
 		count_data: counting_array,
+		total_topples: total_redistributions,
+		odometer_data: Vec::new(),
+		odometer_mass: odometer_array,
 		side_length: side_length,
+		origin_x: origin_x,
+		origin_y: origin_y,
 
 		total_redistributions: total_redistributions,
+		absorbed_grains: 0,
+		absorbed_mass,
 		total_iterations: total_iterations,
+	})
+}
+
+// the mass above 1 on the cells that still have to topple
+fn divisible_excess(data: &[f64], cutoff: f64) -> f64 {
+	data.par_iter().filter(|&&mass| mass > cutoff).map(|&mass| mass - 1.0).sum()
+}
+
+fn process_row_divisible(input_data: &[f64], output_data: &mut [f64], counting_data: &mut [u32], odometer_data: &mut [f64], width: usize, shares: &KernelOffsets<f64>, margin: usize, parity: usize, cutoff: f64) -> u64 {
+	assert_eq!(input_data.len(), output_data.len());
+	assert!(input_data.len() % width == 0);
+	assert!(input_data.len() / width >= margin * 2 + 1);
+
+	let first_row = margin;
+	let last_row = input_data.len() / width - margin;
+
+	let mut num_redistributions = 0;
+	for y in first_row..last_row {
+		for x in margin..width - margin {
+			let index = y * width + x;
+
+			let mass = input_data[index];
+			if mass > cutoff {
+				num_redistributions += 1;
+				counting_data[index] += 1;
+
+				let excess = mass - 1.0;
+				odometer_data[index] += excess;
+				let neighbors = if (parity + x + y) % 2 == 0 { &shares.even } else { &shares.odd };
+
+				output_data[index] -= excess;
+				for &(offset, share) in neighbors {
+					output_data[(index as isize + offset) as usize] += excess * share;
+				}
+			}
+		}
+	}
+	num_redistributions
+}
+
+// the stochastic models topple every cell that can topple exactly once per iteration, however many grains it holds. the
+// random choices of a topple are drawn from the seed, the cell's world coordinates and the number of times it toppled
+// before, so they don't depend on how the array is laid out, split into chunks or processed in parallel.
+// `chunk_origin` holds the world coordinates of the first cell of the chunk
+fn process_row_stochastic<T: Grains>(input_data: &[T], output_data: &mut [T], counting_data: &mut [u32], odometer_data: &mut [u64], width: usize, kernel: &KernelOffsets<T>, margin: usize, parity: usize, first_column: usize, last_column: usize, model: Model, chunk_origin: (i64, i64)) -> u64 {
+	assert_eq!(input_data.len(), output_data.len());
+	assert_eq!(input_data.len(), counting_data.len());
+	assert_eq!(input_data.len(), odometer_data.len());
+
+	assert!(input_data.len() % width == 0);
+	assert!(input_data.len() / width >= margin * 2 + 1);
+	assert!(first_column >= margin && last_column <= width - margin);
+
+	let first_row = margin;
+	let last_row = input_data.len() / width - margin;
+
+	let threshold = kernel.threshold;
+	let one = T::from(1);
+
+	let mut num_redistributions = 0;
+	for y in first_row..last_row {
+		for x in first_column..last_column {
+			let index = y * width + x;
+
+			// no threshold is ever below the topple rule's
+			let val = input_data[index];
+			if val < threshold {
+				continue;
+			}
+
+			let key = [(chunk_origin.0 + x as i64) as u64, (chunk_origin.1 + y as i64) as u64, odometer_data[index]];
+			let neighbors = if (parity + x + y) % 2 == 0 { &kernel.even } else { &kernel.odd };
+
+			match model {
+				Model::Manna { seed } => {
+					let mut random = Random::keyed(seed, &key);
+					let total_weight: u64 = neighbors.iter().map(|&(_, amount)| amount.into()).sum();
+
+					output_data[index] -= threshold;
+					for _ in 0..threshold.into() {
+						let mut choice = random.below(total_weight);
+						for &(offset, amount) in neighbors {
+							if choice < amount.into() {
+								output_data[(index as isize + offset) as usize] += one;
+								break;
+							}
+							choice -= amount.into();
+						}
+					}
+				},
+				Model::Oslo { seed } => {
+					if val < threshold + T::from((Random::keyed(seed, &key).next_u64() & 1) as u32) {
+						continue;
+					}
+
+					output_data[index] -= threshold;
+					for &(offset, amount) in neighbors {
+						output_data[(index as isize + offset) as usize] += amount;
+					}
+				},
+				Model::Abelian | Model::Divisible | Model::RotorRouter => unreachable!("only the stochastic models are processed here"),
+			}
+
+			num_redistributions += 1;
+			counting_data[index] += 1;
+			odometer_data[index] += 1;
+		}
+	}
+	num_redistributions
+}
+
+// the part of the array that makes up the result: the whole pile in quadrant mode, and only the domain itself for a
+// fixed-size domain. returns the cells along with their side length and the world coordinates of the first one
+fn visible_cells<V: Copy + Default>(data: Vec<V>, side_length: usize, origin_x: i64, origin_y: i64, symmetry: Symmetry, boundary: Boundary, margin: usize) -> (Vec<V>, usize, i64, i64) {
+	if symmetry == Symmetry::Quadrant {
+		let ghost_size = quadrant_ghost_size(margin);
+		let quadrant_size = side_length - ghost_size;
+		let offset = (ghost_size + 1) as i64 - quadrant_size as i64;
+		(expand_quadrant(&data, side_length, ghost_size), quadrant_size * 2 - 1, origin_x + offset, origin_y + offset)
+	} else if let Some(size) = boundary.domain_size() {
+		// leave out the cells around a fixed-size domain. they're always empty
+		(crop(&data, side_length, margin, size), size, origin_x + margin as i64, origin_y + margin as i64)
+	} else {
+		(data, side_length, origin_x, origin_y)
+	}
+}
+
+fn record_frame<T: Grains>(recorder: &Mutex<TimelineRecorder>, data: &[T], iteration: usize, side_length: usize, origin_x: i64, origin_y: i64, settings: &Settings) -> Result<(), ComputeError> {
+	let margin = max(settings.topple_rule.radius(), 1);
+	let cells: Vec<u8> = data.iter().map(|&value| min(value.into(), 255) as u8).collect();
+	let (cells, side_length, origin_x, origin_y) = visible_cells(cells, side_length, origin_x, origin_y, settings.symmetry, settings.boundary, margin);
+
+	recorder.lock().unwrap().record(iteration, &cells, side_length, origin_x, origin_y)
+		.map_err(|error| ComputeError::Io(format!("couldn't write timeline file: {}", error)))
+}
+
+pub(crate) const EXCESS_INTERVAL: usize = 64;
+
+// the grains above the largest stable height, which all have to be toppled away before the pile is stable
+pub(crate) fn excess_grains<T: Grains>(data: &[T], threshold: T) -> u64 {
+	let max_stable = threshold - T::from(1);
+	data.par_iter().filter(|&&value| value > max_stable).map(|&value| (value - max_stable).into()).sum()
+}
+
+// the kernels of a topple rule, converted to offsets into an array with the given width
+struct KernelOffsets<T> {
+	threshold: T,
+	even: Vec<(isize, T)>,
+	odd: Vec<(isize, T)>,
+}
+
+impl<T: Grains> KernelOffsets<T> {
+	fn new(topple_rule: &ToppleRule, width: usize) -> Self {
+		Self::with_amounts(topple_rule, width, T::from)
 	}
 }
 
-fn process_row(input_data: &[u32], output_data: &mut [u32], counting_data: &mut [u32], width: usize) -> i32 {
+impl<T: Copy> KernelOffsets<T> {
+	// `convert` turns the threshold and the amounts sent to each neighbor into the type the cells are stored in
+	fn with_amounts<F: Fn(u32) -> T>(topple_rule: &ToppleRule, width: usize, convert: F) -> Self {
+		let kernel_offsets = |kernel: &[ToppleNeighbor]| -> Vec<(isize, T)> {
+			kernel.iter().map(|neighbor| (neighbor.dy * width as isize + neighbor.dx, convert(neighbor.amount))).collect()
+		};
+		let even = kernel_offsets(&topple_rule.kernel);
+		let odd = topple_rule.odd_kernel.as_ref().map(|kernel| kernel_offsets(kernel)).unwrap_or_else(|| even.clone());
+
+		Self { threshold: convert(topple_rule.threshold), even, odd }
+	}
+}
+
+// with `fire_once`, a cell topples once however many times over it holds the threshold, as in parallel chip-firing
+fn process_row<T: Grains>(input_data: &[T], output_data: &mut [T], counting_data: &mut [u32], odometer_data: &mut [u64], width: usize, kernel: &KernelOffsets<T>, margin: usize, parity: usize, first_column: usize, last_column: usize, fire_once: bool) -> u64 {
 
 	assert_eq!(input_data.len(), output_data.len());
 	assert_eq!(input_data.len(), counting_data.len());
+	assert_eq!(input_data.len(), odometer_data.len());
 
 	assert!(input_data.len() % width == 0);
-	assert!(input_data.len() / width >= 3);
+	assert!(input_data.len() / width >= margin * 2 + 1);
+	assert!(first_column >= margin && last_column <= width - margin);
 
-	let num_rows = input_data.len() / width - MARGIN * 2;
+	let num_rows = input_data.len() / width - margin * 2;
 
 
-	let first_row = MARGIN;
+	let first_row = margin;
 	let last_row = first_row + num_rows;
 
-	let first_column = MARGIN;
-	let last_column = width - MARGIN + 1;
+	let threshold = kernel.threshold;
 
 	let mut num_redistributions = 0;
 	for y in first_row..last_row {
@@ -139,33 +1690,36 @@ fn process_row(input_data: &[u32], output_data: &mut [u32], counting_data: &mut
 			let index = y * width + x;
 
 			let val = input_data[index];
-			if val >= TOPPLE_AMOUNT {
+			if val >= threshold {
 				num_redistributions += 1;
 				counting_data[index] += 1;
 
-				let distribute = val / TOPPLE_AMOUNT;
+				let distribute = if fire_once { T::from(1) } else { val / threshold };
+				odometer_data[index] += distribute.into();
 
-				output_data[index - width] += distribute;
+				let neighbors = if (parity + x + y) % 2 == 0 { &kernel.even } else { &kernel.odd };
 
-				output_data[index - 1] += distribute;
-				output_data[index] -= distribute * TOPPLE_AMOUNT;
-				output_data[index + 1] += distribute;
-
-				output_data[index + width] += distribute;
+				output_data[index] -= distribute * threshold;
+				for &(offset, amount) in neighbors {
+					output_data[(index as isize + offset) as usize] += distribute * amount;
+				}
 			}
 		}
 	}
 	num_redistributions
 }
 
-fn maybe_reallocate(main_array: &mut Vec<u32>, secondary_array: &mut Vec<u32>, counting_array: &mut Vec<u32>, side_length: &mut usize) -> usize {
+fn maybe_reallocate<T: Copy + Send + Sync + PartialEq, O: Copy + Default + Send + Sync>(main_array: &mut Vec<T>, secondary_array: &mut Vec<T>, counting_array: &mut Vec<u32>, odometer_array: &mut Vec<O>, side_length: &mut usize, origin_x: &mut i64, origin_y: &mut i64, topple_rule: &ToppleRule, background: T) -> usize {
+	let margin = max(topple_rule.radius(), 1);
 
 	// find the bounds of the fractal data, so that we can re-center it inside the new array
+	// every cell that differs from the background or has toppled has to be included, otherwise it would be lost when copying
+	let changed = |index: usize| main_array[index] != background || counting_array[index] != 0;
 	let mut miny = 0;
 	'outer_miny: for y in 0..*side_length {
 		for x in 0..*side_length {
 			let index = y * *side_length + x;
-			if main_array[index] >= TOPPLE_AMOUNT {
+			if changed(index) {
 				miny = y;
 				break 'outer_miny;
 			}
@@ -176,7 +1730,7 @@ fn maybe_reallocate(main_array: &mut Vec<u32>, secondary_array: &mut Vec<u32>, c
 	'outer_maxy: for y in (0..*side_length).rev() {
 		for x in (0..*side_length).rev() {
 			let index = y * *side_length + x;
-			if main_array[index] >= TOPPLE_AMOUNT {
+			if changed(index) {
 				maxy = y;
 				break 'outer_maxy;
 			}
@@ -185,9 +1739,9 @@ fn maybe_reallocate(main_array: &mut Vec<u32>, secondary_array: &mut Vec<u32>, c
 
 	let mut minx = 0;
 	'outer_minx: for x in 0..*side_length {
-		for y in miny..maxy {
+		for y in miny..=maxy {
 			let index = y * *side_length + x;
-			if main_array[index] >= TOPPLE_AMOUNT {
+			if changed(index) {
 				minx = x;
 				break 'outer_minx;
 			}
@@ -196,9 +1750,9 @@ fn maybe_reallocate(main_array: &mut Vec<u32>, secondary_array: &mut Vec<u32>, c
 
 	let mut maxx = 0;
 	'outer_maxx: for x in (0..*side_length).rev() {
-		for y in (miny..maxy).rev() {
+		for y in (miny..=maxy).rev() {
 			let index = y * *side_length + x;
-			if main_array[index] >= TOPPLE_AMOUNT {
+			if changed(index) {
 				maxx = x;
 				break 'outer_maxx;
 			}
@@ -210,16 +1764,17 @@ fn maybe_reallocate(main_array: &mut Vec<u32>, secondary_array: &mut Vec<u32>, c
 
 	let closest = min(closest_vertical, closest_horizontal);
 
-	if closest <= MARGIN {
+	if closest <= margin {
 		const MIN_SIZE: usize = 120;
-		const STANDARD_INCREASE: usize = 8;
+		let standard_increase = margin * 8;
 
-		let new_side_length = next_multiple(max(MIN_SIZE, *side_length + STANDARD_INCREASE), REQUIRED_SIZE_MULTIPLE);
+		let new_side_length = next_multiple(max(MIN_SIZE, *side_length + standard_increase), required_size_multiple(margin));
 
 		let increase = new_side_length - *side_length;
 
-		let mut new_main_array = vec![0; new_side_length * new_side_length];
-		let mut new_counting_array = new_main_array.clone();
+		let mut new_main_array = vec![background; new_side_length * new_side_length];
+		let mut new_counting_array = vec![0; new_side_length * new_side_length];
+		let mut new_odometer_array = vec![O::default(); new_side_length * new_side_length];
 
 		let size_x = maxx - minx + 1;
 		let size_y = maxy - miny + 1;
@@ -243,32 +1798,419 @@ fn maybe_reallocate(main_array: &mut Vec<u32>, secondary_array: &mut Vec<u32>, c
 				for (old_row, new_row) in old_counting_rows.zip(new_counting_rows) {
 					new_row[new_x_begin..new_x_begin+size_x].copy_from_slice(&old_row[minx..minx+size_x]);
 				}
+
+				let old_odometer_rows = odometer_array.chunks(*side_length).skip(miny).take(size_y);
+				let new_odometer_rows = new_odometer_array.chunks_mut(new_side_length).skip(new_y_begin).take(size_y);
+
+				for (old_row, new_row) in old_odometer_rows.zip(new_odometer_rows) {
+					new_row[new_x_begin..new_x_begin+size_x].copy_from_slice(&old_row[minx..minx+size_x]);
+				}
 			}
 		);
 
+		// keep track of where the world origin moved to, so that results can be mapped back to world coordinates
+		*origin_x += minx as i64 - new_x_begin as i64;
+		*origin_y += miny as i64 - new_y_begin as i64;
+
+		*secondary_array = new_main_array.clone();
+		*main_array = new_main_array;
+		*counting_array = new_counting_array;
+		*odometer_array = new_odometer_array;
+		*side_length = new_side_length;
+
+		max(1, increase / (margin * 4))
+	} else {
+		copy_data(&main_array, &mut *secondary_array);
+
+		// the fractal grows by at most one margin per iteration
+		max(1, closest / (margin * 2))
+	}
+}
+
+fn quadrant_ghost_size(margin: usize) -> usize {
+	// ghost cells within one margin of the axes topple into the quadrant, and the margin beyond them catches their output
+	margin * 2
+}
+
+// copies the cells just inside the quadrant to the ghost cells on the other side of the symmetry axes.
+// the axes pass through the source cell, so ghost cell -k mirrors cell +k
+fn mirror_ghost_cells<T: Copy>(data: &mut [T], side_length: usize, ghost_size: usize) {
+	for row in data.chunks_mut(side_length).skip(ghost_size) {
+		for k in 1..=ghost_size {
+			row[ghost_size - k] = row[ghost_size + k];
+		}
+	}
+	for k in 1..=ghost_size {
+		let (before, after) = data.split_at_mut((ghost_size + k) * side_length);
+		before[(ghost_size - k) * side_length..(ghost_size - k + 1) * side_length].copy_from_slice(&after[..side_length]);
+	}
+}
+
+// like maybe_reallocate, except that the quadrant only ever grows to the right and bottom, so nothing needs to be re-centered
+fn maybe_grow_quadrant<T: Copy + Send + Sync + PartialEq, O: Copy + Default + Send + Sync>(main_array: &mut Vec<T>, secondary_array: &mut Vec<T>, counting_array: &mut Vec<u32>, odometer_array: &mut Vec<O>, side_length: &mut usize, topple_rule: &ToppleRule, background: T) -> usize {
+	let margin = max(topple_rule.radius(), 1);
+
+	let mut max_extent = 0;
+	for (y, (row, counting_row)) in main_array.chunks(*side_length).zip(counting_array.chunks(*side_length)).enumerate() {
+		if let Some(x) = row.iter().zip(counting_row).rposition(|(&value, &count)| value != background || count != 0) {
+			max_extent = max(max_extent, max(x, y));
+		}
+	}
+
+	let closest = *side_length - max_extent - 1;
+
+	if closest <= margin {
+		const MIN_SIZE: usize = 64;
+		let standard_increase = margin * 8;
+
+		let new_side_length = next_multiple(max(MIN_SIZE, *side_length + standard_increase), required_size_multiple(margin));
+		let increase = new_side_length - *side_length;
+
+		let mut new_main_array = vec![background; new_side_length * new_side_length];
+		let mut new_counting_array = vec![0; new_side_length * new_side_length];
+		let mut new_odometer_array = vec![O::default(); new_side_length * new_side_length];
+
+		for (old_row, new_row) in main_array.chunks(*side_length).zip(new_main_array.chunks_mut(new_side_length)) {
+			new_row[..*side_length].copy_from_slice(old_row);
+		}
+		for (old_row, new_row) in counting_array.chunks(*side_length).zip(new_counting_array.chunks_mut(new_side_length)) {
+			new_row[..*side_length].copy_from_slice(old_row);
+		}
+		for (old_row, new_row) in odometer_array.chunks(*side_length).zip(new_odometer_array.chunks_mut(new_side_length)) {
+			new_row[..*side_length].copy_from_slice(old_row);
+		}
+
 		*secondary_array = new_main_array.clone();
 		*main_array = new_main_array;
 		*counting_array = new_counting_array;
+		*odometer_array = new_odometer_array;
 		*side_length = new_side_length;
 
-		increase / 4
+		max(1, increase / (margin * 2))
 	} else {
 		copy_data(&main_array, &mut *secondary_array);
 
-		closest / 2
+		max(1, closest / (margin * 2))
+	}
+}
+
+// unfolds a simulated quadrant into the full square, dropping the ghost cells
+fn expand_quadrant<T: Copy + Default>(data: &[T], side_length: usize, ghost_size: usize) -> Vec<T> {
+	let quadrant_size = side_length - ghost_size;
+	let full_size = quadrant_size * 2 - 1;
+	let center = quadrant_size - 1;
+
+	let mut full_data = vec![T::default(); full_size * full_size];
+	for (y, row) in full_data.chunks_mut(full_size).enumerate() {
+		let source_y = ghost_size + if y >= center { y - center } else { center - y };
+		let source_row = &data[source_y * side_length..(source_y + 1) * side_length];
+		for (x, cell) in row.iter_mut().enumerate() {
+			let source_x = ghost_size + if x >= center { x - center } else { center - x };
+			*cell = source_row[source_x];
+		}
+	}
+	full_data
+}
+
+// moves the grains that landed outside a fixed-size domain, according to the boundary. the domain starts `offset` cells
+// into the array in both directions. returns the number of grains absorbed by a sink
+fn fold_boundary<T: Copy + Default + PartialEq + AddAssign>(data: &mut [T], side_length: usize, offset: usize, size: usize, boundary: Boundary) -> T {
+	let mut absorbed_grains = T::default();
+
+	let domain = offset..offset + size;
+	for y in 0..side_length {
+		// only look at the cells outside the domain
+		let skip_end = if domain.contains(&y) { offset + size } else { offset };
+		for x in (0..offset).chain(skip_end..side_length) {
+			let index = y * side_length + x;
+			let value = data[index];
+			if value == T::default() {
+				continue;
+			}
+			data[index] = T::default();
+
+			let domain_x = x as isize - offset as isize;
+			let domain_y = y as isize - offset as isize;
+			let target = match boundary {
+				Boundary::Plane | Boundary::Sink { .. } => None,
+				Boundary::Torus { .. } => Some((domain_x.rem_euclid(size as isize), domain_y.rem_euclid(size as isize))),
+				Boundary::Reflecting { .. } => Some((reflect(domain_x, size), reflect(domain_y, size))),
+			};
+
+			match target {
+				Some((target_x, target_y)) => data[(target_y as usize + offset) * side_length + target_x as usize + offset] += value,
+				None => absorbed_grains += value,
+			}
+		}
+	}
+	absorbed_grains
+}
+
+fn reflect(position: isize, size: usize) -> isize {
+	let period = size as isize * 2;
+	let position = position.rem_euclid(period);
+	if position < size as isize { position } else { period - 1 - position }
+}
+
+fn crop<T: Copy>(data: &[T], side_length: usize, offset: usize, size: usize) -> Vec<T> {
+	data.chunks(side_length).skip(offset).take(size).flat_map(|row| row[offset..offset + size].iter().cloned()).collect()
+}
+
+// runs `process` on every chunk of `rows_per_chunk` rows in two passes, the second one shifted by half a chunk, so that
+// the chunks that run at the same time never write to the same rows. `process` also gets the index of each chunk's first row
+pub(crate) fn process_chunks<T, O, F>(read_array: &[T], write_array: &mut [T], counting_array: &mut [u32], odometer_array: &mut [O], row_length: usize, rows_per_chunk: usize, process: F) -> u64
+	where T: Sync + Send, O: Send, F: Fn(usize, &[T], &mut [T], &mut [u32], &mut [O]) -> u64 + Sync
+{
+	let rows = read_array.len() / row_length;
+	let chunk_length = row_length * rows_per_chunk;
+	let mut total = 0;
+	for i in 0..2 {
+		let offset = i * (rows_per_chunk / 2);
+		let limit = rows.saturating_sub(offset) / rows_per_chunk;
+		if limit == 0 {
+			continue;
+		}
+
+		let read_iter = read_array[(offset * row_length)..].par_chunks(chunk_length).take(limit);
+		let write_iter = write_array[(offset * row_length)..].par_chunks_mut(chunk_length).take(limit);
+		let counting_iter = counting_array[(offset * row_length)..].par_chunks_mut(chunk_length).take(limit);
+		let odometer_iter = odometer_array[(offset * row_length)..].par_chunks_mut(chunk_length).take(limit);
+
+		total += read_iter.zip(write_iter).zip(counting_iter).zip(odometer_iter).enumerate().map(|(chunk_index, (((input_chunk, output_chunk), counting_chunk), odometer_chunk))| {
+			process(offset + chunk_index * rows_per_chunk, input_chunk, output_chunk, counting_chunk, odometer_chunk)
+		}).sum::<u64>();
 	}
+	total
 }
 
-fn copy_data<T: Copy + Sync + Send>(src: &[T], dst: &mut [T]) {
+pub(crate) fn copy_data<T: Copy + Sync + Send>(src: &[T], dst: &mut [T]) {
 	let chunk_size = src.len() / 8;
 	src.par_chunks(chunk_size).zip(dst.par_chunks_mut(chunk_size)).for_each(|(input_chunk, output_chunk)| output_chunk.copy_from_slice(input_chunk));
 }
 
-fn next_multiple(val: usize, multiple: usize) -> usize {
+pub(crate) fn next_multiple(val: usize, multiple: usize) -> usize {
 	let distance = val % multiple;
 	if distance == 0 {
 		val
 	} else {
 		val + multiple - distance
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use crate::common::DataField;
+
+	pub(crate) fn stabilize_with<T: Grains>(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, symmetry: Symmetry, background_height: u32, engine: Engine) -> Result<FractalResult, ComputeError> {
+		let settings = Settings { symmetry, background_height, ..Settings::new(topple_rule, boundary, engine) };
+		compute_fractal_data::<T>(initial_configuration, &settings)
+	}
+
+	fn assert_engines_agree<T: Grains>(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, symmetry: Symmetry, background_height: u32) -> FractalResult {
+		let dense = stabilize_with::<T>(initial_configuration, topple_rule, boundary, symmetry, background_height, Engine::Dense).unwrap();
+		let sparse = stabilize_with::<T>(initial_configuration, topple_rule, boundary, symmetry, background_height, Engine::Sparse).unwrap();
+		assert_eq!((dense.side_length, dense.origin_x, dense.origin_y), (sparse.side_length, sparse.origin_x, sparse.origin_y));
+		assert!(dense.sand_data == sparse.sand_data, "sand differs");
+		assert!(dense.count_data == sparse.count_data, "topple steps differ");
+		assert!(dense.odometer_data == sparse.odometer_data, "odometers differ");
+		assert_eq!(dense.total_topples, sparse.total_topples);
+		assert_eq!(dense.absorbed_grains, sparse.absorbed_grains);
+		dense
+	}
+
+	pub(crate) fn source(x: i64, y: i64, value: u64) -> InitialCell {
+		InitialCell { x, y, value }
+	}
+
+	#[test]
+	fn sparse_matches_dense_on_the_plane() {
+		let sources = [source(0, 0, 3000), source(17, -5, 800), source(-9, 12, 1500)];
+		for topple_rule in &[ToppleRule::von_neumann(), ToppleRule::moore(), ToppleRule::hexagonal(), ToppleRule::triangular()] {
+			assert_engines_agree::<u32>(&sources, topple_rule, Boundary::Plane, Symmetry::None, 0);
+			assert_engines_agree::<u64>(&sources, topple_rule, Boundary::Plane, Symmetry::None, 0);
+		}
+		assert_engines_agree::<u32>(&sources, &ToppleRule::von_neumann(), Boundary::Plane, Symmetry::None, 2);
+	}
+
+	#[test]
+	fn sparse_matches_dense_while_the_array_grows() {
+		// the initial array only just covers the source, so the pile has to be reallocated several times
+		let sources = [source(0, 0, 20000)];
+		let result = assert_engines_agree::<u32>(&sources, &ToppleRule::von_neumann(), Boundary::Plane, Symmetry::None, 0);
+		assert!(result.side_length > 64);
+		assert_engines_agree::<u64>(&sources, &ToppleRule::extended_von_neumann(2), Boundary::Plane, Symmetry::None, 0);
+	}
+
+	#[test]
+	fn sparse_matches_dense_on_fixed_domains() {
+		let sources = [source(0, 0, 2000), source(10, 3, 700), source(-20, -20, 500)];
+		// the grains on the torus and reflecting domains stay well below the number of edges, so the piles stabilize
+		for &(boundary, background_height) in &[(Boundary::Sink { size: 41 }, 2), (Boundary::Sink { size: 64 }, 1), (Boundary::Torus { size: 48 }, 0), (Boundary::Reflecting { size: 45 }, 0)] {
+			for topple_rule in &[ToppleRule::von_neumann(), ToppleRule::moore()] {
+				assert_engines_agree::<u32>(&sources, topple_rule, boundary, Symmetry::None, background_height);
+				assert_engines_agree::<u64>(&sources, topple_rule, boundary, Symmetry::None, background_height);
+			}
+		}
+	}
+
+	#[test]
+	fn sparse_matches_dense_with_quadrant_symmetry() {
+		let sources = [source(0, 0, 30000)];
+		for topple_rule in &[ToppleRule::von_neumann(), ToppleRule::moore(), ToppleRule::extended_von_neumann(2)] {
+			let quadrant = assert_engines_agree::<u32>(&sources, topple_rule, Boundary::Plane, Symmetry::Quadrant, 0);
+			assert_engines_agree::<u64>(&sources, topple_rule, Boundary::Plane, Symmetry::Quadrant, 1);
+
+			// and the symmetric pile is the whole pile
+			let full = stabilize_with::<u32>(&sources, topple_rule, Boundary::Plane, Symmetry::None, 0, Engine::Dense).unwrap();
+			for index in 0..full.sand_data.len() {
+				let (x, y) = full.index_to_world(index);
+				let value = quadrant.world_to_index(x, y).map_or(0, |index| quadrant.sand_data[index]);
+				assert_eq!(full.sand_data[index], value, "({}, {})", x, y);
+			}
+		}
+	}
+
+	#[test]
+	fn piles_that_never_stabilize_on_closed_domains() {
+		// fewer grains than the domains can hold, but more than the number of edges
+		let sources = [source(0, 0, 2000), source(10, 3, 700), source(-20, -20, 500)];
+		for &boundary in &[Boundary::Torus { size: 48 }, Boundary::Reflecting { size: 45 }] {
+			for &engine in &[Engine::Dense, Engine::Sparse] {
+				let result = stabilize_with::<u32>(&sources, &ToppleRule::von_neumann(), boundary, Symmetry::None, 1, engine);
+				assert!(matches!(result, Err(ComputeError::NeverStabilizes(_))), "{:?} {:?}", boundary, engine);
+			}
+		}
+
+		// a kernel that loses grains always stabilizes in the end
+		let leaky = ToppleRule::weighted(5, ToppleRule::von_neumann().kernel).unwrap();
+		assert!(closed_domain_size(&leaky, Model::Abelian, Boundary::Torus { size: 48 }).is_none());
+		assert!(stabilize_with::<u32>(&sources, &leaky, Boundary::Torus { size: 48 }, Symmetry::None, 1, Engine::Dense).is_ok());
+	}
+
+	#[test]
+	fn divisible_odometer_is_the_mass_sent_out() {
+		let topple_rule = ToppleRule::von_neumann();
+		let settings = Settings { model: Model::Divisible, ..Settings::new(&topple_rule, Boundary::Sink { size: 21 }, Engine::Dense) };
+		let (data, side_length, origin_x, origin_y) = initial_array(&[source(0, 0, 400)], &settings, 0.0, |value| value as f64);
+		let result = stabilize_divisible(data, side_length, origin_x, origin_y, &settings, 1e-9, 0).unwrap();
+		assert!(result.odometer_data.is_empty());
+
+		// every cell ends up with what it started with, plus its share of what its neighbors sent out, minus what it sent out
+		let sent = |x: i64, y: i64| result.world_to_index(x, y).map_or(0.0, |index| result.field_value(DataField::Odometer, index));
+		for (index, &mass) in result.mass_data.iter().enumerate() {
+			let (x, y) = result.index_to_world(index);
+			let initial = if (x, y) == (0, 0) { 400.0 } else { 0.0 };
+			let received: f64 = topple_rule.kernel.iter().map(|neighbor| sent(x - neighbor.dx as i64, y - neighbor.dy as i64) / 4.0).sum();
+			assert!((initial + received - sent(x, y) - mass).abs() < 1e-6, "({}, {})", x, y);
+		}
+
+		let remaining: f64 = result.mass_data.iter().sum();
+		assert!(result.absorbed_mass > 0.0);
+		assert!((remaining + result.absorbed_mass - 400.0).abs() < 1e-6);
+	}
+
+	#[test]
+	fn generated_graphs_are_limited_in_size() {
+		let graph = |kind: GraphKind, size: &str, branching: &str| ComputeParams { graph: kind, graph_size: size.into(), tree_branching: branching.into(), ..Default::default() }.graph();
+		assert!(graph(GraphKind::Torus, "100", "2").is_ok());
+		assert!(graph(GraphKind::Torus, "100000", "2").is_err());
+		assert!(graph(GraphKind::Torus, &usize::MAX.to_string(), "2").is_err());
+		assert!(graph(GraphKind::Complete, "5001", "2").is_err());
+		assert!(graph(GraphKind::Tree, "5", "3").is_ok());
+		assert!(graph(GraphKind::Tree, "5", "0").is_err());
+		// a chain is limited by its length, and is rejected without building it
+		assert!(graph(GraphKind::Tree, "1000", "1").is_ok());
+		assert!(graph(GraphKind::Tree, &usize::MAX.to_string(), "1").is_err());
+		assert!(graph(GraphKind::Tree, "40", "2").is_err());
+	}
+
+	#[test]
+	fn timelines_line_up_across_reallocations() {
+		let path = std::env::temp_dir().join(format!("sandpile_timeline_{}.bin", std::process::id()));
+		let path = path.to_str().unwrap();
+		let topple_rule = ToppleRule::von_neumann();
+		let recorder = Mutex::new(TimelineRecorder::create(path, Schedule::Every(1), &topple_rule, Model::Abelian, Boundary::Plane, 0).unwrap());
+		let result = {
+			let settings = Settings { recorder: Some(&recorder), ..Settings::new(&topple_rule, Boundary::Plane, Engine::Dense) };
+			compute_fractal_data::<u32>(&[source(0, 0, 40000)], &settings).unwrap()
+		};
+		recorder.into_inner().unwrap().finish().unwrap();
+		let timeline = crate::timeline::load_timeline(path).unwrap();
+		std::fs::remove_file(path).unwrap();
+
+		// one frame at the start of every iteration. the last one didn't topple anything, so it holds the final pile
+		assert_eq!(timeline.frames.len(), result.total_iterations);
+		let first = &timeline.frames[0];
+		assert!(timeline.frames.iter().any(|frame| (frame.side_length, frame.origin_x, frame.origin_y) != (first.side_length, first.origin_x, first.origin_y)));
+
+		let last = timeline.frame_result(timeline.frames.len() - 1);
+		for (index, &value) in last.sand_data.iter().enumerate() {
+			let (x, y) = last.index_to_world(index);
+			assert_eq!(value, result.world_to_index(x, y).map_or(0, |index| result.sand_data[index]), "({}, {})", x, y);
+		}
+
+		let first = timeline.frame_result(0);
+		for (index, &value) in first.sand_data.iter().enumerate() {
+			assert_eq!(value, if first.index_to_world(index) == (0, 0) { 255 } else { 0 });
+		}
+	}
+
+	#[test]
+	fn stochastic_models_repeat_with_the_same_seed() {
+		let topple_rule = ToppleRule::von_neumann();
+		let stabilize = |model| {
+			let settings = Settings { model, ..Settings::new(&topple_rule, Boundary::Plane, Engine::Dense) };
+			compute_fractal_data::<u32>(&[source(0, 0, 3000)], &settings).unwrap()
+		};
+		for &model in &[Model::Manna { seed: 1 }, Model::Oslo { seed: 1 }] {
+			let other_seed = match model {
+				Model::Manna { .. } => Model::Manna { seed: 2 },
+				_ => Model::Oslo { seed: 2 },
+			};
+			let first = stabilize(model);
+			let second = stabilize(model);
+			let other = stabilize(other_seed);
+			assert!(first.sand_data == second.sand_data && first.odometer_data == second.odometer_data, "{} differs between runs", model.name());
+			assert_eq!(first.total_topples, second.total_topples);
+			assert!(first.sand_data != other.sand_data || first.odometer_data != other.odometer_data, "{} ignores the seed", model.name());
+		}
+	}
+
+	// the cells inside the domain of a sink boundary, row by row
+	fn domain_values(result: &FractalResult) -> Vec<u8> {
+		let start = result.boundary.domain_start();
+		let size = result.boundary.domain_size().unwrap() as i64;
+		(start..start + size).flat_map(|y| (start..start + size).map(move |x| (x, y)))
+			.map(|(x, y)| result.world_to_index(x, y).map_or(0, |index| result.sand_data[index])).collect()
+	}
+
+	#[test]
+	fn identities_of_small_grids() {
+		assert_eq!(domain_values(&identity(3).unwrap()), vec![2, 1, 2, 1, 0, 1, 2, 1, 2]);
+		assert_eq!(domain_values(&identity(2).unwrap()), vec![2; 4]);
+		assert!(matches!(identity(0), Err(ComputeError::InvalidParameter(_))));
+	}
+
+	#[test]
+	fn group_operations_on_a_small_grid() {
+		let identity = identity(5).unwrap();
+		assert_eq!(domain_values(&inverse(&identity, Engine::Dense, None).unwrap()), domain_values(&identity));
+
+		// anything at least as large as the maximal stable configuration stabilizes to a recurrent configuration
+		let recurrent = stabilize_with::<u32>(&[source(0, 0, 7), source(1, -2, 3)], &ToppleRule::von_neumann(), Boundary::Sink { size: 5 }, Symmetry::None, 3, Engine::Dense).unwrap();
+		assert_ne!(domain_values(&recurrent), domain_values(&identity));
+		assert_eq!(domain_values(&add_results(&recurrent, &identity, Engine::Dense, None).unwrap()), domain_values(&recurrent));
+		let inverse = inverse(&recurrent, Engine::Dense, None).unwrap();
+		assert_eq!(domain_values(&add_results(&recurrent, &inverse, Engine::Dense, None).unwrap()), domain_values(&identity));
+	}
+
+	#[test]
+	fn group_operations_need_matching_domains() {
+		let identity = identity(3).unwrap();
+		let other_size = super::identity(4).unwrap();
+		let other_rule = identity_with_rule(3, &ToppleRule::moore(), Engine::Dense, None).unwrap();
+		assert!(matches!(add_results(&identity, &other_size, Engine::Dense, None), Err(ComputeError::InvalidParameter(_))));
+		assert!(matches!(add_results(&identity, &other_rule, Engine::Dense, None), Err(ComputeError::InvalidParameter(_))));
+	}
+}
@@ -0,0 +1,437 @@
+use std::cmp::{max, min};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::common::{Boundary, DataField};
+use crate::compute::{self, ComputeError, ComputeMonitor, Grains, Progress};
+
+// every cell of the cubic lattice has six neighbors, and topples once it holds six grains
+pub const CUBIC_THRESHOLD: u32 = 6;
+
+// grains only ever move to adjacent cells, so the margin is always one cell
+const MARGIN: usize = 1;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct InitialVoxel {
+	pub x: i64,
+	pub y: i64,
+	pub z: i64,
+	pub value: u64,
+}
+
+// the three-dimensional counterpart of FractalResult. the cells are stored slice by slice, so the cell at (x, y, z)
+// is at index (z * side_length + y) * side_length + x, relative to the origin
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VolumeResult {
+	pub initial_configuration: Vec<InitialVoxel>,
+	// only the plane and a sink around a cube are supported
+	pub boundary: Boundary,
+	pub background_height: u32,
+	pub sand_data: Vec<u8>,
+	// number of iterations in which each cell toppled
+	pub count_data: Vec<u32>,
+	// total number of times each cell toppled
+	pub odometer_data: Vec<u64>,
+	pub side_length: usize,
+
+	// world coordinates of the cell at array index 0
+	pub origin_x: i64,
+	pub origin_y: i64,
+	pub origin_z: i64,
+
+	pub total_redistributions: u64,
+	pub total_topples: u64,
+	pub absorbed_grains: u64,
+	pub total_iterations: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Axis {
+	X,
+	Y,
+	Z,
+}
+
+// which plane of a volume to show: either the slice at `position` along the axis, or the largest value along the axis
+// for every cell of the plane. slices along z show x to the right and y down, the other axes show z down
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VolumeView {
+	pub axis: Axis,
+	pub projection: bool,
+	// world coordinate along the axis
+	pub position: i64,
+}
+
+impl Default for VolumeView {
+	fn default() -> Self {
+		Self { axis: Axis::Z, projection: false, position: 0 }
+	}
+}
+
+impl VolumeResult {
+	pub fn world_to_index(&self, x: i64, y: i64, z: i64) -> Option<usize> {
+		let side_length = self.side_length as i64;
+		let (array_x, array_y, array_z) = (x - self.origin_x, y - self.origin_y, z - self.origin_z);
+
+		if [array_x, array_y, array_z].iter().all(|&position| position >= 0 && position < side_length) {
+			Some(((array_z * side_length + array_y) * side_length + array_x) as usize)
+		} else {
+			None
+		}
+	}
+
+	pub fn field_value(&self, field: DataField, index: usize) -> u64 {
+		match field {
+			DataField::Sand => u64::from(self.sand_data[index]),
+			DataField::ToppleSteps => u64::from(self.count_data[index]),
+			DataField::Odometer => self.odometer_data[index],
+			// there's no rotor-router model on the cubic lattice
+			DataField::Rotor => 0,
+		}
+	}
+
+	// the range of world coordinates the volume covers along an axis
+	pub fn extent(&self, axis: Axis) -> (i64, i64) {
+		let origin = match axis {
+			Axis::X => self.origin_x,
+			Axis::Y => self.origin_y,
+			Axis::Z => self.origin_z,
+		};
+		(origin, origin + self.side_length as i64 - 1)
+	}
+
+	// the values of a field on the plane the view shows, row by row. the plane is side_length cells across
+	pub fn view_plane(&self, field: DataField, view: VolumeView) -> Vec<u64> {
+		let side_length = self.side_length;
+		let (first, _) = self.extent(view.axis);
+		let depths = if view.projection {
+			0..side_length
+		} else {
+			let depth = view.position - first;
+			if depth >= 0 && depth < side_length as i64 { depth as usize..depth as usize + 1 } else { 0..0 }
+		};
+
+		let mut plane = vec![0; side_length * side_length];
+		for (plane_index, value) in plane.iter_mut().enumerate() {
+			let (column, row) = (plane_index % side_length, plane_index / side_length);
+			for depth in depths.clone() {
+				let (x, y, z) = match view.axis {
+					Axis::X => (depth, column, row),
+					Axis::Y => (column, depth, row),
+					Axis::Z => (column, row, depth),
+				};
+				*value = max(*value, self.field_value(field, (z * side_length + y) * side_length + x));
+			}
+		}
+		plane
+	}
+}
+
+// stabilizes the sources on the cubic lattice, either on the whole of it or in a cube with a sink boundary
+pub fn run_cubic(initial_configuration: &[InitialVoxel], boundary: Boundary, background_height: u32, monitor: Option<&ComputeMonitor>) -> Result<VolumeResult, ComputeError> {
+	let domain_volume = match boundary {
+		Boundary::Plane => None,
+		Boundary::Sink { size } => Some((size as u64).saturating_mul(size as u64).saturating_mul(size as u64)),
+		_ => return Err(ComputeError::InvalidParameter("the cubic lattice only supports the plane and sink boundaries".into())),
+	};
+	if domain_volume.is_none() && background_height > 0 {
+		return Err(ComputeError::InvalidParameter("a background is only supported inside a sink boundary on the cubic lattice".into()));
+	}
+	if let Some(entry) = initial_configuration.iter().find(|entry| !in_domain(boundary, entry.x, entry.y, entry.z)) {
+		return Err(ComputeError::InvalidParameter(format!("source ({}, {}, {}) is outside the domain", entry.x, entry.y, entry.z)));
+	}
+
+	let total_grains = initial_configuration.iter().try_fold(0u64, |total, entry| total.checked_add(entry.value))
+		.and_then(|total| total.checked_add(domain_volume.unwrap_or(0).saturating_mul(u64::from(background_height))))
+		.ok_or_else(|| ComputeError::InvalidParameter("the total number of grains doesn't fit in 64 bits".into()))?;
+
+	let result = if total_grains <= u64::from(std::u32::MAX) {
+		stabilize_cubic::<u32>(initial_configuration, boundary, background_height, monitor)?
+	} else {
+		stabilize_cubic::<u64>(initial_configuration, boundary, background_height, monitor)?
+	};
+	Ok(VolumeResult { initial_configuration: initial_configuration.to_vec(), ..result })
+}
+
+fn in_domain(boundary: Boundary, x: i64, y: i64, z: i64) -> bool {
+	let start = boundary.domain_start();
+	boundary.contains(x, y) && boundary.domain_size().map_or(true, |size| z >= start && z < start + size as i64)
+}
+
+// the same two-pass scheme as the two-dimensional engine, with slabs of z-slices taking the place of rows
+fn stabilize_cubic<T: Grains>(initial_configuration: &[InitialVoxel], boundary: Boundary, background_height: u32, monitor: Option<&ComputeMonitor>) -> Result<VolumeResult, ComputeError> {
+	let threshold = T::from(CUBIC_THRESHOLD);
+	let slices_per_slab = compute::rows_per_chunk(MARGIN);
+
+	let (mut write_array, mut side_length, mut origin) = initial_volume::<T>(initial_configuration, boundary, background_height);
+	let mut read_array = write_array.clone();
+	let mut counting_array: Vec<u32> = vec![0; write_array.len()];
+	let mut odometer_array: Vec<u64> = vec![0; write_array.len()];
+
+	let mut next_check = match boundary {
+		Boundary::Plane => maybe_grow_volume(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, &mut origin),
+		_ => 0,
+	};
+
+	let mut total_iterations = 0;
+	let mut total_redistributions: u64 = 0;
+	let mut absorbed_grains: u64 = 0;
+
+	let initial_excess = if monitor.is_some() { compute::excess_grains(&read_array, threshold) } else { 0 };
+	let mut completion = 0.0;
+
+	loop {
+		if monitor.map_or(false, |monitor| monitor.is_cancelled()) {
+			return Err(ComputeError::Cancelled);
+		}
+
+		total_iterations += 1;
+
+		let slice_size = side_length * side_length;
+		let current_redist = compute::process_chunks(&read_array, &mut write_array, &mut counting_array, &mut odometer_array, slice_size, slices_per_slab, |_, input_slab, output_slab, counting_slab, odometer_slab| {
+			process_slab(input_slab, output_slab, counting_slab, odometer_slab, side_length, threshold)
+		});
+
+		if let Some(monitor) = monitor {
+			if total_iterations % compute::EXCESS_INTERVAL == 0 && initial_excess > 0 {
+				let excess = compute::excess_grains(&write_array, threshold);
+				completion = f64::max(completion, 1.0 - excess as f64 / initial_excess as f64);
+			}
+			monitor.report(Progress {
+				iterations: total_iterations,
+				side_length,
+				redistributions: current_redist,
+				estimated_completion: if current_redist == 0 { 1.0 } else { completion },
+			});
+		}
+
+		if current_redist == 0 {
+			break;
+		}
+		total_redistributions += current_redist;
+
+		if let Some(size) = boundary.domain_size() {
+			absorbed_grains += clear_outside(&mut write_array, side_length, size).into();
+			compute::copy_data(&write_array, &mut read_array);
+		} else {
+			next_check -= 1;
+			if next_check == 0 {
+				next_check = maybe_grow_volume(&mut write_array, &mut read_array, &mut counting_array, &mut odometer_array, &mut side_length, &mut origin);
+			} else {
+				compute::copy_data(&write_array, &mut read_array);
+			}
+		}
+	}
+
+	let sand_data: Vec<u8> = write_array.into_iter().map(|value| min(value.into(), 255) as u8).collect();
+	let (sand_data, _, _) = visible_volume(sand_data, side_length, origin, boundary);
+	let (odometer_array, _, _) = visible_volume(odometer_array, side_length, origin, boundary);
+	let (counting_array, side_length, (origin_x, origin_y, origin_z)) = visible_volume(counting_array, side_length, origin, boundary);
+
+	Ok(VolumeResult {
+		initial_configuration: Vec::new(),
+		boundary,
+		background_height,
+		sand_data,
+		count_data: counting_array,
+		total_topples: odometer_array.iter().sum(),
+		odometer_data: odometer_array,
+		side_length,
+		origin_x,
+		origin_y,
+		origin_z,
+
+		total_redistributions,
+		absorbed_grains,
+		total_iterations,
+	})
+}
+
+// lays the sources out in a cube. on the plane it just covers the sources, and a sink domain gets one margin of empty
+// cells around it to catch the grains that leave it
+fn initial_volume<T: Grains>(initial_configuration: &[InitialVoxel], boundary: Boundary, background_height: u32) -> (Vec<T>, usize, (i64, i64, i64)) {
+	let (side_length, origin) = match boundary.domain_size() {
+		None => {
+			let min_corner = |coordinate: fn(&InitialVoxel) -> i64| initial_configuration.iter().map(coordinate).min().unwrap_or(0);
+			let max_corner = |coordinate: fn(&InitialVoxel) -> i64| initial_configuration.iter().map(coordinate).max().unwrap_or(0);
+			let origin = (min_corner(|entry| entry.x), min_corner(|entry| entry.y), min_corner(|entry| entry.z));
+			let extent = max(max_corner(|entry| entry.x) - origin.0, max(max_corner(|entry| entry.y) - origin.1, max_corner(|entry| entry.z) - origin.2));
+			(extent as usize + 1, origin)
+		},
+		Some(size) => {
+			let start = boundary.domain_start() - MARGIN as i64;
+			(compute::next_multiple(size + MARGIN * 2, compute::required_size_multiple(MARGIN)), (start, start, start))
+		},
+	};
+
+	let background = T::from(background_height);
+	let mut data: Vec<T> = vec![T::default(); side_length * side_length * side_length];
+	if let Some(size) = boundary.domain_size() {
+		for z in MARGIN..MARGIN + size {
+			for y in MARGIN..MARGIN + size {
+				let row = (z * side_length + y) * side_length;
+				for cell in &mut data[row + MARGIN..row + MARGIN + size] {
+					*cell = background;
+				}
+			}
+		}
+	}
+	for entry in initial_configuration {
+		let (x, y, z) = ((entry.x - origin.0) as usize, (entry.y - origin.1) as usize, (entry.z - origin.2) as usize);
+		data[(z * side_length + y) * side_length + x] += T::from_u64(entry.value);
+	}
+	(data, side_length, origin)
+}
+
+fn process_slab<T: Grains>(input_data: &[T], output_data: &mut [T], counting_data: &mut [u32], odometer_data: &mut [u64], width: usize, threshold: T) -> u64 {
+	assert_eq!(input_data.len(), output_data.len());
+	assert!(input_data.len() % (width * width) == 0);
+
+	let slice_size = width * width;
+	let neighbors = [1, -1, width as isize, -(width as isize), slice_size as isize, -(slice_size as isize)];
+	let last_slice = input_data.len() / slice_size - MARGIN;
+
+	let mut num_redistributions = 0;
+	for z in MARGIN..last_slice {
+		for y in MARGIN..width - MARGIN {
+			for x in MARGIN..width - MARGIN {
+				let index = (z * width + y) * width + x;
+
+				let val = input_data[index];
+				if val >= threshold {
+					num_redistributions += 1;
+					counting_data[index] += 1;
+
+					let distribute = val / threshold;
+					odometer_data[index] += distribute.into();
+
+					output_data[index] -= distribute * threshold;
+					for &offset in &neighbors {
+						output_data[(index as isize + offset) as usize] += distribute;
+					}
+				}
+			}
+		}
+	}
+	num_redistributions
+}
+
+// empties the margin around a sink domain, and returns the number of grains that were in it. grains only ever leave the
+// domain by one cell, so only that shell of the margin has to be visited, rather than the whole array
+fn clear_outside<T: Grains>(data: &mut [T], side_length: usize, size: usize) -> T {
+	let domain = MARGIN..MARGIN + size;
+	let shell = MARGIN - 1..MARGIN + size + 1;
+	let mut absorbed_grains = T::default();
+	let mut clear = |index: usize| {
+		absorbed_grains += data[index];
+		data[index] = T::default();
+	};
+	for z in shell.clone() {
+		for y in shell.clone() {
+			let row = (z * side_length + y) * side_length;
+			if domain.contains(&z) && domain.contains(&y) {
+				clear(row + MARGIN - 1);
+				clear(row + MARGIN + size);
+			} else {
+				for x in shell.clone() {
+					clear(row + x);
+				}
+			}
+		}
+	}
+	absorbed_grains
+}
+
+// like maybe_reallocate in two dimensions: once the pile comes within one margin of a face of the array, it's copied into
+// a larger array with the pile in the middle. returns the number of iterations until the next check
+fn maybe_grow_volume<T: Grains>(main_array: &mut Vec<T>, secondary_array: &mut Vec<T>, counting_array: &mut Vec<u32>, odometer_array: &mut Vec<u64>, side_length: &mut usize, origin: &mut (i64, i64, i64)) -> usize {
+	let side = *side_length;
+	let mut low = [side; 3];
+	let mut high = [0; 3];
+	for (index, (&value, &count)) in main_array.iter().zip(counting_array.iter()).enumerate() {
+		if value != T::default() || count != 0 {
+			let position = [index % side, index / side % side, index / (side * side)];
+			for axis in 0..3 {
+				low[axis] = min(low[axis], position[axis]);
+				high[axis] = max(high[axis], position[axis]);
+			}
+		}
+	}
+	if low[0] > high[0] {
+		// nothing to grow around. the array may be too small to copy in parallel
+		secondary_array.copy_from_slice(main_array);
+		return usize::max_value();
+	}
+
+	let closest = (0..3).map(|axis| min(low[axis], side - high[axis] - 1)).min().unwrap_or(0);
+	if closest > MARGIN {
+		compute::copy_data(main_array, secondary_array);
+		// the pile grows by at most one margin per iteration
+		return max(1, closest / (MARGIN * 2));
+	}
+
+	const MIN_SIZE: usize = 32;
+	let new_side = compute::next_multiple(max(MIN_SIZE, side + MARGIN * 8), compute::required_size_multiple(MARGIN));
+	let size = [high[0] - low[0] + 1, high[1] - low[1] + 1, high[2] - low[2] + 1];
+	let begin = [new_side / 2 - size[0] / 2, new_side / 2 - size[1] / 2, new_side / 2 - size[2] / 2];
+
+	fn move_cells<V: Copy + Default>(old: &[V], side: usize, new_side: usize, low: [usize; 3], size: [usize; 3], begin: [usize; 3]) -> Vec<V> {
+		let mut new = vec![V::default(); new_side * new_side * new_side];
+		for z in 0..size[2] {
+			for y in 0..size[1] {
+				let old_row = ((low[2] + z) * side + low[1] + y) * side + low[0];
+				let new_row = ((begin[2] + z) * new_side + begin[1] + y) * new_side + begin[0];
+				new[new_row..new_row + size[0]].copy_from_slice(&old[old_row..old_row + size[0]]);
+			}
+		}
+		new
+	}
+
+	*main_array = move_cells(main_array, side, new_side, low, size, begin);
+	*secondary_array = main_array.clone();
+	*counting_array = move_cells(counting_array, side, new_side, low, size, begin);
+	*odometer_array = move_cells(odometer_array, side, new_side, low, size, begin);
+	*origin = (
+		origin.0 + low[0] as i64 - begin[0] as i64,
+		origin.1 + low[1] as i64 - begin[1] as i64,
+		origin.2 + low[2] as i64 - begin[2] as i64,
+	);
+	*side_length = new_side;
+
+	max(1, (new_side - side) / (MARGIN * 4))
+}
+
+// the domain of a sink boundary without its margin, or the whole array on the plane
+fn visible_volume<V: Copy>(data: Vec<V>, side_length: usize, origin: (i64, i64, i64), boundary: Boundary) -> (Vec<V>, usize, (i64, i64, i64)) {
+	match boundary.domain_size() {
+		None => (data, side_length, origin),
+		Some(size) => {
+			let mut cropped = Vec::with_capacity(size * size * size);
+			for z in MARGIN..MARGIN + size {
+				for y in MARGIN..MARGIN + size {
+					let row = (z * side_length + y) * side_length;
+					cropped.extend_from_slice(&data[row + MARGIN..row + MARGIN + size]);
+				}
+			}
+			let offset = MARGIN as i64;
+			(cropped, size, (origin.0 + offset, origin.1 + offset, origin.2 + offset))
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sink_keeps_or_absorbs_every_grain() {
+		let sources = [InitialVoxel { x: 0, y: 0, z: 0, value: 5000 }, InitialVoxel { x: 3, y: -2, z: 1, value: 700 }];
+		for &(size, background_height) in &[(9, 2), (10, 5), (1, 0)] {
+			let sources: Vec<InitialVoxel> = sources.iter().filter(|entry| in_domain(Boundary::Sink { size }, entry.x, entry.y, entry.z)).cloned().collect();
+			let result = run_cubic(&sources, Boundary::Sink { size }, background_height, None).unwrap();
+			let input = sources.iter().map(|entry| entry.value).sum::<u64>() + (size * size * size) as u64 * u64::from(background_height);
+			let remaining: u64 = result.sand_data.iter().map(|&value| u64::from(value)).sum();
+			assert!(result.absorbed_grains > 0);
+			assert_eq!(remaining + result.absorbed_grains, input, "size {}", size);
+		}
+	}
+}
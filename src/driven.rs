@@ -0,0 +1,380 @@
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::sync::Arc;
+
+use crate::common::{Boundary, FractalResult, Model, Symmetry, ToppleRule};
+use crate::compute::{ComputeError, ComputeMonitor, Progress};
+use crate::random::Random;
+
+// where each grain is dropped in driven mode
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DropSite {
+	Fixed { x: i64, y: i64 },
+	// a uniformly random cell of the domain
+	Random,
+}
+
+#[derive(Clone, Debug)]
+pub struct DrivenSettings {
+	pub drops: usize,
+	// drops made before recording starts, so that the pile has time to reach its critical state
+	pub warmup: usize,
+	pub site: DropSite,
+	pub seed: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Avalanche {
+	pub drop_x: i64,
+	pub drop_y: i64,
+	// number of topples
+	pub size: u64,
+	// number of distinct cells that toppled
+	pub area: u64,
+	// number of rounds of parallel topples
+	pub duration: u64,
+	// largest distance from the drop site to a cell that toppled
+	pub radius: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct HistogramBin {
+	pub lower: f64,
+	pub upper: f64,
+	pub count: u64,
+	// fraction of the avalanches in this bin, per unit of bin width
+	pub density: f64,
+}
+
+// density ≈ amplitude · value^-exponent
+#[derive(Clone, Copy, Debug)]
+pub struct PowerLawFit {
+	pub exponent: f64,
+	pub amplitude: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Distribution {
+	pub quantity: &'static str,
+	// logarithmic bins, each twice as wide as the one before
+	pub bins: Vec<HistogramBin>,
+	pub fit: Option<PowerLawFit>,
+}
+
+#[derive(Debug)]
+pub struct DrivenResult {
+	// only the avalanches after the warmup, including the drops that didn't topple anything
+	pub avalanches: Vec<Avalanche>,
+	// the pile after the last drop. its counts and odometer only cover the recorded avalanches
+	pub final_state: Arc<FractalResult>,
+}
+
+impl DrivenResult {
+	// distributions of the size, area, duration and radius of the avalanches that toppled at least one cell
+	pub fn distributions(&self) -> Vec<Distribution> {
+		let quantities: [(&'static str, fn(&Avalanche) -> f64); 4] = [
+			("size", |avalanche| avalanche.size as f64),
+			("area", |avalanche| avalanche.area as f64),
+			("duration", |avalanche| avalanche.duration as f64),
+			("radius", |avalanche| avalanche.radius),
+		];
+
+		quantities.iter().map(|&(quantity, value)| {
+			let values: Vec<f64> = self.avalanches.iter().filter(|avalanche| avalanche.size > 0).map(value).collect();
+			let bins = logarithmic_histogram(&values);
+			let fit = fit_power_law(&bins);
+			Distribution { quantity, bins, fit }
+		}).collect()
+	}
+
+	pub fn write_avalanches_csv(&self, path: &str) -> io::Result<()> {
+		let mut file = BufWriter::new(fs::File::create(path)?);
+		writeln!(file, "drop,x,y,size,area,duration,radius")?;
+		for (index, avalanche) in self.avalanches.iter().enumerate() {
+			writeln!(file, "{},{},{},{},{},{},{}", index, avalanche.drop_x, avalanche.drop_y, avalanche.size, avalanche.area, avalanche.duration, avalanche.radius)?;
+		}
+		file.flush()
+	}
+
+	pub fn write_distributions_csv(&self, path: &str) -> io::Result<()> {
+		let mut file = BufWriter::new(fs::File::create(path)?);
+		writeln!(file, "quantity,bin_lower,bin_upper,count,density,fit_exponent,fit_amplitude,fit_density")?;
+		for distribution in self.distributions() {
+			for bin in &distribution.bins {
+				match distribution.fit {
+					Some(fit) => writeln!(file, "{},{},{},{},{},{},{},{}", distribution.quantity, bin.lower, bin.upper, bin.count, bin.density,
+						fit.exponent, fit.amplitude, fit.amplitude * bin_center(bin).powf(-fit.exponent))?,
+					None => writeln!(file, "{},{},{},{},{},,,", distribution.quantity, bin.lower, bin.upper, bin.count, bin.density)?,
+				}
+			}
+		}
+		file.flush()
+	}
+}
+
+pub fn bin_center(bin: &HistogramBin) -> f64 {
+	(bin.lower * bin.upper).sqrt()
+}
+
+// bins values of at least 1 into [1, 2), [2, 4), [4, 8), ...
+fn logarithmic_histogram(values: &[f64]) -> Vec<HistogramBin> {
+	let values: Vec<f64> = values.iter().cloned().filter(|&value| value >= 1.0).collect();
+	let largest = values.iter().cloned().fold(0.0, f64::max);
+	if values.is_empty() {
+		return Vec::new();
+	}
+
+	let num_bins = largest.log2().floor() as usize + 1;
+	let mut counts = vec![0u64; num_bins];
+	for value in &values {
+		counts[std::cmp::min(value.log2().floor() as usize, num_bins - 1)] += 1;
+	}
+
+	counts.iter().enumerate().map(|(index, &count)| {
+		let lower = 2f64.powi(index as i32);
+		let upper = lower * 2.0;
+		HistogramBin { lower, upper, count, density: count as f64 / (values.len() as f64 * (upper - lower)) }
+	}).collect()
+}
+
+// least squares fit of a line through the non-empty bins on a log-log scale
+fn fit_power_law(bins: &[HistogramBin]) -> Option<PowerLawFit> {
+	let points: Vec<(f64, f64)> = bins.iter().filter(|bin| bin.count > 0).map(|bin| (bin_center(bin).ln(), bin.density.ln())).collect();
+	if points.len() < 2 {
+		return None;
+	}
+
+	let n = points.len() as f64;
+	let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+	let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+	let covariance: f64 = points.iter().map(|&(x, y)| (x - mean_x) * (y - mean_y)).sum();
+	let variance: f64 = points.iter().map(|&(x, _)| (x - mean_x) * (x - mean_x)).sum();
+
+	let slope = covariance / variance;
+	Some(PowerLawFit { exponent: -slope, amplitude: (mean_y - slope * mean_x).exp() })
+}
+
+// drops grains one at a time onto a domain with a sink boundary, and lets each avalanche die out before the next drop
+pub fn run_driven(topple_rule: &ToppleRule, boundary: Boundary, background_height: u32, settings: &DrivenSettings, monitor: Option<&ComputeMonitor>) -> Result<DrivenResult, ComputeError> {
+	let size = match boundary {
+		Boundary::Sink { size } => size,
+		_ => return Err(ComputeError::InvalidParameter("driven mode needs a sink boundary, so that grains can leave the domain".into())),
+	};
+	if let DropSite::Fixed { x, y } = settings.site {
+		if !boundary.contains(x, y) {
+			return Err(ComputeError::InvalidParameter(format!("drop site ({}, {}) is outside the domain", x, y)));
+		}
+	}
+
+	let mut pile = DrivenPile::new(topple_rule, boundary, background_height);
+	let mut random = Random::new(settings.seed);
+
+	// a background at or above the threshold has to settle before the first drop
+	let unstable: Vec<usize> = (0..size * size).filter(|&index| pile.grains[index] >= u64::from(topple_rule.threshold)).collect();
+	pile.relax(unstable, 0, 0, false);
+
+	let mut avalanches = Vec::with_capacity(settings.drops);
+	for drop in 0..settings.warmup + settings.drops {
+		if monitor.map_or(false, |monitor| monitor.is_cancelled()) {
+			return Err(ComputeError::Cancelled);
+		}
+
+		let (x, y) = match settings.site {
+			DropSite::Fixed { x, y } => (x, y),
+			DropSite::Random => (pile.start + random.below(size as u64) as i64, pile.start + random.below(size as u64) as i64),
+		};
+		let index = (y - pile.start) as usize * size + (x - pile.start) as usize;
+
+		pile.grains[index] += 1;
+		let recording = drop >= settings.warmup;
+		let avalanche = if pile.grains[index] >= u64::from(topple_rule.threshold) {
+			pile.relax(vec![index], x, y, recording)
+		} else {
+			Avalanche { drop_x: x, drop_y: y, size: 0, area: 0, duration: 0, radius: 0.0 }
+		};
+		if recording {
+			avalanches.push(avalanche);
+		}
+
+		if let Some(monitor) = monitor {
+			if drop % 1024 == 0 {
+				monitor.report(Progress {
+					iterations: drop,
+					side_length: size,
+					redistributions: avalanches.last().map_or(0, |avalanche| avalanche.size),
+					estimated_completion: drop as f64 / (settings.warmup + settings.drops) as f64,
+				});
+			}
+		}
+	}
+
+	if let Some(monitor) = monitor {
+		monitor.report(Progress {
+			iterations: settings.warmup + settings.drops,
+			side_length: size,
+			redistributions: 0,
+			estimated_completion: 1.0,
+		});
+	}
+
+	let final_state = FractalResult {
+		initial_configuration: Vec::new(),
+		topple_rule: topple_rule.clone(),
+		model: Model::Abelian,
+		symmetry: Symmetry::None,
+		boundary,
+		background_height,
+		sand_data: pile.grains.iter().map(|&value| std::cmp::min(value, 255) as u8).collect(),
+		mass_data: Vec::new(),
+		rotor_data: Vec::new(),
+		total_redistributions: pile.counts.iter().map(|&count| u64::from(count)).sum(),
+		count_data: pile.counts,
+		odometer_data: pile.odometer,
+		odometer_mass: Vec::new(),
+		side_length: size,
+		origin_x: pile.start,
+		origin_y: pile.start,
+
+		total_topples: avalanches.iter().map(|avalanche| avalanche.size).sum(),
+		absorbed_grains: pile.absorbed_grains,
+		absorbed_mass: 0.0,
+		total_iterations: settings.warmup + settings.drops,
+	};
+
+	Ok(DrivenResult { avalanches, final_state: Arc::new(final_state) })
+}
+
+struct DrivenPile<'a> {
+	topple_rule: &'a ToppleRule,
+	size: usize,
+	// world coordinate of the first row and column
+	start: i64,
+	grains: Vec<u64>,
+	counts: Vec<u32>,
+	odometer: Vec<u64>,
+	absorbed_grains: u64,
+	// the avalanche each cell last toppled in, and the round it was last queued in, so that neither has to be cleared
+	toppled_in: Vec<u64>,
+	queued_in: Vec<u64>,
+	avalanche_id: u64,
+	round_id: u64,
+}
+
+impl<'a> DrivenPile<'a> {
+	fn new(topple_rule: &'a ToppleRule, boundary: Boundary, background_height: u32) -> Self {
+		let size = boundary.domain_size().unwrap();
+		Self {
+			topple_rule,
+			size,
+			start: boundary.domain_start(),
+			grains: vec![u64::from(background_height); size * size],
+			counts: vec![0; size * size],
+			odometer: vec![0; size * size],
+			absorbed_grains: 0,
+			toppled_in: vec![0; size * size],
+			queued_in: vec![0; size * size],
+			avalanche_id: 0,
+			round_id: 0,
+		}
+	}
+
+	// topples the unstable cells in rounds, where every cell that's unstable at the start of a round topples during it
+	fn relax(&mut self, mut current: Vec<usize>, drop_x: i64, drop_y: i64, recording: bool) -> Avalanche {
+		let threshold = u64::from(self.topple_rule.threshold);
+		self.avalanche_id += 1;
+
+		let mut avalanche = Avalanche { drop_x, drop_y, size: 0, area: 0, duration: 0, radius: 0.0 };
+		let mut topples: Vec<(usize, u64)> = Vec::new();
+		let mut next = Vec::new();
+		while !current.is_empty() {
+			topples.clear();
+			topples.extend(current.iter().map(|&index| (index, self.grains[index] / threshold)).filter(|&(_, times)| times > 0));
+			if topples.is_empty() {
+				break;
+			}
+
+			self.round_id += 1;
+			avalanche.duration += 1;
+			for &(index, times) in &topples {
+				self.grains[index] -= times * threshold;
+				avalanche.size += times;
+				if recording {
+					self.counts[index] = self.counts[index].saturating_add(1);
+					self.odometer[index] += times;
+				}
+				if self.toppled_in[index] != self.avalanche_id {
+					self.toppled_in[index] = self.avalanche_id;
+					avalanche.area += 1;
+
+					let (x, y) = self.world_position(index);
+					let distance = (((x - drop_x) * (x - drop_x) + (y - drop_y) * (y - drop_y)) as f64).sqrt();
+					avalanche.radius = avalanche.radius.max(distance);
+				}
+			}
+
+			next.clear();
+			for &(index, times) in &topples {
+				let (x, y) = self.world_position(index);
+				for neighbor in self.topple_rule.kernel_for(x, y) {
+					let amount = times * u64::from(neighbor.amount);
+					let neighbor_x = x + neighbor.dx as i64 - self.start;
+					let neighbor_y = y + neighbor.dy as i64 - self.start;
+					if neighbor_x < 0 || neighbor_y < 0 || neighbor_x >= self.size as i64 || neighbor_y >= self.size as i64 {
+						self.absorbed_grains += amount;
+						continue;
+					}
+
+					let neighbor_index = neighbor_y as usize * self.size + neighbor_x as usize;
+					self.grains[neighbor_index] += amount;
+					if self.grains[neighbor_index] >= threshold && self.queued_in[neighbor_index] != self.round_id {
+						self.queued_in[neighbor_index] = self.round_id;
+						next.push(neighbor_index);
+					}
+				}
+			}
+			std::mem::swap(&mut current, &mut next);
+		}
+		avalanche
+	}
+
+	fn world_position(&self, index: usize) -> (i64, i64) {
+		(self.start + (index % self.size) as i64, self.start + (index / self.size) as i64)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fit_recovers_a_power_law() {
+		let bins: Vec<HistogramBin> = (0..10).map(|index| {
+			let lower = 2f64.powi(index);
+			let mut bin = HistogramBin { lower, upper: lower * 2.0, count: 1, density: 0.0 };
+			bin.density = 3.0 * bin_center(&bin).powf(-1.5);
+			bin
+		}).collect();
+		let fit = fit_power_law(&bins).unwrap();
+		assert!((fit.exponent - 1.5).abs() < 1e-9 && (fit.amplitude - 3.0).abs() < 1e-9);
+
+		// the quantiles of a density of (exponent - 1)·x^-exponent above 1. averaged over a bin that doubles, the density is
+		// a fixed multiple of its value at the bin's center, so the bins follow the same power law
+		let values: Vec<f64> = (0..100000).map(|index| (1.0 - (index as f64 + 0.5) / 100000.0).powf(-1.0 / 1.2)).collect();
+		let fit = fit_power_law(&logarithmic_histogram(&values)).unwrap();
+		assert!((fit.exponent - 2.2).abs() < 0.1, "exponent {}", fit.exponent);
+
+		assert!(fit_power_law(&bins[..1]).is_none());
+	}
+
+	#[test]
+	fn drops_are_kept_or_absorbed() {
+		for &(site, background_height) in &[(DropSite::Random, 0), (DropSite::Fixed { x: 2, y: -1 }, 3), (DropSite::Random, 5)] {
+			let settings = DrivenSettings { drops: 3000, warmup: 500, site, seed: 7 };
+			let result = run_driven(&ToppleRule::von_neumann(), Boundary::Sink { size: 15 }, background_height, &settings, None).unwrap();
+			let remaining: u64 = result.final_state.sand_data.iter().map(|&value| u64::from(value)).sum();
+			assert_eq!(result.avalanches.len(), 3000);
+			assert!(result.final_state.absorbed_grains > 0);
+			assert_eq!(remaining + result.final_state.absorbed_grains, 3500 + 15 * 15 * u64::from(background_height));
+		}
+	}
+}
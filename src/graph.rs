@@ -0,0 +1,488 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use serde_derive::{Serialize, Deserialize};
+
+use crate::compute::{ComputeError, ComputeMonitor, Progress};
+
+// a graph to play chip-firing on. a vertex fires once it holds at least as many chips as its out-degree, sending one chip
+// along each outgoing edge. the sink never fires, and neither do vertices without outgoing edges, which just keep their chips
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Graph {
+	// the name of each vertex, as it appeared in the file or as given by the generator
+	pub labels: Vec<String>,
+	// the outgoing edges of each vertex, with their multiplicity. undirected edges are stored in both directions
+	pub edges: Vec<Vec<(usize, u32)>>,
+	pub directed: bool,
+	pub sink: Option<usize>,
+}
+
+impl Graph {
+	fn empty(directed: bool) -> Self {
+		Self { labels: Vec::new(), edges: Vec::new(), directed, sink: None }
+	}
+
+	pub fn vertex_count(&self) -> usize {
+		self.labels.len()
+	}
+
+	pub fn edge_count(&self) -> usize {
+		// undirected edges are counted from their lower end, and loops are only stored once
+		self.edges.iter().enumerate().map(|(from, edges)| {
+			edges.iter().filter(|&&(to, _)| self.directed || from <= to).map(|&(_, multiplicity)| multiplicity as usize).sum::<usize>()
+		}).sum()
+	}
+
+	pub fn out_degree(&self, vertex: usize) -> u64 {
+		self.edges[vertex].iter().map(|&(_, multiplicity)| u64::from(multiplicity)).sum()
+	}
+
+	pub fn vertex(&self, label: &str) -> Option<usize> {
+		self.labels.iter().position(|existing| existing == label)
+	}
+
+	pub fn with_sink(mut self, label: &str) -> Result<Self, ComputeError> {
+		self.sink = Some(self.vertex(label).ok_or_else(|| ComputeError::InvalidParameter(format!("there is no vertex '{}' to use as the sink", label)))?);
+		Ok(self)
+	}
+
+	fn add_vertex(&mut self, label: String) -> usize {
+		self.labels.push(label);
+		self.edges.push(Vec::new());
+		self.labels.len() - 1
+	}
+
+	// adds `multiplicity` edges from one vertex to the other, and back again if the graph is undirected. the generators add
+	// every edge once, so the arcs are pushed without looking for an existing one. on a torus of width 2, the two edges
+	// between a pair of vertices are stored separately, which fires the same as one edge of multiplicity 2
+	fn add_edge(&mut self, from: usize, to: usize, multiplicity: u32) {
+		self.edges[from].push((to, multiplicity));
+		if !self.directed && from != to {
+			self.edges[to].push((from, multiplicity));
+		}
+	}
+
+	// adds edges like add_edge, but adds to the multiplicity of an edge that's already there. `positions` holds where each
+	// arc is in the edge list of the vertex it leaves
+	fn merge_edge(&mut self, positions: &mut Vec<HashMap<usize, usize>>, from: usize, to: usize, multiplicity: u32) {
+		positions.resize_with(self.vertex_count(), HashMap::new);
+		let edges = &mut self.edges;
+		let mut add_arc = |from: usize, to: usize| {
+			match positions[from].get(&to) {
+				Some(&position) => edges[from][position].1 += multiplicity,
+				None => {
+					positions[from].insert(to, edges[from].len());
+					edges[from].push((to, multiplicity));
+				},
+			}
+		};
+		add_arc(from, to);
+		if !self.directed && from != to {
+			add_arc(to, from);
+		}
+	}
+}
+
+// the most vertices the generators may build
+pub const MAX_GRAPH_VERTICES: usize = 1 << 24;
+
+// reads a graph from a text file. every line is either an edge, given as "u v" or "u v multiplicity", or the adjacency list
+// of a vertex, given as "u: v w ...". vertices can have any names without whitespace, and lines starting with # are
+// skipped. an adjacency list only adds edges leaving its vertex, so in an undirected graph every edge has to be listed
+// from both ends
+pub fn load_graph(path: &str, directed: bool) -> Result<Graph, ComputeError> {
+	let text = fs::read_to_string(path).map_err(|error| ComputeError::Io(format!("couldn't read graph file '{}': {}", path, error)))?;
+	parse_graph(&text, directed)
+}
+
+pub fn parse_graph(text: &str, directed: bool) -> Result<Graph, ComputeError> {
+	let mut graph = Graph::empty(directed);
+	let mut indices: HashMap<String, usize> = HashMap::new();
+	let mut vertex = |graph: &mut Graph, label: &str| -> usize {
+		*indices.entry(label.to_string()).or_insert_with(|| graph.add_vertex(label.to_string()))
+	};
+	// adjacency lists of undirected graphs are added as arcs, and checked for symmetry at the end
+	let mut arcs: Vec<(usize, usize)> = Vec::new();
+	// an edge can be listed several times
+	let mut positions: Vec<HashMap<usize, usize>> = Vec::new();
+
+	for (line_number, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let invalid = |message: &str| ComputeError::InvalidParameter(format!("line {} of the graph: {}", line_number + 1, message));
+
+		if let Some(colon) = line.find(':') {
+			let from = line[..colon].trim();
+			if from.is_empty() || from.contains(char::is_whitespace) {
+				return Err(invalid("an adjacency list should start with a single vertex"));
+			}
+			let from = vertex(&mut graph, from);
+			for to in line[colon + 1..].split_whitespace() {
+				let to = vertex(&mut graph, to);
+				arcs.push((from, to));
+			}
+		} else {
+			let parts: Vec<&str> = line.split_whitespace().collect();
+			let multiplicity = match parts.len() {
+				2 => 1,
+				3 => parts[2].parse().map_err(|_| invalid("couldn't parse the multiplicity"))?,
+				_ => return Err(invalid("an edge should be 'u v' or 'u v multiplicity'")),
+			};
+			if multiplicity == 0 {
+				return Err(invalid("the multiplicity of an edge should be at least 1"));
+			}
+			let from = vertex(&mut graph, parts[0]);
+			let to = vertex(&mut graph, parts[1]);
+			graph.merge_edge(&mut positions, from, to, multiplicity);
+		}
+	}
+
+	if directed {
+		for (from, to) in arcs {
+			graph.merge_edge(&mut positions, from, to, 1);
+		}
+	} else {
+		let mut unmatched: HashMap<(usize, usize), i64> = HashMap::new();
+		for &(from, to) in &arcs {
+			*unmatched.entry(min_max(from, to)).or_insert(0) += if from <= to { 1 } else { -1 };
+		}
+		if let Some((&(from, to), _)) = unmatched.iter().find(|&(&(from, to), &balance)| from != to && balance != 0) {
+			return Err(ComputeError::InvalidParameter(format!("the adjacency lists of an undirected graph should list the edge between '{}' and '{}' from both ends", graph.labels[from], graph.labels[to])));
+		}
+		// every edge was listed twice, except for loops
+		for (from, to) in arcs {
+			if from <= to {
+				graph.merge_edge(&mut positions, from, to, 1);
+			}
+		}
+	}
+
+	Ok(graph)
+}
+
+fn min_max(a: usize, b: usize) -> (usize, usize) {
+	if a <= b { (a, b) } else { (b, a) }
+}
+
+// an n x m grid that wraps around in both directions. vertices are named "x,y"
+pub fn torus(width: usize, height: usize) -> Graph {
+	let mut graph = Graph::empty(false);
+	for y in 0..height {
+		for x in 0..width {
+			graph.add_vertex(format!("{},{}", x, y));
+		}
+	}
+	for y in 0..height {
+		for x in 0..width {
+			let vertex = y * width + x;
+			graph.add_edge(vertex, y * width + (x + 1) % width, 1);
+			graph.add_edge(vertex, ((y + 1) % height) * width + x, 1);
+		}
+	}
+	graph
+}
+
+pub fn complete(n: usize) -> Graph {
+	let mut graph = Graph::empty(false);
+	for vertex in 0..n {
+		graph.add_vertex(vertex.to_string());
+	}
+	for from in 0..n {
+		for to in from + 1..n {
+			graph.add_edge(from, to, 1);
+		}
+	}
+	graph
+}
+
+// a tree where every vertex above the given depth has `branching` children. the vertices are numbered level by level,
+// so the root is "0"
+pub fn tree(branching: usize, depth: usize) -> Graph {
+	let mut graph = Graph::empty(false);
+	graph.add_vertex("0".into());
+	let mut level = vec![0];
+	for _ in 0..depth {
+		let mut next_level = Vec::with_capacity(level.len() * branching);
+		for &parent in &level {
+			for _ in 0..branching {
+				let child = graph.add_vertex(graph.vertex_count().to_string());
+				graph.add_edge(parent, child, 1);
+				next_level.push(child);
+			}
+		}
+		level = next_level;
+	}
+	graph
+}
+
+// the Sierpinski gasket graph: a triangle at level 0, and three copies of the previous level joined at their corners
+// after that. vertices are named by their axial coordinates "x,y", with the corners at "0,0", "2^level,0" and "0,2^level"
+pub fn sierpinski(level: usize) -> Graph {
+	let mut graph = Graph::empty(false);
+	let mut indices: HashMap<(usize, usize), usize> = HashMap::new();
+	let mut triangles = vec![(0, 0, 1usize << level)];
+	while let Some((x, y, size)) = triangles.pop() {
+		if size > 1 {
+			let half = size / 2;
+			triangles.extend_from_slice(&[(x, y, half), (x + half, y, half), (x, y + half, half)]);
+			continue;
+		}
+
+		let corners = [(x, y), (x + 1, y), (x, y + 1)];
+		let corners: Vec<usize> = corners.iter().map(|&(x, y)| {
+			*indices.entry((x, y)).or_insert_with(|| graph.add_vertex(format!("{},{}", x, y)))
+		}).collect();
+		graph.add_edge(corners[0], corners[1], 1);
+		graph.add_edge(corners[1], corners[2], 1);
+		graph.add_edge(corners[2], corners[0], 1);
+	}
+	graph
+}
+
+// chips placed on a vertex before stabilizing, like InitialCell on a lattice
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct InitialVertex {
+	pub vertex: usize,
+	pub value: u64,
+}
+
+// the graph counterpart of FractalResult
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GraphResult {
+	pub graph: Graph,
+	pub initial_configuration: Vec<InitialVertex>,
+	// chips every vertex except the sink held before the sources were added
+	pub background_height: u32,
+	// the stable number of chips on each vertex. the sink never keeps any
+	pub chips: Vec<u64>,
+	// the number of times each vertex fired
+	pub firing_counts: Vec<u64>,
+	pub total_firings: u64,
+	// chips that reached the sink
+	pub absorbed_chips: u64,
+}
+
+impl GraphResult {
+	// one line per vertex, with its label, chips and firing count
+	pub fn write_csv(&self, path: &str) -> io::Result<()> {
+		let mut file = BufWriter::new(fs::File::create(path)?);
+		writeln!(file, "vertex,chips,firings")?;
+		for (vertex, label) in self.graph.labels.iter().enumerate() {
+			writeln!(file, "{},{},{}", label, self.chips[vertex], self.firing_counts[vertex])?;
+		}
+		file.flush()
+	}
+}
+
+const REPORT_INTERVAL: u64 = 1 << 16;
+
+// fires vertices from a queue until none can fire. without a sink, a closed part of the graph can keep firing forever.
+// that's the case as soon as every vertex of such a part has fired, so the computation stops there
+pub fn stabilize_graph(graph: Graph, initial_configuration: &[InitialVertex], background_height: u32, monitor: Option<&ComputeMonitor>) -> Result<GraphResult, ComputeError> {
+	let vertex_count = graph.vertex_count();
+	if let Some(entry) = initial_configuration.iter().find(|entry| entry.vertex >= vertex_count || Some(entry.vertex) == graph.sink) {
+		return Err(ComputeError::InvalidParameter(match graph.labels.get(entry.vertex) {
+			Some(label) => format!("chips can't be placed on the sink '{}'", label),
+			None => format!("there is no vertex {}", entry.vertex),
+		}));
+	}
+
+	let degrees: Vec<u64> = (0..vertex_count).map(|vertex| graph.out_degree(vertex)).collect();
+	let fires = |vertex: usize| Some(vertex) != graph.sink && degrees[vertex] > 0;
+
+	let mut chips: Vec<u64> = (0..vertex_count).map(|vertex| if Some(vertex) == graph.sink { 0 } else { u64::from(background_height) }).collect();
+	for entry in initial_configuration {
+		chips[entry.vertex] = chips[entry.vertex].checked_add(entry.value)
+			.ok_or_else(|| ComputeError::InvalidParameter("the number of chips doesn't fit in 64 bits".into()))?;
+	}
+
+	// the closed parts of the graph that the chips can never leave, and how many of their vertices haven't fired yet
+	let components = closed_components(&graph);
+	let mut unfired: Vec<usize> = vec![0; components.count];
+	for vertex in 0..vertex_count {
+		if let Some(component) = components.closed[vertex] {
+			unfired[component] += 1;
+		}
+	}
+
+	let mut firing_counts = vec![0u64; vertex_count];
+	let mut total_firings: u64 = 0;
+	let mut absorbed_chips: u64 = 0;
+	let mut queue: VecDeque<usize> = (0..vertex_count).filter(|&vertex| fires(vertex) && chips[vertex] >= degrees[vertex]).collect();
+	while let Some(vertex) = queue.pop_front() {
+		let times = chips[vertex] / degrees[vertex];
+		chips[vertex] -= times * degrees[vertex];
+		if firing_counts[vertex] == 0 {
+			if let Some(component) = components.closed[vertex] {
+				unfired[component] -= 1;
+				if unfired[component] == 0 {
+					return Err(ComputeError::NeverStabilizes(format!("every vertex that vertex '{}' can reach has fired, so it will keep firing forever", graph.labels[vertex])));
+				}
+			}
+		}
+		firing_counts[vertex] += times;
+		total_firings += times;
+
+		for &(target, multiplicity) in &graph.edges[vertex] {
+			let amount = times * u64::from(multiplicity);
+			if Some(target) == graph.sink {
+				absorbed_chips += amount;
+				continue;
+			}
+			let before = chips[target];
+			chips[target] += amount;
+			// a vertex is queued when it becomes able to fire, and fires as often as it can when it's taken off the queue
+			if fires(target) && before < degrees[target] && chips[target] >= degrees[target] {
+				queue.push_back(target);
+			}
+		}
+
+		if let Some(monitor) = monitor {
+			if total_firings / REPORT_INTERVAL != (total_firings - times) / REPORT_INTERVAL {
+				if monitor.is_cancelled() {
+					return Err(ComputeError::Cancelled);
+				}
+				monitor.report(Progress {
+					iterations: total_firings as usize,
+					side_length: (vertex_count as f64).sqrt() as usize,
+					redistributions: times,
+					estimated_completion: 0.0,
+				});
+			}
+		}
+	}
+
+	Ok(GraphResult {
+		graph,
+		initial_configuration: initial_configuration.to_vec(),
+		background_height,
+		chips,
+		firing_counts,
+		total_firings,
+		absorbed_chips,
+	})
+}
+
+struct ClosedComponents {
+	// the closed strongly connected component each vertex belongs to, if it belongs to one that has more than one vertex
+	// able to fire. chips that reach such a component never leave it
+	closed: Vec<Option<usize>>,
+	count: usize,
+}
+
+// finds the strongly connected components with Kosaraju's algorithm, keeping the ones no edge leaves. the sink's edges
+// don't count, since it never fires
+fn closed_components(graph: &Graph) -> ClosedComponents {
+	let vertex_count = graph.vertex_count();
+	let out_edges = |vertex: usize| -> &[(usize, u32)] { if Some(vertex) == graph.sink { &[] } else { &graph.edges[vertex] } };
+
+	// vertices in order of when their depth-first search finished
+	let mut finished = Vec::with_capacity(vertex_count);
+	let mut visited = vec![false; vertex_count];
+	for start in 0..vertex_count {
+		if visited[start] {
+			continue;
+		}
+		visited[start] = true;
+		let mut stack = vec![(start, 0)];
+		while let Some((vertex, next_edge)) = stack.pop() {
+			match out_edges(vertex).get(next_edge) {
+				Some(&(target, _)) => {
+					stack.push((vertex, next_edge + 1));
+					if !visited[target] {
+						visited[target] = true;
+						stack.push((target, 0));
+					}
+				},
+				None => finished.push(vertex),
+			}
+		}
+	}
+
+	let mut in_edges: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+	for vertex in 0..vertex_count {
+		for &(target, _) in out_edges(vertex) {
+			in_edges[target].push(vertex);
+		}
+	}
+
+	let mut component: Vec<Option<usize>> = vec![None; vertex_count];
+	let mut count = 0;
+	for &start in finished.iter().rev() {
+		if component[start].is_some() {
+			continue;
+		}
+		component[start] = Some(count);
+		let mut stack = vec![start];
+		while let Some(vertex) = stack.pop() {
+			for &source in &in_edges[vertex] {
+				if component[source].is_none() {
+					component[source] = Some(count);
+					stack.push(source);
+				}
+			}
+		}
+		count += 1;
+	}
+	// every vertex has been visited by now
+	let component: Vec<usize> = component.into_iter().map(Option::unwrap).collect();
+
+	let mut is_closed = vec![true; count];
+	let mut firing_vertices = vec![0; count];
+	for vertex in 0..vertex_count {
+		if out_edges(vertex).iter().any(|&(target, _)| component[target] != component[vertex]) {
+			is_closed[component[vertex]] = false;
+		}
+		if Some(vertex) != graph.sink && graph.out_degree(vertex) > 0 {
+			firing_vertices[component[vertex]] += 1;
+		}
+	}
+
+	// a single vertex whose edges all loop back to itself keeps firing too
+	let closed = (0..vertex_count).map(|vertex| {
+		let index = component[vertex];
+		if is_closed[index] && firing_vertices[index] > 0 { Some(index) } else { None }
+	}).collect();
+	ClosedComponents { closed, count }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn rejects_edges_without_multiplicity() {
+		assert!(matches!(parse_graph("a b 0", false), Err(ComputeError::InvalidParameter(_))));
+		assert!(matches!(parse_graph("a b 0\nb c", true), Err(ComputeError::InvalidParameter(_))));
+
+		let graph = parse_graph("a b 2\nb c", false).unwrap();
+		assert_eq!(graph.out_degree(graph.vertex("b").unwrap()), 3);
+	}
+
+	#[test]
+	fn merges_repeated_edges_in_files() {
+		let graph = parse_graph("a b\nb a 2\na: c\nc: a\na: c\nc: a", false).unwrap();
+		let a = graph.vertex("a").unwrap();
+		assert_eq!(graph.edges[a].len(), 2);
+		assert_eq!(graph.out_degree(a), 5);
+		assert_eq!(graph.edge_count(), 5);
+	}
+
+	#[test]
+	fn generators_add_every_edge_once() {
+		let complete = complete(6);
+		assert!((0..6).all(|vertex| complete.edges[vertex].len() == 5));
+		assert_eq!(complete.edge_count(), 15);
+
+		let tree = tree(3, 2);
+		assert_eq!(tree.vertex_count(), 13);
+		assert_eq!(tree.edge_count(), 12);
+
+		// the neighbors on either side are the same vertex
+		let torus = torus(2, 2);
+		assert!((0..4).all(|vertex| torus.out_degree(vertex) == 4));
+		assert_eq!(torus.edge_count(), 8);
+	}
+}
@@ -0,0 +1,300 @@
+use std::fmt;
+use num_bigint::{BigInt, BigUint};
+use num_integer::Integer;
+use num_traits::{One, Signed, Zero};
+
+use crate::common::{Boundary, FractalResult, Model, ToppleRule};
+use crate::compute::{ComputeError, ComputeMonitor, Progress};
+use crate::graph::{Graph, GraphResult};
+
+// the elimination works on the whole matrix, which stays quick up to about this many vertices
+pub const MAX_GROUP_VERTICES: usize = 1600;
+
+// the reduced Laplacian of a finite domain with a sink. row v is what toppling or firing v takes away from each vertex:
+// its threshold from v itself, less what every other vertex is sent. grains sent to the sink are left out
+#[derive(Clone, Debug)]
+pub struct ReducedLaplacian {
+	pub rows: Vec<Vec<i64>>,
+}
+
+impl ReducedLaplacian {
+	// the cells of a size x size domain with a sink boundary, in row order
+	pub fn grid(topple_rule: &ToppleRule, size: usize) -> Result<Self, ComputeError> {
+		check_vertex_count(size * size)?;
+		let start = Boundary::Sink { size }.domain_start();
+		let mut rows = vec![vec![0i64; size * size]; size * size];
+		for y in 0..size {
+			for x in 0..size {
+				let row = &mut rows[y * size + x];
+				row[y * size + x] += i64::from(topple_rule.threshold);
+				for neighbor in topple_rule.kernel_for(x as i64 + start, y as i64 + start) {
+					let target_x = x as i64 + neighbor.dx as i64;
+					let target_y = y as i64 + neighbor.dy as i64;
+					if target_x >= 0 && target_x < size as i64 && target_y >= 0 && target_y < size as i64 {
+						row[target_y as usize * size + target_x as usize] -= i64::from(neighbor.amount);
+					}
+				}
+			}
+		}
+		Ok(Self { rows })
+	}
+
+	// every vertex except the sink, in order
+	pub fn graph(graph: &Graph) -> Result<Self, ComputeError> {
+		let sink = graph.sink.ok_or_else(|| ComputeError::InvalidParameter("the sandpile group is only finite on a graph with a sink".into()))?;
+		let vertex_count = graph.vertex_count() - 1;
+		check_vertex_count(vertex_count)?;
+		let index = |vertex: usize| if vertex < sink { vertex } else { vertex - 1 };
+
+		let mut rows = vec![vec![0i64; vertex_count]; vertex_count];
+		for vertex in (0..graph.vertex_count()).filter(|&vertex| vertex != sink) {
+			let row = &mut rows[index(vertex)];
+			row[index(vertex)] += graph.out_degree(vertex) as i64;
+			for &(target, multiplicity) in graph.edges[vertex].iter().filter(|&&(target, _)| target != sink) {
+				row[index(target)] -= i64::from(multiplicity);
+			}
+		}
+		Ok(Self { rows })
+	}
+
+	pub fn vertex_count(&self) -> usize {
+		self.rows.len()
+	}
+}
+
+fn check_vertex_count(vertex_count: usize) -> Result<(), ComputeError> {
+	if vertex_count > MAX_GROUP_VERTICES {
+		return Err(ComputeError::InvalidParameter(format!("the sandpile group can only be computed for up to {} vertices, not {}", MAX_GROUP_VERTICES, vertex_count)));
+	}
+	Ok(())
+}
+
+// the configurations of a domain up to toppling, which form a finite abelian group
+#[derive(Clone, Debug)]
+pub struct SandpileGroup {
+	// the invariant factors other than 1, each dividing the next. the group is the product of the cyclic groups of
+	// these orders, and it needs as many generators as there are factors
+	pub invariant_factors: Vec<BigUint>,
+	// the product of the invariant factors. by the matrix-tree theorem it's also the number of spanning trees, or of
+	// spanning trees oriented towards the sink on a directed graph
+	pub order: BigUint,
+	pub vertex_count: usize,
+	// the order of the configuration the group was computed for, if there was one
+	pub configuration_order: Option<BigUint>,
+}
+
+impl SandpileGroup {
+	pub fn summary(&self) -> String {
+		let mut summary = format!("Sandpile group {}, of order {} (the number of spanning trees)", self, self.order);
+		if let Some(order) = &self.configuration_order {
+			summary.push_str(&format!(", the configuration has order {}", order));
+		}
+		summary
+	}
+}
+
+impl fmt::Display for SandpileGroup {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if self.invariant_factors.is_empty() {
+			return write!(f, "trivial");
+		}
+		let factors: Vec<String> = self.invariant_factors.iter().map(|factor| format!("Z/{}", factor)).collect();
+		write!(f, "{}", factors.join(" × "))
+	}
+}
+
+// brings the reduced Laplacian into Smith normal form. the group is Z^n modulo the rows of the Laplacian, so only column
+// operations change how a configuration is written in it. the configuration goes along as an extra row that only
+// takes part in those, and its order can be read off against the diagonal at the end
+pub fn sandpile_group(laplacian: &ReducedLaplacian, configuration: Option<&[u64]>, monitor: Option<&ComputeMonitor>) -> Result<SandpileGroup, ComputeError> {
+	let n = laplacian.vertex_count();
+	let mut matrix: Vec<Vec<BigInt>> = laplacian.rows.iter().map(|row| row.iter().map(|&value| BigInt::from(value)).collect()).collect();
+	if let Some(values) = configuration {
+		if values.len() != n {
+			return Err(ComputeError::InvalidParameter(format!("the configuration needs one value for each of the {} vertices", n)));
+		}
+	}
+	let mut configuration: Option<Vec<BigInt>> = configuration.map(|values| values.iter().map(|&value| BigInt::from(value)).collect());
+
+	for k in 0..n {
+		if let Some(monitor) = monitor {
+			if monitor.is_cancelled() {
+				return Err(ComputeError::Cancelled);
+			}
+			monitor.report(Progress {
+				iterations: k,
+				side_length: (n as f64).sqrt() as usize,
+				redistributions: 0,
+				estimated_completion: k as f64 / n as f64,
+			});
+		}
+
+		// the pivot is the smallest entry left in row or column k. every reduction leaves smaller remainders, so this
+		// ends once they all divide evenly
+		loop {
+			let in_column = (k..n).filter(|&i| !matrix[i][k].is_zero()).map(|i| (i, k));
+			let in_row = (k + 1..n).filter(|&j| !matrix[k][j].is_zero()).map(|j| (k, j));
+			let (pivot_row, pivot_column) = match in_column.chain(in_row).min_by(|&(ai, aj), &(bi, bj)| matrix[ai][aj].abs().cmp(&matrix[bi][bj].abs())) {
+				Some(position) => position,
+				// everything left in row and column k is zero, so the matrix is singular
+				None => return Err(ComputeError::InvalidParameter("the sandpile group is infinite, since grains can't reach the sink from every vertex".into())),
+			};
+			if pivot_row != k {
+				matrix.swap(k, pivot_row);
+			}
+			if pivot_column != k {
+				for row in matrix.iter_mut().chain(configuration.iter_mut()) {
+					row.swap(k, pivot_column);
+				}
+			}
+
+			let pivot_entries: Vec<(usize, BigInt)> = (k..n).filter(|&j| !matrix[k][j].is_zero()).map(|j| (j, matrix[k][j].clone())).collect();
+			let pivot = matrix[k][k].clone();
+			let mut column_left = false;
+			for row in matrix[k + 1..].iter_mut() {
+				if row[k].is_zero() {
+					continue;
+				}
+				let quotient = &row[k] / &pivot;
+				for (j, value) in &pivot_entries {
+					row[*j] -= &quotient * value;
+				}
+				column_left |= !row[k].is_zero();
+			}
+			if column_left {
+				continue;
+			}
+
+			// column k is clear below the pivot, so these column operations only touch row k and the configuration
+			let mut row_left = false;
+			for j in k + 1..n {
+				if matrix[k][j].is_zero() {
+					continue;
+				}
+				let quotient = &matrix[k][j] / &pivot;
+				matrix[k][j] -= &quotient * &pivot;
+				if let Some(values) = &mut configuration {
+					let change = &quotient * &values[k];
+					values[j] -= change;
+				}
+				row_left |= !matrix[k][j].is_zero();
+			}
+			if !row_left {
+				break;
+			}
+		}
+	}
+
+	let diagonal: Vec<BigUint> = (0..n).map(|i| matrix[i][i].abs().to_biguint().unwrap()).collect();
+	let order = diagonal.iter().fold(BigUint::one(), |product, value| product * value);
+
+	// the configuration is a multiple of the rows exactly when each entry is a multiple of the diagonal entry it's paired with
+	let configuration_order = configuration.map(|values| {
+		values.iter().zip(&diagonal).fold(BigUint::one(), |order, (value, diagonal)| {
+			let value = value.abs().to_biguint().unwrap();
+			order.lcm(&(diagonal / diagonal.gcd(&value)))
+		})
+	});
+
+	Ok(SandpileGroup {
+		invariant_factors: invariant_factors(diagonal),
+		order,
+		vertex_count: n,
+		configuration_order,
+	})
+}
+
+// a diagonal matrix with entries a and b is equivalent to one with entries gcd(a, b) and lcm(a, b). doing that for every
+// pair leaves each entry dividing the next
+fn invariant_factors(diagonal: Vec<BigUint>) -> Vec<BigUint> {
+	let mut factors: Vec<BigUint> = diagonal.into_iter().filter(|value| !value.is_one()).collect();
+	for i in 0..factors.len() {
+		for j in i + 1..factors.len() {
+			let gcd = factors[i].gcd(&factors[j]);
+			let lcm = &factors[i] / &gcd * &factors[j];
+			factors[i] = gcd;
+			factors[j] = lcm;
+		}
+	}
+	factors.retain(|value| !value.is_one());
+	factors
+}
+
+// the group of a result's sink-boundary domain, along with the order of the result's sand_data in it
+pub fn grid_group(result: &FractalResult, monitor: Option<&ComputeMonitor>) -> Result<SandpileGroup, ComputeError> {
+	let size = match result.boundary {
+		Boundary::Sink { size } => size,
+		_ => return Err(ComputeError::InvalidParameter("the sandpile group is only finite on a domain with a sink boundary".into())),
+	};
+	if result.model != Model::Abelian {
+		return Err(ComputeError::InvalidParameter(format!("the {} model has no sandpile group", result.model.name())));
+	}
+
+	let laplacian = ReducedLaplacian::grid(&result.topple_rule, size)?;
+	let start = result.boundary.domain_start();
+	let mut configuration = Vec::with_capacity(size * size);
+	for y in start..start + size as i64 {
+		for x in start..start + size as i64 {
+			configuration.push(match result.world_to_index(x, y) {
+				Some(index) => u64::from(result.sand_data[index]),
+				None => u64::from(result.background_height),
+			});
+		}
+	}
+	sandpile_group(&laplacian, Some(&configuration), monitor)
+}
+
+// the group of a graph with a sink, along with the order of the result's chips in it
+pub fn graph_group(result: &GraphResult, monitor: Option<&ComputeMonitor>) -> Result<SandpileGroup, ComputeError> {
+	let laplacian = ReducedLaplacian::graph(&result.graph)?;
+	let mut configuration = result.chips.clone();
+	if let Some(sink) = result.graph.sink {
+		configuration.remove(sink);
+	}
+	sandpile_group(&laplacian, Some(&configuration), monitor)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::Symmetry;
+	use crate::compute::{identity, Engine};
+	use crate::compute::tests::stabilize_with;
+	use crate::graph::complete;
+
+	fn factors(group: &SandpileGroup) -> Vec<u64> {
+		group.invariant_factors.iter().map(|factor| factor.to_string().parse().unwrap()).collect()
+	}
+
+	#[test]
+	fn groups_of_small_grids() {
+		let two = sandpile_group(&ReducedLaplacian::grid(&ToppleRule::von_neumann(), 2).unwrap(), None, None).unwrap();
+		assert_eq!(factors(&two), vec![8, 24]);
+		assert_eq!(two.order, BigUint::from(192u32));
+
+		let three = sandpile_group(&ReducedLaplacian::grid(&ToppleRule::von_neumann(), 3).unwrap(), None, None).unwrap();
+		assert_eq!(factors(&three), vec![4, 112, 224]);
+		assert_eq!(three.order, BigUint::from(100352u32));
+	}
+
+	#[test]
+	fn group_of_a_complete_graph() {
+		// n^(n - 2) spanning trees, and Z/n^(n - 2) once the sink is removed
+		let graph = complete(5).with_sink("0").unwrap();
+		let group = sandpile_group(&ReducedLaplacian::graph(&graph).unwrap(), None, None).unwrap();
+		assert_eq!(factors(&group), vec![5, 5, 5]);
+		assert_eq!(group.order, BigUint::from(125u32));
+	}
+
+	#[test]
+	fn orders_of_configurations() {
+		let group = grid_group(&identity(3).unwrap(), None).unwrap();
+		assert_eq!(group.configuration_order, Some(BigUint::one()));
+
+		// the maximal stable configuration is recurrent, but isn't the identity
+		let maximal = stabilize_with::<u32>(&[], &ToppleRule::von_neumann(), Boundary::Sink { size: 3 }, Symmetry::None, 3, Engine::Dense).unwrap();
+		let order = grid_group(&maximal, None).unwrap().configuration_order.unwrap();
+		assert!(order > BigUint::one());
+		assert!((BigUint::from(224u32) % &order).is_zero());
+	}
+}
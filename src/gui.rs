@@ -1,13 +1,22 @@
 
-use crate::common::FractalResult;
+use crate::common::{Boundary, DataField, FractalResult, Lattice};
 use crate::compute;
+use crate::driven::DrivenResult;
+use crate::cubic::{Axis, VolumeResult, VolumeView};
+use crate::graph::GraphResult;
+use crate::group::SandpileGroup;
+use crate::burning::Burning;
+use crate::periodic::{Orbit, OrbitFrame};
+use crate::reference::Verification;
 use crate::render;
 use crate::render::ColorChannel;
 use std::sync::Arc;
+use std::future::Future;
+use std::time::Duration;
 
 use iced::{
     button, image, slider, text_input, 
-    Application, Background, Button, Color, Column, Command, Container, Element, HorizontalAlignment, Image, Length, Row, Slider, Text, TextInput,
+    Application, Background, Button, Checkbox, Color, Column, Command, Container, Element, HorizontalAlignment, Image, Length, Radio, Row, Slider, Text, TextInput,
 };
 
 #[derive(Default)]
@@ -16,8 +25,40 @@ pub struct FractalGUI {
     compute_params: compute::ComputeParams,
     render_params: render::RenderParams,
     fractal_data: Option<Arc<FractalResult>>,
+    // a result kept aside as the left operand of A ⊕ B
+    stored_result: Option<Arc<FractalResult>>,
     fractal_image: Option<image::Handle>,
+    // avalanche statistics from driven mode, plotted next to the final pile
+    driven_data: Option<Arc<DrivenResult>>,
+    distribution_image: Option<image::Handle>,
+    // a pile on the cubic lattice, shown one slice or projection at a time in place of fractal_data
+    volume_data: Option<Arc<VolumeResult>>,
+    volume_view: VolumeView,
+    // chips and firing counts of a graph, which have no image and are only summarized and exported
+    graph_data: Option<Arc<GraphResult>>,
+    // the sandpile group of the current result's domain, with the result's order in it
+    group: Option<Arc<SandpileGroup>>,
+    // whether the current result is recurrent. the cells that didn't burn are highlighted in the image
+    burning: Option<Arc<Burning>>,
+    // the cycle parallel chip-firing ended up in. the frame being shown is put in fractal_data or graph_data
+    orbit: Option<Arc<Orbit>>,
+    orbit_frame: usize,
+    orbit_playing: bool,
+    ticking_orbit: bool,
+    // a rotor-router cluster grown from the same sources as the pile in fractal_data, drawn next to it
+    rotor_data: Option<Arc<FractalResult>>,
+    rotor_image: Option<image::Handle>,
+    // prefix of the CSV files the avalanche statistics and the rendered field are exported to
+    export_prefix: String,
+    error_message: Option<String>,
+    // how the last pile compared to the reference engine, if it was checked
+    verification: Option<Arc<Verification>>,
     state: State,
+    mode: Mode,
+    // follows the computation that's currently running
+    monitor: Arc<compute::ComputeMonitor>,
+    progress: Option<compute::Progress>,
+    polling_progress: bool,
 }
 
 #[derive(PartialEq)]
@@ -33,13 +74,107 @@ impl Default for State {
     }
 }
 
+// what the compute button produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Pile,
+    // the identity element of the sandpile group, on a grid with a sink boundary
+    Identity,
+    // grains dropped one at a time on a grid with a sink boundary, recording the avalanches they cause
+    Driven,
+    // real-valued mass instead of grains, using the same sources, lattice and boundary as the pile
+    Divisible,
+    // the three-dimensional cubic lattice, using the sources' z coordinates
+    Cubic,
+    // chip-firing on a graph from a file or a generator, with sources placed on vertices
+    Graph,
+    // parallel chip-firing on a torus or reflecting domain, or on a graph without a sink, until it becomes periodic
+    Period,
+    // a rotor-router cluster from the same sources, lattice and boundary as the pile, shown next to the pile
+    Rotor,
+}
+
+impl Default for Mode {
+    fn default() -> Mode {
+        Mode::Pile
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Message {
-    InitialSizeChanged(String),
+    ModeSelected(Mode),
+    SourceXChanged(usize, String),
+    SourceYChanged(usize, String),
+    SourceZChanged(usize, String),
+    SourceVertexChanged(usize, String),
+    SourceValueChanged(usize, String),
+    AddSource,
+    RemoveSource(usize),
+    LatticeSelected(Lattice),
+    KernelSelected(compute::KernelKind),
+    KernelRadiusChanged(String),
+    KernelStencilChanged(String),
+    KernelThresholdChanged(String),
+    SymmetricToggled(bool),
+    EngineSelected(compute::Engine),
+    BoundarySelected(compute::BoundaryKind),
+    DomainSizeChanged(String),
+    BackgroundHeightChanged(String),
+    TimelinePathChanged(String),
+    TimelineIntervalChanged(String),
+    TimelineLogarithmicToggled(bool),
+    ExportTimeline,
+    TimelineExported(Result<usize, String>),
+    CheckpointIntervalChanged(String),
+    DropCountChanged(String),
+    WarmupDropsChanged(String),
+    DropSeedChanged(String),
+    RandomDropsToggled(bool),
+    ExportPrefixChanged(String),
+    ExportCsv,
+    FieldSelected(DataField),
+    ExportField,
+    VerifyToggled(bool),
+    DivisibleToleranceChanged(String),
+    DivisibleMaxIterationsChanged(String),
+    ModelSelected(compute::ModelKind),
+    ModelSeedChanged(String),
+    MannaThresholdChanged(String),
+    AxisSelected(Axis),
+    ProjectionToggled(bool),
+    SliceMoved(f32),
+    GraphSelected(compute::GraphKind),
+    GraphPathChanged(String),
+    GraphDirectedToggled(bool),
+    GraphSizeChanged(String),
+    TreeBranchingChanged(String),
+    GraphSinkChanged(String),
+    PeriodOnGraphToggled(bool),
+    PeriodMaxStepsChanged(String),
+    OrbitFrameMoved(f32),
+    OrbitPlayToggled(bool),
+    OrbitTick,
     ColorChanged(SliderColor, ColorChannel, f32),
     BeginComputingFractal,
-    FractalComputed(Arc<FractalResult>),
+    CancelComputation,
+    ProgressTick,
+    StoreResult,
+    AddStoredResult,
+    InvertResult,
+    ComputeGroup,
+    BurningTest,
+    FractalComputed(Result<Arc<FractalResult>, compute::ComputeError>),
+    VerifiedComputed(Result<(Arc<FractalResult>, Arc<Verification>), compute::ComputeError>),
     FractalRendered(image::Handle),
+    DrivenComputed(Result<Arc<DrivenResult>, compute::ComputeError>),
+    DistributionRendered(image::Handle),
+    VolumeComputed(Result<Arc<VolumeResult>, compute::ComputeError>),
+    GraphComputed(Result<Arc<GraphResult>, compute::ComputeError>),
+    GroupComputed(Result<Arc<SandpileGroup>, compute::ComputeError>),
+    BurningComputed(Result<Arc<Burning>, compute::ComputeError>),
+    PeriodComputed(Result<Arc<Orbit>, compute::ComputeError>),
+    RotorComputed(Result<(Arc<FractalResult>, Arc<FractalResult>), compute::ComputeError>),
+    RotorRendered(image::Handle),
 }
 
 #[derive(Debug, Clone)]
@@ -50,10 +185,54 @@ pub enum SliderColor {
     Color3,
 }
 
+#[derive(Default)]
+struct SourceUIData {
+    x_text: text_input::State,
+    y_text: text_input::State,
+    z_text: text_input::State,
+    vertex_text: text_input::State,
+    value_text: text_input::State,
+    remove_button: button::State,
+}
+
 #[derive(Default)]
 struct UIData {
     compute_button: button::State,
-    initial_size_text: text_input::State,
+    cancel_button: button::State,
+    store_button: button::State,
+    add_button: button::State,
+    invert_button: button::State,
+    group_button: button::State,
+    burning_button: button::State,
+    export_button: button::State,
+    export_field_button: button::State,
+    add_source_button: button::State,
+    sources: Vec<SourceUIData>,
+    kernel_radius_text: text_input::State,
+    kernel_stencil_text: text_input::State,
+    kernel_threshold_text: text_input::State,
+    domain_size_text: text_input::State,
+    grid_size_text: text_input::State,
+    background_height_text: text_input::State,
+    timeline_path_text: text_input::State,
+    export_timeline_button: button::State,
+    timeline_interval_text: text_input::State,
+    checkpoint_interval_text: text_input::State,
+    drop_count_text: text_input::State,
+    warmup_drops_text: text_input::State,
+    drop_seed_text: text_input::State,
+    export_prefix_text: text_input::State,
+    divisible_tolerance_text: text_input::State,
+    divisible_max_iterations_text: text_input::State,
+    model_seed_text: text_input::State,
+    manna_threshold_text: text_input::State,
+    slice_slider: slider::State,
+    graph_path_text: text_input::State,
+    graph_size_text: text_input::State,
+    tree_branching_text: text_input::State,
+    graph_sink_text: text_input::State,
+    period_max_steps_text: text_input::State,
+    orbit_slider: slider::State,
     background_color_red_slider: slider::State,
     background_color_green_slider: slider::State,
     background_color_blue_slider: slider::State,
@@ -81,8 +260,39 @@ impl Application for FractalGUI {
 
     fn update(&mut self, message: Message) -> Command<Message> {
         match message {
-            Message::InitialSizeChanged(value) => {
-                self.compute_params.initial_size = value;
+            Message::ModeSelected(mode) => {
+                self.mode = mode;
+                Command::none()
+            },
+            Message::SourceXChanged(index, value) => {
+                self.compute_params.sources[index].x = value;
+                Command::none()
+            },
+            Message::SourceYChanged(index, value) => {
+                self.compute_params.sources[index].y = value;
+                Command::none()
+            },
+            Message::SourceZChanged(index, value) => {
+                self.compute_params.sources[index].z = value;
+                Command::none()
+            },
+            Message::SourceVertexChanged(index, value) => {
+                self.compute_params.sources[index].vertex = value;
+                Command::none()
+            },
+            Message::SourceValueChanged(index, value) => {
+                self.compute_params.sources[index].value = value;
+                Command::none()
+            },
+            Message::AddSource => {
+                self.compute_params.sources.push(compute::SourceParams::default());
+                Command::none()
+            },
+            Message::RemoveSource(index) => {
+                if self.compute_params.sources.len() > 1 {
+                    self.compute_params.sources.remove(index);
+                    self.ui_state.sources.remove(index);
+                }
                 Command::none()
             },
             Message::ColorChanged(which_color, channel, value) => {
@@ -92,31 +302,448 @@ impl Application for FractalGUI {
                     SliderColor::Color2 => self.render_params.color2.set_normalized(channel, value),
                     SliderColor::Color3 => self.render_params.color3.set_normalized(channel, value),
                 }
-                if let Some(data) = &self.fractal_data {
-                    if self.state == State::Idle {
-                        Command::perform(render::render_fractal(self.render_params.clone(), Arc::clone(data)), Message::FractalRendered)
-                    } else {
-                        Command::none()
-                    }
+                self.render_again()
+            },
+            Message::FieldSelected(field) => {
+                self.render_params.field = field;
+                self.render_again()
+            },
+            Message::LatticeSelected(lattice) => {
+                self.compute_params.lattice = lattice;
+                Command::none()
+            },
+            Message::KernelSelected(kernel) => {
+                self.compute_params.kernel = kernel;
+                Command::none()
+            },
+            Message::KernelRadiusChanged(value) => {
+                self.compute_params.kernel_radius = value;
+                Command::none()
+            },
+            Message::KernelStencilChanged(value) => {
+                self.compute_params.kernel_stencil = value;
+                Command::none()
+            },
+            Message::KernelThresholdChanged(value) => {
+                self.compute_params.kernel_threshold = value;
+                Command::none()
+            },
+            Message::SymmetricToggled(value) => {
+                self.compute_params.symmetric = value;
+                Command::none()
+            },
+            Message::EngineSelected(engine) => {
+                self.compute_params.engine = engine;
+                Command::none()
+            },
+            Message::BoundarySelected(boundary) => {
+                self.compute_params.boundary = boundary;
+                Command::none()
+            },
+            Message::DomainSizeChanged(value) => {
+                self.compute_params.domain_size = value;
+                Command::none()
+            },
+            Message::BackgroundHeightChanged(value) => {
+                self.compute_params.background_height = value;
+                Command::none()
+            },
+            Message::TimelinePathChanged(value) => {
+                self.compute_params.timeline_path = value;
+                Command::none()
+            },
+            Message::TimelineIntervalChanged(value) => {
+                self.compute_params.timeline_interval = value;
+                Command::none()
+            },
+            Message::TimelineLogarithmicToggled(value) => {
+                self.compute_params.timeline_logarithmic = value;
+                Command::none()
+            },
+            Message::ExportTimeline => {
+                let prefix = format!("{}_frame", self.export_prefix());
+                Command::perform(render::export_timeline(self.render_params.clone(), self.compute_params.timeline_path.clone(), prefix), Message::TimelineExported)
+            },
+            Message::TimelineExported(result) => {
+                self.error_message = result.err().map(|error| compute::ComputeError::Io(error).to_string());
+                Command::none()
+            },
+            Message::CheckpointIntervalChanged(value) => {
+                self.compute_params.checkpoint_interval = value;
+                Command::none()
+            },
+            Message::DropCountChanged(value) => {
+                self.compute_params.drop_count = value;
+                Command::none()
+            },
+            Message::WarmupDropsChanged(value) => {
+                self.compute_params.warmup_drops = value;
+                Command::none()
+            },
+            Message::DropSeedChanged(value) => {
+                self.compute_params.drop_seed = value;
+                Command::none()
+            },
+            Message::RandomDropsToggled(value) => {
+                self.compute_params.random_drops = value;
+                Command::none()
+            },
+            Message::ExportPrefixChanged(value) => {
+                self.export_prefix = value;
+                Command::none()
+            },
+            Message::ExportCsv => {
+                if let Some(driven_data) = &self.driven_data {
+                    let prefix = self.export_prefix();
+                    let written = driven_data.write_avalanches_csv(&format!("{}_avalanches.csv", prefix))
+                        .and_then(|_| driven_data.write_distributions_csv(&format!("{}_distributions.csv", prefix)));
+                    self.error_message = written.err().map(|error| compute::ComputeError::Io(error.to_string()).to_string());
+                }
+                Command::none()
+            },
+            Message::ExportField => {
+                if let Some(graph_data) = &self.graph_data {
+                    let written = graph_data.write_csv(&format!("{}_graph.csv", self.export_prefix()));
+                    self.error_message = written.err().map(|error| compute::ComputeError::Io(error.to_string()).to_string());
+                } else if let Some(data) = &self.fractal_data {
+                    let field = self.render_params.field;
+                    let written = data.write_field_csv(field, &format!("{}_{}.csv", self.export_prefix(), field.name()));
+                    self.error_message = written.err().map(|error| compute::ComputeError::Io(error.to_string()).to_string());
+                }
+                Command::none()
+            },
+            Message::VerifyToggled(value) => {
+                self.compute_params.verify = value;
+                Command::none()
+            },
+            Message::DivisibleToleranceChanged(value) => {
+                self.compute_params.divisible_tolerance = value;
+                Command::none()
+            },
+            Message::DivisibleMaxIterationsChanged(value) => {
+                self.compute_params.divisible_max_iterations = value;
+                Command::none()
+            },
+            Message::ModelSelected(model) => {
+                self.compute_params.model = model;
+                Command::none()
+            },
+            Message::ModelSeedChanged(value) => {
+                self.compute_params.model_seed = value;
+                Command::none()
+            },
+            Message::MannaThresholdChanged(value) => {
+                self.compute_params.manna_threshold = value;
+                Command::none()
+            },
+            Message::AxisSelected(axis) => {
+                self.volume_view.axis = axis;
+                self.center_slice();
+                self.render_again()
+            },
+            Message::ProjectionToggled(value) => {
+                self.volume_view.projection = value;
+                self.render_again()
+            },
+            Message::SliceMoved(value) => {
+                let position = value.round() as i64;
+                if position != self.volume_view.position {
+                    self.volume_view.position = position;
+                    self.render_again()
+                } else {
+                    Command::none()
                 }
-                else {
+            },
+            Message::GraphSelected(graph) => {
+                self.compute_params.graph = graph;
+                Command::none()
+            },
+            Message::GraphPathChanged(value) => {
+                self.compute_params.graph_path = value;
+                Command::none()
+            },
+            Message::GraphDirectedToggled(value) => {
+                self.compute_params.graph_directed = value;
+                Command::none()
+            },
+            Message::GraphSizeChanged(value) => {
+                self.compute_params.graph_size = value;
+                Command::none()
+            },
+            Message::TreeBranchingChanged(value) => {
+                self.compute_params.tree_branching = value;
+                Command::none()
+            },
+            Message::GraphSinkChanged(value) => {
+                self.compute_params.graph_sink = value;
+                Command::none()
+            },
+            Message::PeriodOnGraphToggled(value) => {
+                self.compute_params.period_on_graph = value;
+                Command::none()
+            },
+            Message::PeriodMaxStepsChanged(value) => {
+                self.compute_params.period_max_steps = value;
+                Command::none()
+            },
+            Message::OrbitFrameMoved(value) => {
+                let frame = value.round() as usize;
+                if frame != self.orbit_frame && self.state == State::Idle {
+                    self.orbit_frame = frame;
+                    self.show_orbit_frame()
+                } else {
                     Command::none()
                 }
             },
+            Message::OrbitPlayToggled(value) => {
+                self.orbit_playing = value;
+                if value && !self.ticking_orbit {
+                    self.ticking_orbit = true;
+                    Command::perform(wait_for_frame(), |_| Message::OrbitTick)
+                } else {
+                    Command::none()
+                }
+            },
+            Message::OrbitTick => {
+                match self.orbit.as_ref().map(|orbit| orbit.period) {
+                    Some(period) if self.orbit_playing => {
+                        // a frame that's still being rendered is left to finish, rather than queueing up renders
+                        let show = if self.state == State::Idle {
+                            self.orbit_frame = (self.orbit_frame + 1) % period;
+                            self.show_orbit_frame()
+                        } else {
+                            Command::none()
+                        };
+                        Command::batch(vec![show, Command::perform(wait_for_frame(), |_| Message::OrbitTick)].into_iter())
+                    },
+                    _ => {
+                        self.ticking_orbit = false;
+                        Command::none()
+                    },
+                }
+            },
             Message::BeginComputingFractal => {
-                self.state = State::Computing;
-                Command::perform(compute::compute_fractal(self.compute_params.clone()), Message::FractalComputed)
+                let monitor = self.new_monitor();
+                match self.mode {
+                    Mode::Pile if self.compute_params.verify => self.start_computation(compute::compute_verified(self.compute_params.clone(), monitor), Message::VerifiedComputed),
+                    Mode::Pile => self.start_computation(compute::compute_fractal(self.compute_params.clone(), monitor), Message::FractalComputed),
+                    Mode::Identity => self.start_computation(compute::compute_identity(self.compute_params.clone(), monitor), Message::FractalComputed),
+                    Mode::Driven => self.start_computation(compute::compute_driven(self.compute_params.clone(), monitor), Message::DrivenComputed),
+                    Mode::Divisible => self.start_computation(compute::compute_divisible(self.compute_params.clone(), monitor), Message::FractalComputed),
+                    Mode::Cubic => self.start_computation(compute::compute_cubic(self.compute_params.clone(), monitor), Message::VolumeComputed),
+                    Mode::Graph => self.start_computation(compute::compute_graph(self.compute_params.clone(), monitor), Message::GraphComputed),
+                    Mode::Period => self.start_computation(compute::compute_period(self.compute_params.clone(), monitor), Message::PeriodComputed),
+                    Mode::Rotor => self.start_computation(compute::compute_rotor(self.compute_params.clone(), monitor), Message::RotorComputed),
+                }
+            },
+            Message::CancelComputation => {
+                self.monitor.cancel();
+                Command::none()
+            },
+            Message::ProgressTick => {
+                if self.state == State::Computing {
+                    self.progress = Some(self.monitor.progress());
+                    Command::perform(wait_for_progress(), |_| Message::ProgressTick)
+                } else {
+                    self.polling_progress = false;
+                    Command::none()
+                }
             },
-            Message::FractalComputed(result) => {
+            Message::StoreResult => {
+                self.stored_result = self.fractal_data.clone();
+                Command::none()
+            },
+            Message::AddStoredResult => {
+                match (self.stored_result.clone(), self.fractal_data.clone()) {
+                    (Some(stored), Some(current)) => {
+                        let monitor = self.new_monitor();
+                        self.start_computation(compute::compute_sum(stored, current, self.compute_params.engine, monitor), Message::FractalComputed)
+                    },
+                    _ => Command::none(),
+                }
+            },
+            Message::InvertResult => {
+                match self.fractal_data.clone() {
+                    Some(current) => {
+                        let monitor = self.new_monitor();
+                        self.start_computation(compute::compute_inverse(current, self.compute_params.engine, monitor), Message::FractalComputed)
+                    },
+                    None => Command::none(),
+                }
+            },
+            Message::ComputeGroup => {
+                let monitor = self.new_monitor();
+                match (self.graph_data.clone(), self.fractal_data.clone()) {
+                    (Some(graph_data), _) => self.start_computation(compute::compute_graph_group(graph_data, monitor), Message::GroupComputed),
+                    (None, Some(current)) => self.start_computation(compute::compute_group(current, monitor), Message::GroupComputed),
+                    (None, None) => Command::none(),
+                }
+            },
+            Message::BurningTest => {
+                let monitor = self.new_monitor();
+                match (self.graph_data.clone(), self.fractal_data.clone()) {
+                    (Some(graph_data), _) => self.start_computation(compute::compute_graph_burning(graph_data, monitor), Message::BurningComputed),
+                    (None, Some(current)) => self.start_computation(compute::compute_burning(current, monitor), Message::BurningComputed),
+                    (None, None) => Command::none(),
+                }
+            },
+            Message::FractalComputed(Ok(result)) => {
                 self.fractal_data = Some(Arc::clone(&result));
+                self.group = None;
+                self.burning = None;
+                self.orbit = None;
+                self.rotor_data = None;
+                self.rotor_image = None;
+                self.volume_data = None;
+                self.graph_data = None;
+                self.driven_data = None;
+                self.distribution_image = None;
                 self.state = State::Rendering;
                 Command::perform(render::render_fractal(self.render_params.clone(), result), Message::FractalRendered)
             }
+            Message::VerifiedComputed(Ok((result, verification))) => {
+                self.verification = Some(verification);
+                self.update(Message::FractalComputed(Ok(result)))
+            }
+            Message::VerifiedComputed(Err(error)) => {
+                self.update(Message::FractalComputed(Err(error)))
+            }
+            Message::FractalComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
             Message::FractalRendered(result) => {
                 self.fractal_image = Some(result);
                 self.state = State::Idle;
                 Command::none()
             }
+            Message::DrivenComputed(Ok(result)) => {
+                self.fractal_data = Some(Arc::clone(&result.final_state));
+                self.group = None;
+                self.burning = None;
+                self.orbit = None;
+                self.rotor_data = None;
+                self.rotor_image = None;
+                self.volume_data = None;
+                self.graph_data = None;
+                self.driven_data = Some(Arc::clone(&result));
+                self.state = State::Rendering;
+                Command::batch(vec![
+                    Command::perform(render::render_fractal(self.render_params.clone(), Arc::clone(&result.final_state)), Message::FractalRendered),
+                    Command::perform(render::render_distributions(self.render_params.clone(), result), Message::DistributionRendered),
+                ].into_iter())
+            }
+            Message::DrivenComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::DistributionRendered(result) => {
+                self.distribution_image = Some(result);
+                Command::none()
+            }
+            Message::VolumeComputed(Ok(result)) => {
+                self.volume_data = Some(Arc::clone(&result));
+                self.group = None;
+                self.burning = None;
+                self.orbit = None;
+                self.rotor_data = None;
+                self.rotor_image = None;
+                self.fractal_data = None;
+                self.graph_data = None;
+                self.driven_data = None;
+                self.distribution_image = None;
+                self.center_slice();
+                self.state = State::Rendering;
+                Command::perform(render::render_volume(self.render_params.clone(), result, self.volume_view), Message::FractalRendered)
+            }
+            Message::VolumeComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::GraphComputed(Ok(result)) => {
+                self.graph_data = Some(result);
+                self.group = None;
+                self.burning = None;
+                self.orbit = None;
+                self.rotor_data = None;
+                self.rotor_image = None;
+                self.fractal_data = None;
+                self.volume_data = None;
+                self.driven_data = None;
+                self.fractal_image = None;
+                self.distribution_image = None;
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::GraphComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::GroupComputed(Ok(group)) => {
+                self.group = Some(group);
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::GroupComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::BurningComputed(Ok(burning)) => {
+                self.burning = Some(Arc::clone(&burning));
+                match &self.fractal_data {
+                    Some(data) => {
+                        self.state = State::Rendering;
+                        Command::perform(render::render_burning(self.render_params.clone(), Arc::clone(data), burning), Message::FractalRendered)
+                    },
+                    None => {
+                        self.state = State::Idle;
+                        Command::none()
+                    },
+                }
+            }
+            Message::BurningComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::PeriodComputed(Ok(orbit)) => {
+                self.orbit = Some(orbit);
+                self.orbit_frame = 0;
+                self.orbit_playing = false;
+                self.group = None;
+                self.burning = None;
+                self.rotor_data = None;
+                self.rotor_image = None;
+                self.volume_data = None;
+                self.driven_data = None;
+                self.distribution_image = None;
+                self.show_orbit_frame()
+            }
+            Message::PeriodComputed(Err(error)) => {
+                self.error_message = Some(error.to_string());
+                self.state = State::Idle;
+                Command::none()
+            }
+            Message::RotorComputed(Ok((pile, cluster))) => {
+                let render = self.update(Message::FractalComputed(Ok(pile)));
+                self.rotor_data = Some(Arc::clone(&cluster));
+                Command::batch(vec![
+                    render,
+                    Command::perform(render::render_fractal(self.render_params.clone(), cluster), Message::RotorRendered),
+                ].into_iter())
+            }
+            Message::RotorComputed(Err(error)) => {
+                self.update(Message::FractalComputed(Err(error)))
+            }
+            Message::RotorRendered(result) => {
+                self.rotor_image = Some(result);
+                Command::none()
+            }
         }
     }
 
@@ -125,39 +752,618 @@ impl Application for FractalGUI {
             ui_state,
             compute_params,
             render_params,
-            fractal_data: _,
+            fractal_data,
+            stored_result,
             fractal_image,
+            driven_data,
+            distribution_image,
+            volume_data,
+            volume_view,
+            graph_data,
+            group,
+            burning,
+            orbit,
+            orbit_frame,
+            orbit_playing,
+            rotor_data,
+            rotor_image,
+            export_prefix,
+            error_message,
+            verification,
             state,
+            mode,
+            progress,
+            ..
         } = self;
 
 
 
-        let initial_size_text = TextInput::new(
-            &mut ui_state.initial_size_text,
-            "Initial Count",
-            &compute_params.initial_size,
-            |mut value| {
-                value.retain(|c| c.is_digit(10));
-                Message::InitialSizeChanged(value)
+        ui_state.sources.resize_with(compute_params.sources.len(), Default::default);
+
+        let can_remove_source = compute_params.sources.len() > 1;
+        let show_z = *mode == Mode::Cubic;
+        let on_graph = *mode == Mode::Graph || (*mode == Mode::Period && compute_params.period_on_graph);
+        // sources on a graph are placed on a vertex by its label, instead of by their coordinates
+        let show_vertex = on_graph;
+        let sources_column = ui_state.sources.iter_mut().zip(compute_params.sources.iter()).enumerate().fold(
+            Column::new().spacing(5),
+            |column, (index, (source_ui, source))| {
+                let row = Row::new()
+                    .width(Length::Fill)
+                    .spacing(5);
+                let row = if show_vertex {
+                    row.push(TextInput::new(
+                        &mut source_ui.vertex_text,
+                        "Vertex",
+                        &source.vertex,
+                        move |value| Message::SourceVertexChanged(index, value)
+                    ).padding(10).size(20))
+                } else {
+                    row.push(TextInput::new(
+                        &mut source_ui.x_text,
+                        "X",
+                        &source.x,
+                        move |mut value| {
+                            value.retain(|c| c.is_digit(10) || c == '-');
+                            Message::SourceXChanged(index, value)
+                        }
+                    ).padding(10).size(20))
+                    .push(TextInput::new(
+                        &mut source_ui.y_text,
+                        "Y",
+                        &source.y,
+                        move |mut value| {
+                            value.retain(|c| c.is_digit(10) || c == '-');
+                            Message::SourceYChanged(index, value)
+                        }
+                    ).padding(10).size(20))
+                };
+                let row = if show_z {
+                    row.push(TextInput::new(
+                        &mut source_ui.z_text,
+                        "Z",
+                        &source.z,
+                        move |mut value| {
+                            value.retain(|c| c.is_digit(10) || c == '-');
+                            Message::SourceZChanged(index, value)
+                        }
+                    ).padding(10).size(20))
+                } else {
+                    row
+                };
+                column.push(row
+                    .push(TextInput::new(
+                        &mut source_ui.value_text,
+                        "Count",
+                        &source.value,
+                        move |mut value| {
+                            value.retain(|c| c.is_digit(10));
+                            Message::SourceValueChanged(index, value)
+                        }
+                    ).padding(10).size(20))
+                    .push(button(&mut source_ui.remove_button, "Remove", can_remove_source, Message::RemoveSource(index)))
+                )
             }
-        )
-        .padding(15)
-        .size(30);
+        );
+
+        let lattice_options = [
+            (Lattice::Square, "Square"),
+            (Lattice::Hexagonal, "Hexagonal"),
+            (Lattice::Triangular, "Triangular"),
+        ];
+        let lattice_row = lattice_options.iter().fold(
+            Row::new().width(Length::Fill).spacing(10),
+            |row, (lattice, label)| row.push(Radio::new(*lattice, label, Some(compute_params.lattice), Message::LatticeSelected)),
+        );
+
+        let kernel_options = [
+            (compute::KernelKind::VonNeumann, "Von Neumann"),
+            (compute::KernelKind::Moore, "Moore"),
+            (compute::KernelKind::Extended, "Extended"),
+            (compute::KernelKind::Weighted, "Weighted"),
+        ];
+        let kernel_row = kernel_options.iter().fold(
+            Row::new().width(Length::Fill).spacing(10),
+            |row, (kernel, label)| row.push(Radio::new(*kernel, label, Some(compute_params.kernel), Message::KernelSelected)),
+        );
+
+        let kernel_settings: Element<Message> = match compute_params.kernel {
+            _ if compute_params.lattice != Lattice::Square => Column::new().into(),
+            compute::KernelKind::Extended => Row::new()
+                .width(Length::Fill)
+                .spacing(5)
+                .push(Text::new("Radius").color([0.1, 0.1, 0.1]))
+                .push(TextInput::new(
+                    &mut ui_state.kernel_radius_text,
+                    "Radius",
+                    &compute_params.kernel_radius,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::KernelRadiusChanged(value)
+                    }
+                ).padding(10).size(20))
+                .into(),
+            compute::KernelKind::Weighted => Row::new()
+                .width(Length::Fill)
+                .spacing(5)
+                .push(TextInput::new(
+                    &mut ui_state.kernel_stencil_text,
+                    "dx,dy,amount; ...",
+                    &compute_params.kernel_stencil,
+                    Message::KernelStencilChanged
+                ).padding(10).size(20))
+                .push(TextInput::new(
+                    &mut ui_state.kernel_threshold_text,
+                    "Threshold",
+                    &compute_params.kernel_threshold,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::KernelThresholdChanged(value)
+                    }
+                ).padding(10).size(20))
+                .into(),
+            _ => Column::new().into(),
+        };
+
+        let boundary_options = [
+            (compute::BoundaryKind::Plane, "Plane"),
+            (compute::BoundaryKind::Sink, "Sink"),
+            (compute::BoundaryKind::Torus, "Torus"),
+            (compute::BoundaryKind::Reflecting, "Reflecting"),
+        ];
+        let boundary_row = boundary_options.iter().fold(
+            Row::new().width(Length::Fill).spacing(10),
+            |row, (boundary, label)| row.push(Radio::new(*boundary, label, Some(compute_params.boundary), Message::BoundarySelected)),
+        );
+        let boundary_row = if compute_params.boundary != compute::BoundaryKind::Plane {
+            boundary_row.push(TextInput::new(
+                &mut ui_state.domain_size_text,
+                "Size",
+                &compute_params.domain_size,
+                |mut value| {
+                    value.retain(|c| c.is_digit(10));
+                    Message::DomainSizeChanged(value)
+                }
+            ).padding(10).size(20))
+        } else {
+            boundary_row
+        };
+
+        let mode_row = Row::new()
+            .width(Length::Fill)
+            .spacing(10)
+            .push(Radio::new(Mode::Pile, "Pile", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Identity, "Sandpile group identity", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Driven, "Driven (avalanches)", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Divisible, "Divisible sandpile", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Cubic, "3D cubic", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Graph, "Chip-firing graph", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Period, "Parallel chip-firing period", Some(*mode), Message::ModeSelected))
+            .push(Radio::new(Mode::Rotor, "Rotor-router next to the pile", Some(*mode), Message::ModeSelected));
+        let mode_row = if *mode == Mode::Identity || *mode == Mode::Driven {
+            // the identity and driven mode share the domain size with the fixed-size boundaries
+            mode_row.push(TextInput::new(
+                &mut ui_state.grid_size_text,
+                "Grid Size",
+                &compute_params.domain_size,
+                |mut value| {
+                    value.retain(|c| c.is_digit(10));
+                    Message::DomainSizeChanged(value)
+                }
+            ).padding(10).size(20))
+        } else {
+            mode_row
+        };
+
+        let progress_text = match (&*state, progress) {
+            (State::Computing, Some(progress)) => format!(
+                "{} {:.0}% - iteration {}, {}x{} cells, {} topples in the last iteration",
+                progress_bar(progress.estimated_completion),
+                progress.estimated_completion * 100.0,
+                progress.iterations,
+                progress.side_length,
+                progress.side_length,
+                progress.redistributions,
+            ),
+            (State::Computing, None) => "Starting...".into(),
+            _ => String::new(),
+        };
+
+        let mode_settings_row = if *mode == Mode::Driven {
+            Row::new()
+                .width(Length::Fill)
+                .spacing(10)
+                .push(TextInput::new(
+                    &mut ui_state.drop_count_text,
+                    "Drops",
+                    &compute_params.drop_count,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::DropCountChanged(value)
+                    }
+                ).padding(10).size(20))
+                .push(TextInput::new(
+                    &mut ui_state.warmup_drops_text,
+                    "Warmup Drops",
+                    &compute_params.warmup_drops,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::WarmupDropsChanged(value)
+                    }
+                ).padding(10).size(20))
+                .push(Checkbox::new(compute_params.random_drops, "Random sites (otherwise the first source)", Message::RandomDropsToggled))
+                .push(TextInput::new(
+                    &mut ui_state.drop_seed_text,
+                    "Seed",
+                    &compute_params.drop_seed,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::DropSeedChanged(value)
+                    }
+                ).padding(10).size(20))
+        } else if *mode == Mode::Divisible {
+            Row::new()
+                .width(Length::Fill)
+                .spacing(10)
+                .push(Text::new("Tolerance").color([0.1, 0.1, 0.1]))
+                .push(TextInput::new(
+                    &mut ui_state.divisible_tolerance_text,
+                    "1e-6",
+                    &compute_params.divisible_tolerance,
+                    Message::DivisibleToleranceChanged
+                ).padding(10).size(20))
+                .push(Text::new("Max Iterations").color([0.1, 0.1, 0.1]))
+                .push(TextInput::new(
+                    &mut ui_state.divisible_max_iterations_text,
+                    "0 for no limit",
+                    &compute_params.divisible_max_iterations,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::DivisibleMaxIterationsChanged(value)
+                    }
+                ).padding(10).size(20))
+        } else if *mode == Mode::Cubic {
+            let row = Row::new()
+                .width(Length::Fill)
+                .spacing(10)
+                .push(Radio::new(Axis::X, "X", Some(volume_view.axis), Message::AxisSelected))
+                .push(Radio::new(Axis::Y, "Y", Some(volume_view.axis), Message::AxisSelected))
+                .push(Radio::new(Axis::Z, "Z", Some(volume_view.axis), Message::AxisSelected))
+                .push(Checkbox::new(volume_view.projection, "Maximum projection", Message::ProjectionToggled));
+            match &*volume_data {
+                Some(volume) if !volume_view.projection => {
+                    let (first, last) = volume.extent(volume_view.axis);
+                    row.push(Slider::new(&mut ui_state.slice_slider, first as f32..=last as f32, volume_view.position as f32, Message::SliceMoved))
+                        .push(Text::new(format!("Slice {}", volume_view.position)).color([0.1, 0.1, 0.1]))
+                },
+                _ => row,
+            }
+        } else if on_graph {
+            let graph_options = [
+                (compute::GraphKind::Torus, "Torus"),
+                (compute::GraphKind::Complete, "Complete"),
+                (compute::GraphKind::Tree, "Tree"),
+                (compute::GraphKind::Sierpinski, "Sierpinski gasket"),
+                (compute::GraphKind::File, "File"),
+            ];
+            let graph_row = graph_options.iter().fold(
+                Row::new().width(Length::Fill).spacing(10),
+                |row, (graph, label)| row.push(Radio::new(*graph, label, Some(compute_params.graph), Message::GraphSelected)),
+            );
+            let graph_row = match compute_params.graph {
+                compute::GraphKind::File => graph_row
+                    .push(TextInput::new(
+                        &mut ui_state.graph_path_text,
+                        "Edge list or adjacency file",
+                        &compute_params.graph_path,
+                        Message::GraphPathChanged
+                    ).padding(10).size(20))
+                    .push(Checkbox::new(compute_params.graph_directed, "Directed", Message::GraphDirectedToggled)),
+                graph => {
+                    let graph_row = graph_row.push(TextInput::new(
+                        &mut ui_state.graph_size_text,
+                        match graph {
+                            compute::GraphKind::Tree => "Depth",
+                            compute::GraphKind::Sierpinski => "Level",
+                            _ => "Size",
+                        },
+                        &compute_params.graph_size,
+                        |mut value| {
+                            value.retain(|c| c.is_digit(10));
+                            Message::GraphSizeChanged(value)
+                        }
+                    ).padding(10).size(20));
+                    if graph == compute::GraphKind::Tree {
+                        graph_row.push(TextInput::new(
+                            &mut ui_state.tree_branching_text,
+                            "Children",
+                            &compute_params.tree_branching,
+                            |mut value| {
+                                value.retain(|c| c.is_digit(10));
+                                Message::TreeBranchingChanged(value)
+                            }
+                        ).padding(10).size(20))
+                    } else {
+                        graph_row
+                    }
+                },
+            };
+            graph_row
+                .push(Text::new("Sink").color([0.1, 0.1, 0.1]))
+                .push(TextInput::new(
+                    &mut ui_state.graph_sink_text,
+                    "None",
+                    &compute_params.graph_sink,
+                    Message::GraphSinkChanged
+                ).padding(10).size(20))
+        } else if *mode == Mode::Pile || *mode == Mode::Rotor {
+            let model_options = [
+                (compute::ModelKind::Abelian, "Abelian"),
+                (compute::ModelKind::Manna, "Manna"),
+                (compute::ModelKind::Oslo, "Oslo"),
+            ];
+            let model_row = model_options.iter().fold(
+                Row::new().width(Length::Fill).spacing(10),
+                |row, (model, label)| row.push(Radio::new(*model, label, Some(compute_params.model), Message::ModelSelected)),
+            );
+            let model_row = if compute_params.model != compute::ModelKind::Abelian {
+                model_row.push(TextInput::new(
+                    &mut ui_state.model_seed_text,
+                    "Seed",
+                    &compute_params.model_seed,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::ModelSeedChanged(value)
+                    }
+                ).padding(10).size(20))
+            } else {
+                model_row
+            };
+            if compute_params.model == compute::ModelKind::Manna {
+                model_row
+                    .push(Text::new("Threshold").color([0.1, 0.1, 0.1]))
+                    .push(TextInput::new(
+                        &mut ui_state.manna_threshold_text,
+                        "Threshold",
+                        &compute_params.manna_threshold,
+                        |mut value| {
+                            value.retain(|c| c.is_digit(10));
+                            Message::MannaThresholdChanged(value)
+                        }
+                    ).padding(10).size(20))
+            } else {
+                model_row
+            }
+        } else {
+            Row::new()
+        };
+
+        let period_row = if *mode == Mode::Period {
+            let row = Row::new()
+                .width(Length::Fill)
+                .spacing(10)
+                .push(Checkbox::new(compute_params.period_on_graph, "On the graph (otherwise the torus or reflecting domain)", Message::PeriodOnGraphToggled))
+                .push(Text::new("Step Limit").color([0.1, 0.1, 0.1]))
+                .push(TextInput::new(
+                    &mut ui_state.period_max_steps_text,
+                    "1000000",
+                    &compute_params.period_max_steps,
+                    |mut value| {
+                        value.retain(|c| c.is_digit(10));
+                        Message::PeriodMaxStepsChanged(value)
+                    }
+                ).padding(10).size(20));
+            match &*orbit {
+                Some(orbit) if orbit.period > 1 => row
+                    .push(Slider::new(&mut ui_state.orbit_slider, 0.0..=(orbit.period - 1) as f32, *orbit_frame as f32, Message::OrbitFrameMoved))
+                    .push(Text::new(format!("Step {} of {}", *orbit_frame + 1, orbit.period)).color([0.1, 0.1, 0.1]))
+                    .push(Checkbox::new(*orbit_playing, "Play", Message::OrbitPlayToggled)),
+                _ => row,
+            }
+        } else {
+            Row::new()
+        };
+
+        let stats_text = if let Some(data) = &*graph_data {
+            format!("{} vertices, {} edges, {} firings, {} chips absorbed by the sink", data.graph.vertex_count(), data.graph.edge_count(), data.total_firings, data.absorbed_chips)
+        } else {
+            match (&*driven_data, &*fractal_data, &*volume_data) {
+                (_, _, Some(data)) => format!("{} iterations, {} topple steps, {} topples, {} grains absorbed", data.total_iterations, data.total_redistributions, data.total_topples, data.absorbed_grains),
+                (Some(driven_data), _, None) => {
+                    let fits: Vec<String> = driven_data.distributions().iter().map(|distribution| match distribution.fit {
+                        Some(fit) => format!("{} τ = {:.2}", distribution.quantity, fit.exponent),
+                        None => format!("{} τ = ?", distribution.quantity),
+                    }).collect();
+                    format!("{} avalanches, {}", driven_data.avalanches.len(), fits.join(", "))
+                },
+                (None, fractal_data, None) => match fractal_data {
+                    Some(data) => {
+                        let stats = if data.mass_data.is_empty() {
+                            format!("{} iterations, {} topple steps, {} topples, {} grains absorbed", data.total_iterations, data.total_redistributions, data.total_topples, data.absorbed_grains)
+                        } else {
+                            format!("{} iterations, {} topple steps, {} topples, {:.6} mass absorbed", data.total_iterations, data.total_redistributions, data.total_topples, data.absorbed_mass)
+                        };
+                        let stats = match data.model.seed() {
+                            Some(seed) => format!("{}\n{} model with seed {}, update order: {}", stats, data.model.name(), seed, data.model.update_order()),
+                            None => stats,
+                        };
+                        match &*rotor_data {
+                            Some(cluster) => {
+                                let occupied = cluster.sand_data.iter().filter(|&&particles| particles != 0).count();
+                                format!("{}\nrotor-router: {} cells occupied, {} particles sent on, {} particles lost", stats, occupied, cluster.total_topples, cluster.absorbed_grains)
+                            },
+                            None => stats,
+                        }
+                    },
+                    None => String::new(),
+                },
+            }
+        };
+
+        // the group is only finite on a sink-boundary domain, or a graph with a sink
+        let has_finite_group = match (&*graph_data, &*fractal_data) {
+            (Some(graph_data), _) => graph_data.graph.sink.is_some(),
+            (None, Some(data)) => match data.boundary {
+                Boundary::Sink { .. } => true,
+                _ => false,
+            },
+            (None, None) => false,
+        };
 
-       
         let content = Row::new()
             .width(Length::Fill)
             .spacing(20)
             .push(Column::new()
                 .width(Length::Fill)
                 .spacing(10)
-                .push(Text::new("Initial Count")
+                .push(mode_row)
+                .push(mode_settings_row)
+                .push(period_row)
+                .push(Text::new(if show_vertex { "Sources (Vertex, Count)" } else { "Sources (X, Y, Count)" })
                     .color([0.1, 0.1, 0.1])
                     .horizontal_alignment(HorizontalAlignment::Center)
                 )
-                .push(initial_size_text)
+                .push(sources_column)
                 .push(
-                    button(&mut ui_state.compute_button, "Compute", *state == State::Idle, Message::BeginComputingFractal),
+                    button(&mut ui_state.add_source_button, "Add Source", true, Message::AddSource),
+                )
+                .push(Text::new("Lattice")
+                    .color([0.1, 0.1, 0.1])
+                    .horizontal_alignment(HorizontalAlignment::Center)
+                )
+                .push(lattice_row)
+                .push(Text::new("Topple Kernel (Square Lattice)")
+                    .color([0.1, 0.1, 0.1])
+                    .horizontal_alignment(HorizontalAlignment::Center)
+                )
+                .push(kernel_row)
+                .push(kernel_settings)
+                .push(Checkbox::new(compute_params.symmetric, "Simulate one quadrant (single source, symmetric kernel)", Message::SymmetricToggled))
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(Text::new("Engine").color([0.1, 0.1, 0.1]))
+                    .push(Radio::new(compute::Engine::Dense, "Dense", Some(compute_params.engine), Message::EngineSelected))
+                    .push(Radio::new(compute::Engine::Sparse, "Sparse (skip stable tiles)", Some(compute_params.engine), Message::EngineSelected))
+                )
+                .push(Text::new("Boundary")
+                    .color([0.1, 0.1, 0.1])
+                    .horizontal_alignment(HorizontalAlignment::Center)
+                )
+                .push(boundary_row)
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(Text::new("Background Height").color([0.1, 0.1, 0.1]))
+                    .push(TextInput::new(
+                        &mut ui_state.background_height_text,
+                        "0",
+                        &compute_params.background_height,
+                        |mut value| {
+                            value.retain(|c| c.is_digit(10));
+                            Message::BackgroundHeightChanged(value)
+                        }
+                    ).padding(10).size(20))
+                )
+                .push(Text::new("Timeline Recording")
+                    .color([0.1, 0.1, 0.1])
+                    .horizontal_alignment(HorizontalAlignment::Center)
+                )
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(TextInput::new(
+                        &mut ui_state.timeline_path_text,
+                        "File (leave empty to skip)",
+                        &compute_params.timeline_path,
+                        Message::TimelinePathChanged
+                    ).padding(10).size(20))
+                    .push(TextInput::new(
+                        &mut ui_state.timeline_interval_text,
+                        "Interval",
+                        &compute_params.timeline_interval,
+                        |mut value| {
+                            value.retain(|c| c.is_digit(10));
+                            Message::TimelineIntervalChanged(value)
+                        }
+                    ).padding(10).size(20))
+                    .push(Checkbox::new(compute_params.timeline_logarithmic, "Logarithmic", Message::TimelineLogarithmicToggled))
+                    .push(button(&mut ui_state.export_timeline_button, "Export Frames", *state == State::Idle && compute_params.timeline_path.len() > 0, Message::ExportTimeline))
+                )
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(Text::new("Checkpoint Every (Seconds, 0 = Off)").color([0.1, 0.1, 0.1]))
+                    .push(TextInput::new(
+                        &mut ui_state.checkpoint_interval_text,
+                        "600",
+                        &compute_params.checkpoint_interval,
+                        |mut value| {
+                            value.retain(|c| c.is_digit(10));
+                            Message::CheckpointIntervalChanged(value)
+                        }
+                    ).padding(10).size(20))
+                )
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(button(&mut ui_state.compute_button, "Compute", *state == State::Idle, Message::BeginComputingFractal))
+                    .push(button(&mut ui_state.cancel_button, "Cancel", *state == State::Computing, Message::CancelComputation))
+                    .push(Checkbox::new(compute_params.verify, "Verify against the reference engine", Message::VerifyToggled))
+                )
+                .push(Text::new(progress_text)
+                    .color([0.1, 0.1, 0.1])
+                )
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(button(&mut ui_state.store_button, "Store as A", *state == State::Idle && fractal_data.is_some(), Message::StoreResult))
+                    .push(button(&mut ui_state.add_button, "A + Current", *state == State::Idle && fractal_data.is_some() && stored_result.is_some(), Message::AddStoredResult))
+                    .push(button(&mut ui_state.invert_button, "Inverse", *state == State::Idle && fractal_data.is_some(), Message::InvertResult))
+                    .push(button(&mut ui_state.group_button, "Group Structure", *state == State::Idle && has_finite_group, Message::ComputeGroup))
+                    .push(button(&mut ui_state.burning_button, "Burning Test", *state == State::Idle && has_finite_group, Message::BurningTest))
+                )
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(TextInput::new(
+                        &mut ui_state.export_prefix_text,
+                        DEFAULT_EXPORT_PREFIX,
+                        export_prefix,
+                        Message::ExportPrefixChanged
+                    ).padding(10).size(20))
+                    .push(button(&mut ui_state.export_field_button, "Export Field", fractal_data.is_some() || graph_data.is_some(), Message::ExportField))
+                    .push(button(&mut ui_state.export_button, "Export Avalanches", driven_data.is_some(), Message::ExportCsv))
+                )
+                .push(Text::new(stats_text)
+                    .color([0.1, 0.1, 0.1])
+                )
+                .push(Text::new(orbit.as_ref().map(|orbit| orbit.summary()).unwrap_or_default())
+                    .color([0.1, 0.1, 0.1])
+                )
+                .push(Text::new(group.as_ref().map(|group| group.summary()).unwrap_or_default())
+                    .color([0.1, 0.1, 0.1])
+                )
+                .push(Text::new(burning.as_ref().map(|burning| burning.report()).unwrap_or_default())
+                    .color(if burning.as_ref().map_or(true, |burning| burning.is_recurrent()) { [0.1, 0.5, 0.1] } else { [0.8, 0.1, 0.1] })
+                )
+                .push(Text::new(verification.as_ref().map(|verification| verification.report()).unwrap_or_default())
+                    .color(if verification.as_ref().map_or(true, |verification| verification.passed()) { [0.1, 0.5, 0.1] } else { [0.8, 0.1, 0.1] })
+                )
+                .push(Text::new(error_message.as_ref().map(|message| message.as_str()).unwrap_or(""))
+                    .color([0.8, 0.1, 0.1])
+                )
+                .push(Text::new("Rendered Field")
+                    .color([0.1, 0.1, 0.1])
+                    .horizontal_alignment(HorizontalAlignment::Center)
+                )
+                .push(Row::new()
+                    .width(Length::Fill)
+                    .spacing(10)
+                    .push(Radio::new(DataField::Sand, "Sand", Some(render_params.field), Message::FieldSelected))
+                    .push(Radio::new(DataField::ToppleSteps, "Topple steps", Some(render_params.field), Message::FieldSelected))
+                    .push(Radio::new(DataField::Odometer, "Odometer", Some(render_params.field), Message::FieldSelected))
+                    .push(Radio::new(DataField::Rotor, "Rotors", Some(render_params.field), Message::FieldSelected))
                 )
                 .push(Text::new("Background Color")
                     .color([0.1, 0.1, 0.1])
@@ -223,6 +1429,18 @@ impl Application for FractalGUI {
         else {
             content
         };
+        let content = if let Some(image_handle) = rotor_image {
+            content.push(Image::new(image_handle.clone()).width(Length::Fill).height(Length::Fill))
+        }
+        else {
+            content
+        };
+        let content = if let Some(image_handle) = distribution_image {
+            content.push(Image::new(image_handle.clone()).width(Length::Fill).height(Length::Fill))
+        }
+        else {
+            content
+        };
 
         Container::new(content)
             .width(Length::Fill)
@@ -233,6 +1451,123 @@ impl Application for FractalGUI {
     }
 }
 
+const DEFAULT_EXPORT_PREFIX: &str = "sandpile";
+
+impl FractalGUI {
+    fn export_prefix(&self) -> &str {
+        if self.export_prefix.len() > 0 { &self.export_prefix } else { DEFAULT_EXPORT_PREFIX }
+    }
+
+    // moves the slice of a volume to the middle of the axis it's taken along
+    fn center_slice(&mut self) {
+        if let Some(volume) = &self.volume_data {
+            let (first, last) = volume.extent(self.volume_view.axis);
+            self.volume_view.position = (first + last) / 2;
+        }
+    }
+
+    // puts the current frame of the orbit in place of the result, and renders it if it's on a lattice
+    fn show_orbit_frame(&mut self) -> Command<Message> {
+        let frame = match &self.orbit {
+            Some(orbit) => orbit.frame(self.orbit_frame),
+            None => return Command::none(),
+        };
+        match frame {
+            OrbitFrame::Lattice(result) => {
+                let result = Arc::new(result);
+                self.fractal_data = Some(Arc::clone(&result));
+                self.graph_data = None;
+                self.state = State::Rendering;
+                Command::perform(render::render_fractal(self.render_params.clone(), result), Message::FractalRendered)
+            },
+            OrbitFrame::Graph(result) => {
+                self.graph_data = Some(Arc::new(result));
+                self.fractal_data = None;
+                self.fractal_image = None;
+                self.state = State::Idle;
+                Command::none()
+            },
+        }
+    }
+
+    // renders the current result again after the render settings changed
+    fn render_again(&self) -> Command<Message> {
+        if let Some(volume) = &self.volume_data {
+            if self.state == State::Idle {
+                Command::perform(render::render_volume(self.render_params.clone(), Arc::clone(volume), self.volume_view), Message::FractalRendered)
+            } else {
+                Command::none()
+            }
+        } else if let Some(data) = &self.fractal_data {
+            if self.state == State::Idle {
+                let render = match &self.burning {
+                    Some(burning) => Command::perform(render::render_burning(self.render_params.clone(), Arc::clone(data), Arc::clone(burning)), Message::FractalRendered),
+                    None => Command::perform(render::render_fractal(self.render_params.clone(), Arc::clone(data)), Message::FractalRendered),
+                };
+                match &self.driven_data {
+                    Some(driven_data) => Command::batch(vec![
+                        render,
+                        Command::perform(render::render_distributions(self.render_params.clone(), Arc::clone(driven_data)), Message::DistributionRendered),
+                    ].into_iter()),
+                    None => match &self.rotor_data {
+                        Some(rotor_data) => Command::batch(vec![
+                            render,
+                            Command::perform(render::render_fractal(self.render_params.clone(), Arc::clone(rotor_data)), Message::RotorRendered),
+                        ].into_iter()),
+                        None => render,
+                    },
+                }
+            } else {
+                Command::none()
+            }
+        }
+        else {
+            Command::none()
+        }
+    }
+
+    // replaces the monitor, so that cancelling doesn't affect computations that have already finished
+    fn new_monitor(&mut self) -> Arc<compute::ComputeMonitor> {
+        self.monitor = Arc::new(compute::ComputeMonitor::default());
+        Arc::clone(&self.monitor)
+    }
+
+    fn start_computation<F>(&mut self, computation: F, finished: fn(F::Output) -> Message) -> Command<Message>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        self.state = State::Computing;
+        self.error_message = None;
+        self.verification = None;
+        self.progress = None;
+
+        let computation = Command::perform(computation, finished);
+        if self.polling_progress {
+            computation
+        } else {
+            self.polling_progress = true;
+            Command::batch(vec![computation, Command::perform(wait_for_progress(), |_| Message::ProgressTick)].into_iter())
+        }
+    }
+}
+
+// iced has no way to subscribe to the computation, so its progress is polled on a timer instead
+async fn wait_for_progress() {
+    std::thread::sleep(Duration::from_millis(250));
+}
+
+// how long each frame of an orbit is shown for while it plays
+async fn wait_for_frame() {
+    std::thread::sleep(Duration::from_millis(100));
+}
+
+fn progress_bar(completion: f64) -> String {
+    const WIDTH: usize = 30;
+    let filled = ((completion * WIDTH as f64) as usize).min(WIDTH);
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(WIDTH - filled))
+}
+
 fn button<'a, Message>(
     state: &'a mut button::State,
     label: &str,
@@ -3,6 +3,17 @@ mod cache;
 mod common;
 mod compute;
 mod render;
+mod sparse;
+mod timeline;
+mod random;
+mod driven;
+mod reference;
+mod cubic;
+mod graph;
+mod group;
+mod burning;
+mod periodic;
+mod rotor;
 mod gui;
 
 use iced::{ Settings, Application };
@@ -0,0 +1,366 @@
+use std::cmp::min;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::common::{Boundary, FractalResult, InitialCell, Model, Symmetry, ToppleRule};
+use crate::compute::{ComputeError, ComputeMonitor, Grains, Progress, SynchronousDomain};
+use crate::graph::{Graph, GraphResult, InitialVertex};
+
+// the frames of the orbit are kept in memory, so the cells of all of them together are limited to this many
+const MAX_ORBIT_CELLS: usize = 1 << 28;
+
+const REPORT_INTERVAL: usize = 64;
+
+// the configurations parallel chip-firing cycles through, along with what they live on
+#[derive(Debug)]
+pub enum OrbitFrames {
+	// the cells of a torus or reflecting domain, row by row
+	Lattice {
+		topple_rule: ToppleRule,
+		boundary: Boundary,
+		background_height: u32,
+		initial_configuration: Vec<InitialCell>,
+		frames: Vec<Vec<u8>>,
+	},
+	Graph {
+		graph: Graph,
+		initial_configuration: Vec<InitialVertex>,
+		background_height: u32,
+		frames: Vec<Vec<u64>>,
+	},
+}
+
+// with no sink, chips are never lost and there are only finitely many configurations, so parallel chip-firing ends up
+// going round a cycle
+#[derive(Debug)]
+pub struct Orbit {
+	// the number of steps before the configuration first comes back to one it has been in
+	pub transient_length: usize,
+	pub period: usize,
+	// how often each site fires over one period. on a lattice, the sites are the cells of the domain row by row
+	pub firing_counts: Vec<u64>,
+	// the cycle, starting with the configuration the transient ends on
+	pub frames: OrbitFrames,
+}
+
+// one configuration of an orbit, as a result that can be shown like any other
+pub enum OrbitFrame {
+	Lattice(FractalResult),
+	Graph(GraphResult),
+}
+
+impl Orbit {
+	pub fn total_firings(&self) -> u64 {
+		self.firing_counts.iter().sum()
+	}
+
+	pub fn summary(&self) -> String {
+		// the activity is the share of the sites that fire in an average step
+		let activity = self.total_firings() as f64 / (self.firing_counts.len() * self.period) as f64;
+		format!(
+			"Periodic after a transient of {} steps, with period {}: {} firings per period, an activity of {:.4}",
+			self.transient_length, self.period, self.total_firings(), activity,
+		)
+	}
+
+	// the configuration `index` steps into the cycle. the firing counts of every frame are the ones of the whole period
+	pub fn frame(&self, index: usize) -> OrbitFrame {
+		let total_firings = self.total_firings();
+		match &self.frames {
+			OrbitFrames::Lattice { topple_rule, boundary, background_height, initial_configuration, frames } => {
+				let size = boundary.domain_size().expect("the boundary should have a fixed-size domain");
+				let start = boundary.domain_start();
+				OrbitFrame::Lattice(FractalResult {
+					initial_configuration: initial_configuration.clone(),
+					topple_rule: topple_rule.clone(),
+					model: Model::Abelian,
+					symmetry: Symmetry::None,
+					boundary: *boundary,
+					background_height: *background_height,
+					sand_data: frames[index].clone(),
+					mass_data: Vec::new(),
+					rotor_data: Vec::new(),
+					count_data: self.firing_counts.iter().map(|&count| min(count, u64::from(std::u32::MAX)) as u32).collect(),
+					odometer_data: self.firing_counts.clone(),
+					odometer_mass: Vec::new(),
+					side_length: size,
+					origin_x: start,
+					origin_y: start,
+
+					total_redistributions: total_firings,
+					total_topples: total_firings,
+					absorbed_grains: 0,
+					absorbed_mass: 0.0,
+					total_iterations: self.transient_length + index,
+				})
+			},
+			OrbitFrames::Graph { graph, initial_configuration, background_height, frames } => OrbitFrame::Graph(GraphResult {
+				graph: graph.clone(),
+				initial_configuration: initial_configuration.clone(),
+				background_height: *background_height,
+				chips: frames[index].clone(),
+				firing_counts: self.firing_counts.clone(),
+				total_firings,
+				absorbed_chips: 0,
+			}),
+		}
+	}
+}
+
+// where the dynamics first comes back to a configuration it has been in
+struct Cycle<S> {
+	transient_length: usize,
+	period: usize,
+	// the configuration the transient ends on, which is the first one to repeat
+	entry: S,
+}
+
+fn hash_state<S: Hash>(state: &S) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	state.hash(&mut hasher);
+	hasher.finish()
+}
+
+// runs the dynamics until a configuration repeats. only the hashes of the configurations are remembered, along with the
+// steps they were seen at, so a matching hash is confirmed by running the dynamics from the start again up to the
+// earlier step and comparing the configurations. a hash collision costs that run, but can't give a wrong period
+fn find_cycle<S, F>(initial: &S, mut step: F, max_steps: usize, site_count: usize, monitor: Option<&ComputeMonitor>) -> Result<Cycle<S>, ComputeError>
+where
+	S: Clone + Eq + Hash,
+	F: FnMut(&mut S) -> u64,
+{
+	let cancelled = || monitor.map_or(false, |monitor| monitor.is_cancelled());
+
+	let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+	let mut state = initial.clone();
+	let mut steps = 0;
+	loop {
+		let hash = hash_state(&state);
+		if let Some(earlier) = seen.get(&hash) {
+			for &first in earlier {
+				let mut replayed = initial.clone();
+				for replayed_steps in 0..first {
+					if replayed_steps % REPORT_INTERVAL == 0 && cancelled() {
+						return Err(ComputeError::Cancelled);
+					}
+					step(&mut replayed);
+				}
+				if replayed == state {
+					return Ok(Cycle { transient_length: first, period: steps - first, entry: state });
+				}
+			}
+		}
+		if steps == max_steps {
+			return Err(ComputeError::InvalidParameter(format!("no configuration repeated within the limit of {} steps", max_steps)));
+		}
+		seen.entry(hash).or_default().push(steps);
+
+		let fired = step(&mut state);
+		steps += 1;
+
+		if let Some(monitor) = monitor {
+			if steps % REPORT_INTERVAL == 0 {
+				if monitor.is_cancelled() {
+					return Err(ComputeError::Cancelled);
+				}
+				// how long the cycle takes to show up can't be told in advance, so this is only the share of the limit used up
+				monitor.report(Progress {
+					iterations: steps,
+					side_length: (site_count as f64).sqrt() as usize,
+					redistributions: fired,
+					estimated_completion: steps as f64 / max_steps as f64,
+				});
+			}
+		}
+	}
+}
+
+fn check_orbit_size(period: usize, site_count: usize) -> Result<(), ComputeError> {
+	if period.saturating_mul(site_count) > MAX_ORBIT_CELLS {
+		return Err(ComputeError::InvalidParameter(format!("the period of {} steps is too long to keep every configuration of the orbit", period)));
+	}
+	Ok(())
+}
+
+// parallel chip-firing on a torus or reflecting domain, where every cell holding at least the threshold topples once at
+// every step. the domain never loses grains, unless the kernel sends out fewer than its threshold
+pub fn lattice_orbit(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, background_height: u32, max_steps: usize, monitor: Option<&ComputeMonitor>) -> Result<Orbit, ComputeError> {
+	let size = match boundary {
+		Boundary::Torus { size } | Boundary::Reflecting { size } => size,
+		_ => return Err(ComputeError::InvalidParameter("parallel chip-firing only becomes periodic on a torus or reflecting boundary, which keep every grain".into())),
+	};
+	if let Some(entry) = initial_configuration.iter().find(|entry| !boundary.contains(entry.x, entry.y)) {
+		return Err(ComputeError::InvalidParameter(format!("source ({}, {}) is outside the domain", entry.x, entry.y)));
+	}
+
+	let total_grains = initial_configuration.iter().try_fold(0u64, |total, entry| total.checked_add(entry.value))
+		.and_then(|total| total.checked_add((size as u64).saturating_mul(size as u64).saturating_mul(u64::from(background_height))))
+		.ok_or_else(|| ComputeError::InvalidParameter("the total number of grains doesn't fit in 64 bits".into()))?;
+
+	if total_grains <= u64::from(std::u32::MAX) {
+		run_lattice::<u32>(initial_configuration, topple_rule, boundary, background_height, max_steps, monitor)
+	} else {
+		run_lattice::<u64>(initial_configuration, topple_rule, boundary, background_height, max_steps, monitor)
+	}
+}
+
+fn run_lattice<T: Grains>(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, background_height: u32, max_steps: usize, monitor: Option<&ComputeMonitor>) -> Result<Orbit, ComputeError> {
+	let size = boundary.domain_size().expect("the boundary should have a fixed-size domain");
+	let threshold = T::from(topple_rule.threshold);
+
+	let (mut domain, cells) = SynchronousDomain::<T>::new(initial_configuration, topple_rule, boundary, background_height);
+	let cycle = find_cycle(&cells, |cells| domain.step(cells), max_steps, size * size, monitor)?;
+	check_orbit_size(cycle.period, size * size)?;
+
+	let mut cells = cycle.entry;
+	let mut firing_counts = vec![0u64; size * size];
+	let mut frames = Vec::with_capacity(cycle.period);
+	for _ in 0..cycle.period {
+		let domain_cells = domain.domain_cells(&cells);
+		for (count, &value) in firing_counts.iter_mut().zip(&domain_cells) {
+			if value >= threshold {
+				*count += 1;
+			}
+		}
+		frames.push(domain_cells.into_iter().map(|value| min(value.into(), 255) as u8).collect());
+		domain.step(&mut cells);
+	}
+
+	Ok(Orbit {
+		transient_length: cycle.transient_length,
+		period: cycle.period,
+		firing_counts,
+		frames: OrbitFrames::Lattice {
+			topple_rule: topple_rule.clone(),
+			boundary,
+			background_height,
+			initial_configuration: initial_configuration.to_vec(),
+			frames,
+		},
+	})
+}
+
+// parallel chip-firing on a graph without a sink, where every vertex holding at least its out-degree fires once at every
+// step. vertices without outgoing edges never fire, as in stabilize_graph
+pub fn graph_orbit(graph: Graph, initial_configuration: &[InitialVertex], background_height: u32, max_steps: usize, monitor: Option<&ComputeMonitor>) -> Result<Orbit, ComputeError> {
+	if let Some(sink) = graph.sink {
+		return Err(ComputeError::InvalidParameter(format!("parallel chip-firing only becomes periodic on a graph without a sink, but '{}' is one", graph.labels[sink])));
+	}
+	let vertex_count = graph.vertex_count();
+	if let Some(entry) = initial_configuration.iter().find(|entry| entry.vertex >= vertex_count) {
+		return Err(ComputeError::InvalidParameter(format!("there is no vertex {}", entry.vertex)));
+	}
+
+	let mut chips = vec![u64::from(background_height); vertex_count];
+	for entry in initial_configuration {
+		chips[entry.vertex] = chips[entry.vertex].checked_add(entry.value)
+			.ok_or_else(|| ComputeError::InvalidParameter("the number of chips doesn't fit in 64 bits".into()))?;
+	}
+	chips.iter().try_fold(0u64, |total, &value| total.checked_add(value))
+		.ok_or_else(|| ComputeError::InvalidParameter("the total number of chips doesn't fit in 64 bits".into()))?;
+
+	let degrees: Vec<u64> = (0..vertex_count).map(|vertex| graph.out_degree(vertex)).collect();
+	let fires = |chips: &[u64], vertex: usize| degrees[vertex] > 0 && chips[vertex] >= degrees[vertex];
+	let mut firing: Vec<usize> = Vec::new();
+	let mut step = |chips: &mut Vec<u64>| {
+		// every vertex that can fire does so at the same time, so they're all picked out before any chips move
+		firing.clear();
+		firing.extend((0..vertex_count).filter(|&vertex| fires(chips, vertex)));
+		for &vertex in &firing {
+			chips[vertex] -= degrees[vertex];
+			for &(target, multiplicity) in &graph.edges[vertex] {
+				chips[target] += u64::from(multiplicity);
+			}
+		}
+		firing.len() as u64
+	};
+
+	let cycle = find_cycle(&chips, &mut step, max_steps, vertex_count, monitor)?;
+	check_orbit_size(cycle.period, vertex_count)?;
+
+	let mut chips = cycle.entry;
+	let mut firing_counts = vec![0u64; vertex_count];
+	let mut frames = Vec::with_capacity(cycle.period);
+	for _ in 0..cycle.period {
+		for (vertex, count) in firing_counts.iter_mut().enumerate() {
+			if fires(&chips, vertex) {
+				*count += 1;
+			}
+		}
+		frames.push(chips.clone());
+		step(&mut chips);
+	}
+
+	Ok(Orbit {
+		transient_length: cycle.transient_length,
+		period: cycle.period,
+		firing_counts,
+		frames: OrbitFrames::Graph {
+			graph,
+			initial_configuration: initial_configuration.to_vec(),
+			background_height,
+			frames,
+		},
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::graph::parse_graph;
+
+	#[test]
+	fn finds_where_the_dynamics_loops() {
+		// 0, 1 and 2 lead into the cycle 3, 4, 5, 6
+		let cycle = find_cycle(&0u64, |state| { *state = if *state < 6 { *state + 1 } else { 3 }; 1 }, 100, 1, None).unwrap();
+		assert_eq!((cycle.transient_length, cycle.period, cycle.entry), (3, 4, 3));
+
+		assert!(matches!(find_cycle(&0u64, |state| { *state += 1; 1 }, 100, 1, None), Err(ComputeError::InvalidParameter(_))));
+	}
+
+	#[test]
+	fn orbit_of_a_path() {
+		// a fires into b, and then b fires back out to both ends, over and over
+		let graph = parse_graph("a b\nb c", false).unwrap();
+		let (a, b) = (graph.vertex("a").unwrap(), graph.vertex("b").unwrap());
+		let orbit = graph_orbit(graph, &[InitialVertex { vertex: a, value: 1 }, InitialVertex { vertex: b, value: 1 }], 0, 100, None).unwrap();
+		assert_eq!((orbit.transient_length, orbit.period), (1, 2));
+		assert_eq!(orbit.firing_counts, vec![1, 1, 1]);
+		match &orbit.frames {
+			OrbitFrames::Graph { frames, .. } => assert_eq!(frames, &vec![vec![0, 2, 0], vec![1, 0, 1]]),
+			_ => panic!("expected the frames of a graph"),
+		}
+	}
+
+	#[test]
+	fn lattice_orbit_starts_where_it_loops() {
+		let topple_rule = ToppleRule::von_neumann();
+		let boundary = Boundary::Torus { size: 6 };
+		let sources = [InitialCell { x: 0, y: 0, value: 9 }, InitialCell { x: 2, y: -1, value: 5 }];
+		let orbit = lattice_orbit(&sources, &topple_rule, boundary, 2, 10000, None).unwrap();
+		let frames = match &orbit.frames {
+			OrbitFrames::Lattice { frames, .. } => frames,
+			_ => panic!("expected the frames of a lattice"),
+		};
+		assert!(orbit.transient_length > 0 && orbit.period > 1);
+		assert_eq!(frames.len(), orbit.period);
+
+		// replay the dynamics: every frame shows up at its step, the one before the transient ends isn't on the cycle,
+		// and a whole period later it's back at the start
+		let (mut domain, mut cells) = SynchronousDomain::<u32>::new(&sources, &topple_rule, boundary, 2);
+		let mut history = vec![domain.domain_cells(&cells)];
+		for _ in 0..orbit.transient_length + orbit.period {
+			domain.step(&mut cells);
+			history.push(domain.domain_cells(&cells));
+		}
+		let as_bytes = |cells: &Vec<u32>| cells.iter().map(|&value| min(value, 255) as u8).collect::<Vec<u8>>();
+		for (index, frame) in frames.iter().enumerate() {
+			assert_eq!(&as_bytes(&history[orbit.transient_length + index]), frame);
+		}
+		assert_eq!(history[orbit.transient_length], history[orbit.transient_length + orbit.period]);
+		if orbit.transient_length > 0 {
+			assert_ne!(history[orbit.transient_length - 1], history[orbit.transient_length - 1 + orbit.period]);
+		}
+	}
+}
@@ -0,0 +1,35 @@
+// a small seeded generator (splitmix64), so that random runs can be repeated exactly
+#[derive(Clone, Debug)]
+pub struct Random {
+	state: u64,
+}
+
+impl Random {
+	pub fn new(seed: u64) -> Self {
+		Self { state: seed }
+	}
+
+	// a generator that only depends on the seed and the key, so that events can be given their own random numbers in
+	// whatever order they're processed
+	pub fn keyed(seed: u64, key: &[u64]) -> Self {
+		let mut random = Self::new(seed);
+		for &part in key {
+			random.state ^= part;
+			random.state = random.next_u64();
+		}
+		random
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut value = self.state;
+		value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		value ^ (value >> 31)
+	}
+
+	// uniformly distributed in 0..bound
+	pub fn below(&mut self, bound: u64) -> u64 {
+		((u128::from(self.next_u64()) * u128::from(bound)) >> 64) as u64
+	}
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::common::{Boundary, FractalResult, InitialCell, Model, ToppleRule};
+use crate::compute::{closed_domain_size, ComputeError, ComputeMonitor, Progress};
+
+// a deliberately simple engine to check the main one against. it keeps the cells in a hash map and topples them one
+// at a time from a queue, so it shares none of the array layout, chunking, folding or reallocation logic
+pub struct ReferencePile {
+	// every cell that has been touched. the rest hold the background
+	pub cells: HashMap<(i64, i64), u64>,
+	pub background_height: u64,
+	pub absorbed_grains: u64,
+	pub topples: u64,
+}
+
+impl ReferencePile {
+	pub fn value_at(&self, x: i64, y: i64) -> u64 {
+		self.cells.get(&(x, y)).cloned().unwrap_or(self.background_height)
+	}
+}
+
+const REPORT_INTERVAL: u64 = 1 << 16;
+
+// `expected_topples` is only used to estimate the progress
+pub fn stabilize_reference(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, background_height: u32, monitor: Option<&ComputeMonitor>, expected_topples: u64) -> Result<ReferencePile, ComputeError> {
+	let threshold = u64::from(topple_rule.threshold);
+	let background = u64::from(background_height);
+	if boundary == Boundary::Plane && background >= threshold {
+		return Err(ComputeError::NeverStabilizes(format!("a background height of {} is unstable everywhere on the plane", background_height)));
+	}
+
+	let mut pile = ReferencePile { cells: HashMap::new(), background_height: background, absorbed_grains: 0, topples: 0 };
+	if let Some(size) = boundary.domain_size() {
+		let start = boundary.domain_start();
+		for y in start..start + size as i64 {
+			for x in start..start + size as i64 {
+				pile.cells.insert((x, y), background);
+			}
+		}
+	}
+	for entry in initial_configuration {
+		*pile.cells.entry((entry.x, entry.y)).or_insert(background) += entry.value;
+	}
+
+	// on a domain where the pile keeps toppling once every cell has toppled, the cells that haven't yet
+	let mut untoppled: HashSet<(i64, i64)> = match closed_domain_size(topple_rule, Model::Abelian, boundary) {
+		Some(_) => pile.cells.keys().cloned().collect(),
+		None => HashSet::new(),
+	};
+
+	let mut queue: VecDeque<(i64, i64)> = pile.cells.iter().filter(|(_, &value)| value >= threshold).map(|(&cell, _)| cell).collect();
+	while let Some((x, y)) = queue.pop_front() {
+		let value = pile.cells[&(x, y)];
+		let times = value / threshold;
+		pile.cells.insert((x, y), value - times * threshold);
+		pile.topples += times;
+
+		if untoppled.remove(&(x, y)) && untoppled.is_empty() {
+			return Err(ComputeError::NeverStabilizes(format!("every cell of the {0}x{0} domain has toppled, so the pile will keep toppling forever", boundary.domain_size().unwrap())));
+		}
+
+		for neighbor in topple_rule.kernel_for(x, y) {
+			let amount = times * u64::from(neighbor.amount);
+			match boundary_target(boundary, x + neighbor.dx as i64, y + neighbor.dy as i64) {
+				Some(target) => {
+					let cell = pile.cells.entry(target).or_insert(background);
+					let before = *cell;
+					*cell += amount;
+					// a cell is queued when it becomes unstable, and toppled all the way down when it's taken off the queue,
+					// so it's never in the queue twice
+					if before < threshold && *cell >= threshold {
+						queue.push_back(target);
+					}
+				},
+				None => pile.absorbed_grains += amount,
+			}
+		}
+
+		if let Some(monitor) = monitor {
+			if pile.topples / REPORT_INTERVAL != (pile.topples - times) / REPORT_INTERVAL {
+				if monitor.is_cancelled() {
+					return Err(ComputeError::Cancelled);
+				}
+				monitor.report(Progress {
+					iterations: pile.topples as usize,
+					side_length: (pile.cells.len() as f64).sqrt() as usize,
+					redistributions: times,
+					estimated_completion: if expected_topples > 0 { f64::min(pile.topples as f64 / expected_topples as f64, 1.0) } else { 0.0 },
+				});
+			}
+		}
+	}
+
+	Ok(pile)
+}
+
+// where grains sent to (x, y) end up, or None if they leave through a sink
+fn boundary_target(boundary: Boundary, x: i64, y: i64) -> Option<(i64, i64)> {
+	let size = match boundary.domain_size() {
+		None => return Some((x, y)),
+		Some(size) => size as i64,
+	};
+	if boundary.contains(x, y) {
+		return Some((x, y));
+	}
+
+	let start = boundary.domain_start();
+	match boundary {
+		Boundary::Plane => Some((x, y)),
+		Boundary::Sink { .. } => None,
+		Boundary::Torus { .. } => Some(((x - start).rem_euclid(size) + start, (y - start).rem_euclid(size) + start)),
+		Boundary::Reflecting { .. } => {
+			// the walls are halfway between cells, so the cell just outside the domain mirrors the last cell inside it
+			let reflect = |position: i64| {
+				let position = (position - start).rem_euclid(size * 2);
+				(if position < size { position } else { size * 2 - 1 - position }) + start
+			};
+			Some((reflect(x), reflect(y)))
+		},
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+	pub x: i64,
+	pub y: i64,
+	pub engine_value: u64,
+	pub reference_value: u64,
+}
+
+const MAX_REPORTED_MISMATCHES: usize = 100;
+
+// the outcome of checking a result against the reference engine. on the plane, the grain counts only include the
+// grains above the background, since the background itself is infinite
+#[derive(Clone, Debug)]
+pub struct Verification {
+	// the first few cells that differ, in row order
+	pub mismatches: Vec<Mismatch>,
+	pub mismatch_count: usize,
+	pub input_grains: i64,
+	// what the result holds, and what it says it lost
+	pub remaining_grains: i64,
+	pub absorbed_grains: u64,
+	// grains lost by kernels that send out fewer grains than the threshold, worked out from the odometer
+	pub kernel_lost_grains: u64,
+	pub reference_absorbed_grains: u64,
+	pub engine_topples: u64,
+	pub reference_topples: u64,
+}
+
+impl Verification {
+	pub fn conserves_grains(&self) -> bool {
+		self.input_grains == self.remaining_grains + self.absorbed_grains as i64 + self.kernel_lost_grains as i64
+	}
+
+	pub fn passed(&self) -> bool {
+		self.mismatch_count == 0 && self.conserves_grains()
+			&& self.absorbed_grains == self.reference_absorbed_grains
+			&& self.engine_topples == self.reference_topples
+	}
+
+	pub fn report(&self) -> String {
+		let mut lines = vec![format!(
+			"{}: {} grains in, {} remaining, {} absorbed by the boundary, {} lost by the kernel",
+			if self.passed() { "Verified against the reference engine" } else { "Verification failed" },
+			self.input_grains, self.remaining_grains, self.absorbed_grains, self.kernel_lost_grains,
+		)];
+
+		if !self.conserves_grains() {
+			lines.push(format!("grains aren't conserved: {} are unaccounted for", self.input_grains - self.remaining_grains - self.absorbed_grains as i64 - self.kernel_lost_grains as i64));
+		}
+		if self.absorbed_grains != self.reference_absorbed_grains {
+			lines.push(format!("the reference engine absorbed {} grains", self.reference_absorbed_grains));
+		}
+		if self.engine_topples != self.reference_topples {
+			lines.push(format!("{} topples, but the reference engine made {}", self.engine_topples, self.reference_topples));
+		}
+		if self.mismatch_count > 0 {
+			lines.push(format!("{} cells differ from the reference engine:", self.mismatch_count));
+			for mismatch in self.mismatches.iter().take(10) {
+				lines.push(format!("  ({}, {}): {} instead of {}", mismatch.x, mismatch.y, mismatch.engine_value, mismatch.reference_value));
+			}
+		}
+		lines.join("\n")
+	}
+}
+
+// stabilizes the result's initial configuration again with the reference engine, and compares the two
+pub fn verify(result: &FractalResult, monitor: Option<&ComputeMonitor>) -> Result<Verification, ComputeError> {
+	let topple_rule = &result.topple_rule;
+	if result.model != Model::Abelian {
+		return Err(ComputeError::InvalidParameter(format!("the reference engine only computes the abelian model, not the {} model", result.model.name())));
+	}
+	if topple_rule.threshold > 256 {
+		return Err(ComputeError::InvalidParameter("verifying needs a threshold of at most 256, so that the stable heights fit in the result".into()));
+	}
+
+	let reference = stabilize_reference(&result.initial_configuration, topple_rule, result.boundary, result.background_height, monitor, result.total_topples)?;
+	let background = u64::from(result.background_height);
+
+	let mut mismatches = Vec::new();
+	let mut mismatch_count = 0;
+	let mut add_mismatch = |x: i64, y: i64, engine_value: u64, reference_value: u64| {
+		mismatch_count += 1;
+		if mismatches.len() < MAX_REPORTED_MISMATCHES {
+			mismatches.push(Mismatch { x, y, engine_value, reference_value });
+		}
+	};
+
+	for (index, &value) in result.sand_data.iter().enumerate() {
+		let (x, y) = result.index_to_world(index);
+		let reference_value = reference.value_at(x, y);
+		if u64::from(value) != reference_value {
+			add_mismatch(x, y, u64::from(value), reference_value);
+		}
+	}
+	// away from its array, the result holds the background
+	let mut outside: Vec<(&(i64, i64), &u64)> = reference.cells.iter()
+		.filter(|(&(x, y), &value)| result.world_to_index(x, y).is_none() && value != background)
+		.collect();
+	outside.sort_by_key(|(&(x, y), _)| (y, x));
+	for (&(x, y), &value) in outside {
+		add_mismatch(x, y, background, value);
+	}
+
+	let source_grains: u64 = result.initial_configuration.iter().map(|entry| entry.value).sum();
+	let remaining_grains: i64 = result.sand_data.iter().map(|&value| i64::from(value)).sum();
+	let (input_grains, remaining_grains) = match result.boundary.domain_size() {
+		Some(size) => (source_grains as i64 + (background * (size * size) as u64) as i64, remaining_grains),
+		None => (source_grains as i64, remaining_grains - (background * result.sand_data.len() as u64) as i64),
+	};
+
+	let kernel_lost_grains = result.odometer_data.iter().enumerate().map(|(index, &topples)| {
+		let (x, y) = result.index_to_world(index);
+		let sent: u64 = topple_rule.kernel_for(x, y).iter().map(|neighbor| u64::from(neighbor.amount)).sum();
+		topples * (u64::from(topple_rule.threshold) - sent)
+	}).sum();
+
+	Ok(Verification {
+		mismatches,
+		mismatch_count,
+		input_grains,
+		remaining_grains,
+		absorbed_grains: result.absorbed_grains,
+		kernel_lost_grains,
+		reference_absorbed_grains: reference.absorbed_grains,
+		engine_topples: result.total_topples,
+		reference_topples: reference.topples,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::common::{Symmetry, ToppleNeighbor};
+	use crate::compute::Engine;
+	use crate::compute::tests::{source, stabilize_with};
+
+	fn assert_verified(result: &FractalResult) {
+		let verification = verify(result, None).unwrap();
+		assert!(verification.passed(), "{}", verification.report());
+	}
+
+	#[test]
+	fn verifies_a_single_source() {
+		let result = stabilize_with::<u32>(&[source(0, 0, 5000)], &ToppleRule::von_neumann(), Boundary::Plane, Symmetry::None, 0, Engine::Dense).unwrap();
+		assert!(result.total_topples > 0);
+		assert_verified(&result);
+	}
+
+	#[test]
+	fn verifies_several_sources() {
+		let sources = [source(0, 0, 4000), source(12, -5, 2500), source(-8, 9, 3000)];
+		for &engine in &[Engine::Dense, Engine::Sparse] {
+			assert_verified(&stabilize_with::<u32>(&sources, &ToppleRule::moore(), Boundary::Plane, Symmetry::None, 0, engine).unwrap());
+			assert_verified(&stabilize_with::<u32>(&sources, &ToppleRule::von_neumann(), Boundary::Plane, Symmetry::None, 2, engine).unwrap());
+		}
+	}
+
+	#[test]
+	fn verifies_other_kernels() {
+		// sends out 6 of its 7 grains, so the verification has to account for the grains the kernel loses
+		let offsets = [(1, 0), (-1, 0), (0, 1), (0, -1), (2, 0), (-2, 0)];
+		let kernel = offsets.iter().map(|&(dx, dy)| ToppleNeighbor { dx, dy, amount: 1 }).collect();
+		let weighted = ToppleRule::weighted(7, kernel).unwrap();
+		let result = stabilize_with::<u32>(&[source(0, 0, 6000)], &weighted, Boundary::Plane, Symmetry::None, 0, Engine::Dense).unwrap();
+		let verification = verify(&result, None).unwrap();
+		assert!(verification.kernel_lost_grains > 0);
+		assert!(verification.passed(), "{}", verification.report());
+
+		for topple_rule in &[ToppleRule::hexagonal(), ToppleRule::triangular(), ToppleRule::extended_von_neumann(2)] {
+			assert_verified(&stabilize_with::<u32>(&[source(0, 0, 4000), source(3, 4, 1000)], topple_rule, Boundary::Plane, Symmetry::None, 0, Engine::Dense).unwrap());
+		}
+	}
+
+	#[test]
+	fn verifies_a_sink() {
+		let result = stabilize_with::<u32>(&[source(0, 0, 20000), source(7, -3, 5000)], &ToppleRule::von_neumann(), Boundary::Sink { size: 31 }, Symmetry::None, 2, Engine::Sparse).unwrap();
+		assert!(result.absorbed_grains > 0);
+		assert_verified(&result);
+	}
+
+	#[test]
+	fn reports_a_corrupted_cell() {
+		let mut result = stabilize_with::<u32>(&[source(0, 0, 5000)], &ToppleRule::von_neumann(), Boundary::Plane, Symmetry::None, 0, Engine::Dense).unwrap();
+		let index = result.world_to_index(2, 3).unwrap();
+		result.sand_data[index] = (result.sand_data[index] + 1) % 4;
+
+		let verification = verify(&result, None).unwrap();
+		assert!(!verification.passed());
+		assert_eq!(verification.mismatch_count, 1);
+		assert_eq!((verification.mismatches[0].x, verification.mismatches[0].y), (2, 3));
+		assert_eq!(u64::from(result.sand_data[index]), verification.mismatches[0].engine_value);
+	}
+
+	#[test]
+	fn piles_that_never_stabilize_on_a_torus() {
+		// more grains than edges with the background, but fewer than the torus can hold
+		let sources = [source(0, 0, 1000), source(10, 3, 500)];
+		let result = stabilize_reference(&sources, &ToppleRule::von_neumann(), Boundary::Torus { size: 32 }, 1, None, 0);
+		assert!(matches!(result, Err(ComputeError::NeverStabilizes(_))));
+
+		let pile = stabilize_reference(&sources, &ToppleRule::von_neumann(), Boundary::Torus { size: 32 }, 0, None, 0).unwrap();
+		assert_eq!(pile.cells.values().sum::<u64>(), 1500);
+	}
+}
@@ -1,10 +1,15 @@
 
-use image::{DynamicImage, ImageBuffer, ImageOutputFormat};
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat, RgbImage};
 use image::math::utils::clamp;
 use iced::image::Handle;
+use std::cmp::max;
 use std::io::Cursor;
 use std::sync::Arc;
-use crate::common::FractalResult;
+use crate::common::{DataField, FractalResult, Lattice};
+use crate::driven::{bin_center, DrivenResult};
+use crate::cubic::{VolumeResult, VolumeView};
+use crate::burning::Burning;
+use crate::timeline::load_timeline;
 
 #[derive(Clone, Debug)]
 pub enum ColorChannel {
@@ -42,6 +47,15 @@ pub struct RenderParams {
 	pub color1: RenderColor,
 	pub color2: RenderColor,
 	pub color3: RenderColor,
+
+	// size in pixels of hexagonal and triangular cells. square cells are always drawn as single pixels
+	pub cell_size: u32,
+
+	// the sand is drawn with one color per height, or a gradient over the stable heights when there are more of them than
+	// colors. the topple counts and the mass of a divisible sandpile are drawn as a gradient from the first color to the
+	// third, scaled to the largest value, and empty cells get the background color.
+	// rotors are drawn as a gradient over the positions of the rotor, on the cells particles have left
+	pub field: DataField,
 }
 
 impl Default for RenderParams {
@@ -51,23 +65,304 @@ impl Default for RenderParams {
             color1: RenderColor(image::Rgb([64,64,255])),
             color2: RenderColor(image::Rgb([255,255,64])),
             color3: RenderColor(image::Rgb([255,64,64])),
+            cell_size: 4,
+            field: DataField::Sand,
         }
     }
 }
 
 
 pub async fn render_fractal(params: RenderParams, fractal_data: Arc<FractalResult>) -> Handle {
-    let mut data_img = ImageBuffer::new(fractal_data.side_length as u32, fractal_data.side_length as u32);
-	for ((_,_, pixel), (fractal_value, _)) in data_img.enumerate_pixels_mut().zip(fractal_data.sand_data.iter().zip(fractal_data.count_data.iter())) {
-		*pixel = match fractal_value {
+	let colors = cell_colors(&params, &fractal_data);
+	encode_cells(&params, &fractal_data, colors)
+}
+
+// draws the pile with the cells the burning algorithm didn't reach lightened towards white, including the empty ones
+pub async fn render_burning(params: RenderParams, fractal_data: Arc<FractalResult>, burning: Arc<Burning>) -> Handle {
+	let colors = cell_colors(&params, &fractal_data).into_iter().zip(&burning.unburnt_mask).map(|(color, &unburnt)| {
+		if unburnt {
+			let color = color.unwrap_or(params.color0.0);
+			Some(image::Rgb([lighten(color[0]), lighten(color[1]), lighten(color[2])]))
+		} else {
+			color
+		}
+	}).collect();
+	encode_cells(&params, &fractal_data, colors)
+}
+
+fn lighten(channel: u8) -> u8 {
+	channel / 3 + 170
+}
+
+// renders every frame of a timeline to its own numbered image, so that they can be made into a video. returns the number
+// of frames
+pub async fn export_timeline(params: RenderParams, path: String, prefix: String) -> Result<usize, String> {
+	let timeline = load_timeline(&path).map_err(|error| format!("couldn't read timeline file '{}': {}", path, error))?;
+	// the frames only hold the sand
+	let params = RenderParams { field: DataField::Sand, ..params };
+	for index in 0..timeline.frames.len() {
+		let frame = timeline.frame_result(index);
+		let colors = cell_colors(&params, &frame);
+		let frame_path = format!("{}_{:05}.png", prefix, index);
+		draw_cells(&params, &frame, colors).save(&frame_path).map_err(|error| format!("couldn't write frame '{}': {}", frame_path, error))?;
+	}
+	Ok(timeline.frames.len())
+}
+
+fn draw_cells(params: &RenderParams, fractal_data: &FractalResult, colors: Vec<Option<image::Rgb<u8>>>) -> RgbImage {
+	match fractal_data.topple_rule.lattice {
+		Lattice::Square => render_square(params, fractal_data, colors),
+		Lattice::Hexagonal => render_hexagonal(params, fractal_data, colors),
+		Lattice::Triangular => render_triangular(params, fractal_data, colors),
+	}
+}
+
+fn encode_cells(params: &RenderParams, fractal_data: &FractalResult, colors: Vec<Option<image::Rgb<u8>>>) -> Handle {
+	let data_img = draw_cells(params, fractal_data, colors);
+
+    let mut cursor = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(data_img).write_to(&mut cursor, ImageOutputFormat::PNG).expect("Failed to encode image data to memory");
+    Handle::from_bytes(cursor.into_inner())
+}
+
+// heights 1, 2 and 3 get a color each. a threshold above 4 leaves more stable heights than that, so they're spread over
+// the gradient from the first color to the third instead, with the highest stable height in the third
+fn value_color(params: &RenderParams, value: u8, threshold: u32) -> image::Rgb<u8> {
+	if threshold <= 4 {
+		return match value {
 			0 => params.color0.0,
 			1 => params.color1.0,
 			2 => params.color2.0,
 			_ => params.color3.0,
+		};
+	}
+	match value {
+		0 => params.color0.0,
+		_ => gradient_color(params, ((f32::from(value) - 1.0) / (threshold - 2) as f32).min(1.0)),
+	}
+}
+
+fn gradient_color(params: &RenderParams, position: f32) -> image::Rgb<u8> {
+	let (from, to, position) = if position < 0.5 {
+		(&params.color1.0, &params.color2.0, position * 2.0)
+	} else {
+		(&params.color2.0, &params.color3.0, position * 2.0 - 1.0)
+	};
+	let mix = |channel: usize| (f32::from(from[channel]) + (f32::from(to[channel]) - f32::from(from[channel])) * position).round() as u8;
+	image::Rgb([mix(0), mix(1), mix(2)])
+}
+
+// the color of every cell, or None for the cells that are drawn as background
+fn cell_colors(params: &RenderParams, fractal_data: &FractalResult) -> Vec<Option<image::Rgb<u8>>> {
+	let num_cells = fractal_data.side_length * fractal_data.side_length;
+	// a pile has no rotors, so it's drawn with its sand when it's shown next to a rotor-router cluster
+	let field = if params.field == DataField::Rotor && fractal_data.rotor_data.is_empty() { DataField::Sand } else { params.field };
+	match field {
+		DataField::Sand if fractal_data.mass_data.is_empty() => fractal_data.sand_data.iter().map(|&value| if value != 0 { Some(value_color(params, value, fractal_data.topple_rule.threshold)) } else { None }).collect(),
+		DataField::Rotor => {
+			let last_position = max(fractal_data.topple_rule.threshold - 1, 1);
+			fractal_data.rotor_data.iter().zip(&fractal_data.odometer_data).map(|(&rotor, &sent)| {
+				if sent != 0 { Some(gradient_color(params, rotor as f32 / last_position as f32)) } else { None }
+			}).collect()
+		},
+		field => {
+			let largest = (0..num_cells).map(|index| fractal_data.field_value(field, index)).fold(0.0, f64::max);
+			(0..num_cells).map(|index| {
+				let value = fractal_data.field_value(field, index);
+				if value > 0.0 { Some(gradient_color(params, (value / largest) as f32)) } else { None }
+			}).collect()
+		},
+	}
+}
+
+fn render_square(params: &RenderParams, fractal_data: &FractalResult, colors: Vec<Option<image::Rgb<u8>>>) -> RgbImage {
+    let mut data_img = ImageBuffer::new(fractal_data.side_length as u32, fractal_data.side_length as u32);
+	for ((_,_, pixel), color) in data_img.enumerate_pixels_mut().zip(colors) {
+		*pixel = color.unwrap_or(params.color0.0);
+	}
+	data_img
+}
+
+// draws every non-empty cell as a polygon. `cell_center` gives the pixel position of a cell's center, and `is_inside` decides
+// whether a pixel offset from that center is inside the cell. empty cells are left as the background color
+fn render_shapes<C, I>(params: &RenderParams, fractal_data: &FractalResult, colors: Vec<Option<image::Rgb<u8>>>, half_width: f32, half_height: f32, cell_center: C, is_inside: I) -> RgbImage
+	where C: Fn(usize, usize) -> (f32, f32), I: Fn(usize, usize, f32, f32) -> bool
+{
+	let side_length = fractal_data.side_length;
+	let nonempty_cells: Vec<(usize, usize, image::Rgb<u8>)> = colors.into_iter().enumerate()
+		.filter_map(|(index, color)| color.map(|color| (index % side_length, index / side_length, color)))
+		.collect();
+
+	// crop the image to the non-empty cells, since the skewed layouts leave lots of empty space in the array
+	let mut min_x = std::f32::MAX;
+	let mut min_y = std::f32::MAX;
+	let mut max_x = std::f32::MIN;
+	let mut max_y = std::f32::MIN;
+	for &(x, y, _) in &nonempty_cells {
+		let (center_x, center_y) = cell_center(x, y);
+		min_x = min_x.min(center_x - half_width);
+		min_y = min_y.min(center_y - half_height);
+		max_x = max_x.max(center_x + half_width);
+		max_y = max_y.max(center_y + half_height);
+	}
+	if nonempty_cells.is_empty() {
+		return ImageBuffer::from_pixel(1, 1, params.color0.0);
+	}
+
+	let width = (max_x - min_x).ceil() as u32 + 1;
+	let height = (max_y - min_y).ceil() as u32 + 1;
+	let mut data_img = ImageBuffer::from_pixel(width, height, params.color0.0);
+
+	for &(x, y, color) in &nonempty_cells {
+		let (center_x, center_y) = cell_center(x, y);
+		let center_x = center_x - min_x;
+		let center_y = center_y - min_y;
+
+		let first_column = (center_x - half_width).floor().max(0.0) as u32;
+		let last_column = ((center_x + half_width).ceil() as u32).min(width - 1);
+		let first_row = (center_y - half_height).floor().max(0.0) as u32;
+		let last_row = ((center_y + half_height).ceil() as u32).min(height - 1);
+
+		for pixel_y in first_row..=last_row {
+			for pixel_x in first_column..=last_column {
+				let offset_x = pixel_x as f32 + 0.5 - center_x;
+				let offset_y = pixel_y as f32 + 0.5 - center_y;
+				if is_inside(x, y, offset_x, offset_y) {
+					data_img.put_pixel(pixel_x, pixel_y, color);
+				}
+			}
 		}
 	}
-    let mut cursor = Cursor::new(Vec::new());
-    DynamicImage::ImageRgb8(data_img).write_to(&mut cursor, ImageOutputFormat::PNG).expect("Failed to encode image data to memory");
-    Handle::from_bytes(cursor.into_inner())
+	data_img
+}
+
+// pointy-topped hexagons, positioned using the axial coordinates of the storage array
+fn render_hexagonal(params: &RenderParams, fractal_data: &FractalResult, colors: Vec<Option<image::Rgb<u8>>>) -> RgbImage {
+	let radius = params.cell_size as f32;
+	let half_width = radius * 3f32.sqrt() / 2.0;
+
+	render_shapes(params, fractal_data, colors, half_width, radius,
+		|x, y| (half_width * (2 * x + y) as f32, radius * 1.5 * y as f32),
+		|_, _, offset_x, offset_y| {
+			let offset_x = offset_x.abs();
+			offset_x <= half_width && offset_y.abs() <= radius - offset_x / 3f32.sqrt()
+		}
+	)
+}
+
+fn render_triangular(params: &RenderParams, fractal_data: &FractalResult, colors: Vec<Option<image::Rgb<u8>>>) -> RgbImage {
+	let half_width = params.cell_size as f32;
+	let height = half_width * 3f32.sqrt();
+	let origin_parity = (fractal_data.origin_x + fractal_data.origin_y).rem_euclid(2) as usize;
+
+	render_shapes(params, fractal_data, colors, half_width, height / 2.0,
+		|x, y| (half_width * (x + 1) as f32, height * (y as f32 + 0.5)),
+		|x, y, offset_x, offset_y| {
+			// how far down the triangle's row the pixel is, from 0 at the top to 1 at the bottom
+			let row_position = offset_y / height + 0.5;
+			let points_up = (x + y + origin_parity) % 2 == 0;
+			let allowed_width = if points_up { row_position } else { 1.0 - row_position };
+			row_position >= 0.0 && row_position <= 1.0 && offset_x.abs() <= half_width * allowed_width
+		}
+	)
+}
+
+// draws a slice or a maximum-intensity projection of a volume. every field, including the sand, is drawn as a gradient
+// scaled to the largest value in view, since the cubic lattice has more stable heights than there are colors
+pub async fn render_volume(params: RenderParams, volume: Arc<VolumeResult>, view: VolumeView) -> Handle {
+	let plane = volume.view_plane(params.field, view);
+	let largest = plane.iter().cloned().max().unwrap_or(0);
+
+	let side_length = volume.side_length as u32;
+	let mut data_img = ImageBuffer::new(side_length, side_length);
+	for ((_, _, pixel), &value) in data_img.enumerate_pixels_mut().zip(plane.iter()) {
+		*pixel = if value != 0 { gradient_color(&params, value as f32 / largest as f32) } else { params.color0.0 };
+	}
+
+	let mut cursor = Cursor::new(Vec::new());
+	DynamicImage::ImageRgb8(data_img).write_to(&mut cursor, ImageOutputFormat::PNG).expect("Failed to encode image data to memory");
+	Handle::from_bytes(cursor.into_inner())
 }
 
+const PLOT_WIDTH: u32 = 512;
+const PLOT_HEIGHT: u32 = 384;
+const PLOT_MARGIN: u32 = 16;
+
+// plots the avalanche distributions on log-log axes: a square for each non-empty bin, and a dotted line for each fit.
+// size, area, duration and radius use the first, second and third colors and white
+pub async fn render_distributions(params: RenderParams, driven_data: Arc<DrivenResult>) -> Handle {
+	let distributions = driven_data.distributions();
+	let colors = [params.color1.0, params.color2.0, params.color3.0, image::Rgb([255, 255, 255])];
+
+	let points: Vec<(f64, f64)> = distributions.iter().flat_map(|distribution| distribution.bins.iter())
+		.filter(|bin| bin.count > 0)
+		.map(|bin| (bin_center(bin).log10(), bin.density.log10()))
+		.collect();
+	let min_x = points.iter().map(|point| point.0).fold(std::f64::INFINITY, f64::min).min(0.0);
+	let max_x = points.iter().map(|point| point.0).fold(std::f64::NEG_INFINITY, f64::max).max(min_x + 1.0);
+	let min_y = points.iter().map(|point| point.1).fold(std::f64::INFINITY, f64::min).min(-1.0);
+	let max_y = points.iter().map(|point| point.1).fold(std::f64::NEG_INFINITY, f64::max).max(min_y + 1.0);
+
+	let inner_width = f64::from(PLOT_WIDTH - 2 * PLOT_MARGIN);
+	let inner_height = f64::from(PLOT_HEIGHT - 2 * PLOT_MARGIN);
+	let to_pixel = |x: f64, y: f64| -> (i64, i64) {
+		(
+			(f64::from(PLOT_MARGIN) + (x - min_x) / (max_x - min_x) * inner_width).round() as i64,
+			(f64::from(PLOT_MARGIN) + (max_y - y) / (max_y - min_y) * inner_height).round() as i64,
+		)
+	};
+
+	let mut plot_img: RgbImage = ImageBuffer::from_pixel(PLOT_WIDTH, PLOT_HEIGHT, params.color0.0);
+	let put = |img: &mut RgbImage, x: i64, y: i64, color: image::Rgb<u8>| {
+		if x >= 0 && y >= 0 && x < i64::from(PLOT_WIDTH) && y < i64::from(PLOT_HEIGHT) {
+			img.put_pixel(x as u32, y as u32, color);
+		}
+	};
+
+	// axes, with a tick at every power of ten
+	let axis_color = image::Rgb([128, 128, 128]);
+	let (origin_x, origin_y) = to_pixel(min_x, min_y);
+	for x in PLOT_MARGIN..PLOT_WIDTH - PLOT_MARGIN {
+		put(&mut plot_img, i64::from(x), origin_y, axis_color);
+	}
+	for y in PLOT_MARGIN..PLOT_HEIGHT - PLOT_MARGIN {
+		put(&mut plot_img, origin_x, i64::from(y), axis_color);
+	}
+	for decade in min_x.ceil() as i64..=max_x.floor() as i64 {
+		let (x, _) = to_pixel(decade as f64, min_y);
+		for offset in 1..5 {
+			put(&mut plot_img, x, origin_y - offset, axis_color);
+		}
+	}
+	for decade in min_y.ceil() as i64..=max_y.floor() as i64 {
+		let (_, y) = to_pixel(min_x, decade as f64);
+		for offset in 1..5 {
+			put(&mut plot_img, origin_x + offset, y, axis_color);
+		}
+	}
+
+	for (distribution, &color) in distributions.iter().zip(colors.iter()) {
+		if let Some(fit) = distribution.fit {
+			for step in (0..inner_width as i64).step_by(4) {
+				let x = min_x + step as f64 / inner_width * (max_x - min_x);
+				let y = fit.amplitude.log10() - fit.exponent * x;
+				if y >= min_y && y <= max_y {
+					let (pixel_x, pixel_y) = to_pixel(x, y);
+					put(&mut plot_img, pixel_x, pixel_y, color);
+				}
+			}
+		}
+		for bin in distribution.bins.iter().filter(|bin| bin.count > 0) {
+			let (pixel_x, pixel_y) = to_pixel(bin_center(bin).log10(), bin.density.log10());
+			for dy in -2..=2 {
+				for dx in -2..=2 {
+					put(&mut plot_img, pixel_x + dx, pixel_y + dy, color);
+				}
+			}
+		}
+	}
+
+	let mut cursor = Cursor::new(Vec::new());
+	DynamicImage::ImageRgb8(plot_img).write_to(&mut cursor, ImageOutputFormat::PNG).expect("Failed to encode image data to memory");
+	Handle::from_bytes(cursor.into_inner())
+}
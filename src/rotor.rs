@@ -0,0 +1,357 @@
+use std::cmp::max;
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use crate::common::{Boundary, FractalResult, InitialCell, Model, Symmetry, ToppleNeighbor, ToppleRule};
+use crate::compute::{ComputeError, ComputeMonitor, Progress};
+
+// every cell keeps a rotor with this many positions at most, one for each grain the threshold stands for
+const MAX_CYCLE_LENGTH: u32 = 1 << 16;
+
+const REPORT_INTERVAL: u64 = 1 << 16;
+
+// the smallest array the plane starts out with
+const MIN_SIDE_LENGTH: usize = 16;
+
+// where successive particles leaving a cell go, one entry per position of its rotor. a rotor goes round the kernel
+// clockwise in the storage array, starting from straight up, and stops at each neighbor once for every grain a topple
+// sends it. then it stops once for every grain the kernel loses, and the particles sent there are lost
+fn rotor_cycle(kernel: &[ToppleNeighbor], threshold: u32) -> Vec<Option<(isize, isize)>> {
+	let mut neighbors: Vec<&ToppleNeighbor> = kernel.iter().collect();
+	neighbors.sort_by(|a, b| clockwise_angle(a).partial_cmp(&clockwise_angle(b)).unwrap());
+
+	let mut cycle = Vec::with_capacity(threshold as usize);
+	for neighbor in neighbors {
+		for _ in 0..neighbor.amount {
+			cycle.push(Some((neighbor.dx, neighbor.dy)));
+		}
+	}
+	cycle.resize(threshold as usize, None);
+	cycle
+}
+
+// y grows downwards in the array, so going clockwise from straight up is going anticlockwise from (0, -1) in the usual
+// orientation
+fn clockwise_angle(neighbor: &ToppleNeighbor) -> f64 {
+	(neighbor.dx as f64).atan2(-neighbor.dy as f64).rem_euclid(2.0 * PI)
+}
+
+// the cells particles have reached so far. on the plane, the array grows whenever a cell near its edge has to send
+// particles on
+struct Cluster {
+	particles: Vec<u64>,
+	rotors: Vec<u32>,
+	count_data: Vec<u32>,
+	odometer_data: Vec<u64>,
+	side_length: usize,
+	origin_x: i64,
+	origin_y: i64,
+}
+
+impl Cluster {
+	fn new(side_length: usize, origin_x: i64, origin_y: i64, initial_rotor: u32) -> Self {
+		let num_cells = side_length * side_length;
+		Cluster {
+			particles: vec![0; num_cells],
+			rotors: vec![initial_rotor; num_cells],
+			count_data: vec![0; num_cells],
+			odometer_data: vec![0; num_cells],
+			side_length,
+			origin_x,
+			origin_y,
+		}
+	}
+
+	fn index(&self, x: i64, y: i64) -> usize {
+		((y - self.origin_y) as usize) * self.side_length + (x - self.origin_x) as usize
+	}
+
+	fn is_near_edge(&self, x: i64, y: i64, margin: usize) -> bool {
+		let margin = margin as i64;
+		let end = self.side_length as i64 - margin;
+		let (array_x, array_y) = (x - self.origin_x, y - self.origin_y);
+		array_x < margin || array_y < margin || array_x >= end || array_y >= end
+	}
+
+	// doubles the array, keeping the cells in the middle
+	fn grow(&mut self, initial_rotor: u32) {
+		let quarter = self.side_length / 2;
+		let mut grown = Cluster::new(self.side_length * 2, self.origin_x - quarter as i64, self.origin_y - quarter as i64, initial_rotor);
+		for y in 0..self.side_length {
+			let from = y * self.side_length;
+			let to = (y + quarter) * grown.side_length + quarter;
+			grown.particles[to..to + self.side_length].copy_from_slice(&self.particles[from..from + self.side_length]);
+			grown.rotors[to..to + self.side_length].copy_from_slice(&self.rotors[from..from + self.side_length]);
+			grown.count_data[to..to + self.side_length].copy_from_slice(&self.count_data[from..from + self.side_length]);
+			grown.odometer_data[to..to + self.side_length].copy_from_slice(&self.odometer_data[from..from + self.side_length]);
+		}
+		*self = grown;
+	}
+}
+
+// where a particle sent to (x, y) ends up, or None if it leaves through a sink boundary
+fn wrap(x: i64, y: i64, boundary: Boundary) -> Option<(i64, i64)> {
+	let start = boundary.domain_start();
+	match boundary {
+		Boundary::Plane => Some((x, y)),
+		Boundary::Sink { .. } => if boundary.contains(x, y) { Some((x, y)) } else { None },
+		Boundary::Torus { size } => {
+			let size = size as i64;
+			Some(((x - start).rem_euclid(size) + start, (y - start).rem_euclid(size) + start))
+		},
+		Boundary::Reflecting { size } => {
+			let size = size as i64;
+			let reflect = |position: i64| {
+				let position = (position - start).rem_euclid(2 * size);
+				if position < size { position + start } else { 2 * size - 1 - position + start }
+			};
+			Some((reflect(x), reflect(y)))
+		},
+	}
+}
+
+// rotor-router aggregation: the particles of each source walk one at a time until they reach a cell no particle has
+// stopped on yet, and stop there. every cell a particle leaves turns its rotor on by one position, see rotor_cycle, and
+// sends the particle where the rotor points. all rotors start out on their last position, so that the first particle
+// to leave a cell takes the first one. like the abelian pile, the cluster and the rotors don't depend on the order the
+// particles move in, so particles that pile up on a cell are sent on together, going round the rotor in bulk.
+// the result has one particle on each occupied cell, the final rotor positions, how often each cell sent particles on
+// as the topple steps, and how many particles each cell sent on as the odometer
+pub fn rotor_aggregation(initial_configuration: &[InitialCell], topple_rule: &ToppleRule, boundary: Boundary, monitor: Option<&ComputeMonitor>) -> Result<FractalResult, ComputeError> {
+	if topple_rule.threshold > MAX_CYCLE_LENGTH {
+		return Err(ComputeError::InvalidParameter(format!("the rotor-router model needs a threshold of at most {}", MAX_CYCLE_LENGTH)));
+	}
+	if let Some(entry) = initial_configuration.iter().find(|entry| !boundary.contains(entry.x, entry.y)) {
+		return Err(ComputeError::InvalidParameter(format!("source ({}, {}) is outside the domain", entry.x, entry.y)));
+	}
+	let total_particles = initial_configuration.iter().try_fold(0u64, |total, entry| total.checked_add(entry.value))
+		.ok_or_else(|| ComputeError::InvalidParameter("the total number of particles doesn't fit in 64 bits".into()))?;
+	if let Boundary::Torus { size } | Boundary::Reflecting { size } = boundary {
+		if topple_rule.grains_sent() == u64::from(topple_rule.threshold) && total_particles > (size * size) as u64 {
+			return Err(ComputeError::NeverStabilizes(format!("{} particles can't each find an empty cell on a {}x{} domain", total_particles, size, size)));
+		}
+	}
+
+	let even_cycle = rotor_cycle(&topple_rule.kernel, topple_rule.threshold);
+	let odd_cycle = match &topple_rule.odd_kernel {
+		Some(odd_kernel) => rotor_cycle(odd_kernel, topple_rule.threshold),
+		None => even_cycle.clone(),
+	};
+	let cycle_length = u64::from(topple_rule.threshold);
+	let initial_rotor = topple_rule.threshold - 1;
+	let margin = max(topple_rule.radius(), 1);
+
+	let mut cluster = match boundary.domain_size() {
+		Some(size) => Cluster::new(size, boundary.domain_start(), boundary.domain_start(), initial_rotor),
+		None => {
+			let min_x = initial_configuration.iter().map(|entry| entry.x).min().unwrap_or(0);
+			let min_y = initial_configuration.iter().map(|entry| entry.y).min().unwrap_or(0);
+			let max_x = initial_configuration.iter().map(|entry| entry.x).max().unwrap_or(0);
+			let max_y = initial_configuration.iter().map(|entry| entry.y).max().unwrap_or(0);
+			let extent = max(max_x - min_x, max_y - min_y) as usize + 1;
+			let side_length = max(extent + 4 * margin, MIN_SIDE_LENGTH);
+			Cluster::new(side_length, min_x - 2 * margin as i64, min_y - 2 * margin as i64, initial_rotor)
+		},
+	};
+
+	// cells holding more than one particle, which still have to send the rest on
+	let mut unsettled: VecDeque<(i64, i64)> = VecDeque::new();
+	let mut occupied_cells: u64 = 0;
+	for entry in initial_configuration.iter().filter(|entry| entry.value > 0) {
+		let index = cluster.index(entry.x, entry.y);
+		let before = cluster.particles[index];
+		cluster.particles[index] += entry.value;
+		if before == 0 {
+			occupied_cells += 1;
+		}
+		if before <= 1 && cluster.particles[index] > 1 {
+			unsettled.push_back((entry.x, entry.y));
+		}
+	}
+
+	let mut lost_particles: u64 = 0;
+	let mut total_iterations: u64 = 0;
+	while let Some((x, y)) = unsettled.pop_front() {
+		if boundary == Boundary::Plane {
+			while cluster.is_near_edge(x, y, margin) {
+				cluster.grow(initial_rotor);
+			}
+		}
+		let index = cluster.index(x, y);
+		let leaving = cluster.particles[index] - 1;
+		cluster.particles[index] = 1;
+		cluster.count_data[index] += 1;
+		cluster.odometer_data[index] += leaving;
+
+		// every position of the rotor gets the full rounds, and the ones just after where it points get one more. when
+		// there are fewer particles than positions, only those ones are visited
+		let rounds = leaving / cycle_length;
+		let rest = leaving % cycle_length;
+		let rotor = u64::from(cluster.rotors[index]);
+		let cycle = if (x + y).rem_euclid(2) == 1 { &odd_cycle } else { &even_cycle };
+		for step in 1..=(if rounds == 0 { rest } else { cycle_length }) {
+			let target = cycle[((rotor + step) % cycle_length) as usize];
+			let sent = rounds + if step <= rest { 1 } else { 0 };
+			match target.and_then(|(dx, dy)| wrap(x + dx as i64, y + dy as i64, boundary)) {
+				Some((target_x, target_y)) => {
+					let target_index = cluster.index(target_x, target_y);
+					let before = cluster.particles[target_index];
+					cluster.particles[target_index] += sent;
+					if before == 0 {
+						occupied_cells += 1;
+					}
+					if before <= 1 && cluster.particles[target_index] > 1 {
+						unsettled.push_back((target_x, target_y));
+					}
+				},
+				None => lost_particles += sent,
+			}
+		}
+		cluster.rotors[index] = ((rotor + rest) % cycle_length) as u32;
+
+		total_iterations += 1;
+		if let Some(monitor) = monitor {
+			if total_iterations % REPORT_INTERVAL == 0 {
+				if monitor.is_cancelled() {
+					return Err(ComputeError::Cancelled);
+				}
+				monitor.report(Progress {
+					iterations: total_iterations as usize,
+					side_length: cluster.side_length,
+					redistributions: leaving,
+					estimated_completion: (occupied_cells + lost_particles) as f64 / total_particles as f64,
+				});
+			}
+		}
+	}
+
+	let cluster = if boundary == Boundary::Plane { crop_to_occupied(&cluster, initial_rotor) } else { cluster };
+	let total_redistributions = cluster.count_data.iter().map(|&count| u64::from(count)).sum();
+	let total_topples = cluster.odometer_data.iter().sum();
+	Ok(FractalResult {
+		initial_configuration: initial_configuration.to_vec(),
+		topple_rule: topple_rule.clone(),
+		model: Model::RotorRouter,
+		symmetry: Symmetry::None,
+		boundary,
+		background_height: 0,
+		sand_data: cluster.particles.iter().map(|&particles| particles as u8).collect(),
+		mass_data: Vec::new(),
+		rotor_data: cluster.rotors,
+		count_data: cluster.count_data,
+		odometer_data: cluster.odometer_data,
+		odometer_mass: Vec::new(),
+		side_length: cluster.side_length,
+		origin_x: cluster.origin_x,
+		origin_y: cluster.origin_y,
+
+		total_redistributions,
+		total_topples,
+		absorbed_grains: lost_particles,
+		absorbed_mass: 0.0,
+		total_iterations: total_iterations as usize,
+	})
+}
+
+// the smallest square around the occupied cells, taking the cells outside the array to be empty
+fn crop_to_occupied(cluster: &Cluster, initial_rotor: u32) -> Cluster {
+	let occupied = || (0..cluster.particles.len()).filter(|&index| cluster.particles[index] > 0)
+		.map(|index| ((index % cluster.side_length) as i64 + cluster.origin_x, (index / cluster.side_length) as i64 + cluster.origin_y));
+	let min_x = occupied().map(|(x, _)| x).min().unwrap_or(0);
+	let min_y = occupied().map(|(_, y)| y).min().unwrap_or(0);
+	let max_x = occupied().map(|(x, _)| x).max().unwrap_or(0);
+	let max_y = occupied().map(|(_, y)| y).max().unwrap_or(0);
+	let side_length = max(max_x - min_x, max_y - min_y) as usize + 1;
+
+	let mut cropped = Cluster::new(side_length, min_x, min_y, initial_rotor);
+	for y in min_y..min_y + side_length as i64 {
+		for x in min_x..min_x + side_length as i64 {
+			if x < cluster.origin_x || y < cluster.origin_y || x >= cluster.origin_x + cluster.side_length as i64 || y >= cluster.origin_y + cluster.side_length as i64 {
+				continue;
+			}
+			let (from, to) = (cluster.index(x, y), cropped.index(x, y));
+			cropped.particles[to] = cluster.particles[from];
+			cropped.rotors[to] = cluster.rotors[from];
+			cropped.count_data[to] = cluster.count_data[from];
+			cropped.odometer_data[to] = cluster.odometer_data[from];
+		}
+	}
+	cropped
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::{HashMap, HashSet};
+
+	fn occupied_cells(result: &FractalResult) -> u64 {
+		result.sand_data.iter().filter(|&&particles| particles > 0).count() as u64
+	}
+
+	#[test]
+	fn every_particle_stops_or_is_lost() {
+		let sources = [InitialCell { x: 0, y: 0, value: 500 }, InitialCell { x: 6, y: -3, value: 200 }];
+		let result = rotor_aggregation(&sources, &ToppleRule::von_neumann(), Boundary::Sink { size: 21 }, None).unwrap();
+		assert!(result.absorbed_grains > 0);
+		assert_eq!(occupied_cells(&result) + result.absorbed_grains, 700);
+
+		let result = rotor_aggregation(&sources, &ToppleRule::moore(), Boundary::Plane, None).unwrap();
+		assert_eq!(result.absorbed_grains, 0);
+		assert_eq!(occupied_cells(&result), 700);
+	}
+
+	#[test]
+	fn single_source_is_close_to_a_disk() {
+		// all rotors start out pointing the same way, so the cluster isn't exactly symmetric, but the symmetries of the
+		// lattice only move cells within the last layer of the disk
+		let result = rotor_aggregation(&[InitialCell { x: 0, y: 0, value: 5000 }], &ToppleRule::von_neumann(), Boundary::Plane, None).unwrap();
+		let radius = (5000.0 / PI).sqrt();
+		let reach = radius as i64 + 3;
+		for y in -reach..=reach {
+			for x in -reach..=reach {
+				let occupied = result.world_to_index(x, y).map_or(0, |index| result.sand_data[index]) > 0;
+				let distance = ((x * x + y * y) as f64).sqrt();
+				assert!(occupied || distance >= radius - 1.0, "({}, {}) is empty", x, y);
+				assert!(!occupied || distance <= radius + 1.0, "({}, {}) is occupied", x, y);
+			}
+		}
+		assert!(result.side_length as i64 <= 2 * reach);
+	}
+
+	#[test]
+	fn moving_particles_in_bulk_matches_one_at_a_time() {
+		let topple_rule = ToppleRule::von_neumann();
+		let boundary = Boundary::Sink { size: 15 };
+		let sources = [InitialCell { x: 0, y: 0, value: 150 }, InitialCell { x: -4, y: 3, value: 60 }];
+		let result = rotor_aggregation(&sources, &topple_rule, boundary, None).unwrap();
+
+		// walk every particle on its own, turning the rotor of every cell it leaves
+		let cycle = rotor_cycle(&topple_rule.kernel, topple_rule.threshold);
+		let mut occupied: HashSet<(i64, i64)> = HashSet::new();
+		let mut rotors: HashMap<(i64, i64), usize> = HashMap::new();
+		let mut lost = 0;
+		for entry in &sources {
+			for _ in 0..entry.value {
+				let mut position = Some((entry.x, entry.y));
+				while let Some((x, y)) = position {
+					if occupied.insert((x, y)) {
+						break;
+					}
+					let rotor = rotors.entry((x, y)).or_insert(cycle.len() - 1);
+					*rotor = (*rotor + 1) % cycle.len();
+					position = cycle[*rotor].and_then(|(dx, dy)| wrap(x + dx as i64, y + dy as i64, boundary));
+				}
+				if position.is_none() {
+					lost += 1;
+				}
+			}
+		}
+
+		assert_eq!(result.absorbed_grains, lost);
+		for index in 0..result.sand_data.len() {
+			let position = result.index_to_world(index);
+			assert_eq!(result.sand_data[index] > 0, occupied.contains(&position), "{:?}", position);
+			assert_eq!(result.rotor_data[index] as usize, rotors.get(&position).cloned().unwrap_or(cycle.len() - 1), "{:?}", position);
+		}
+	}
+}
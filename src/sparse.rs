@@ -0,0 +1,161 @@
+use std::cmp::{min, max};
+use rayon::prelude::*;
+use crate::compute::Grains;
+
+const TILE_WIDTH: usize = 64;
+
+// Keeps track of which parts of the array contain cells that are able to topple, so that the sparse engine can skip the rest.
+//
+// Rows are grouped into bands that line up with the rows each parallel chunk processes: band 2k is processed by chunk k
+// in the first pass, and band 2k+1 by chunk k in the second pass. Each band is split into tiles of TILE_WIDTH columns.
+// The rows and columns at the edges of the array that are never processed belong to the first and last bands and tiles.
+pub struct ActiveTiles {
+	side_length: usize,
+	margin: usize,
+	tile_width: usize,
+	num_bands: usize,
+	num_tiles: usize,
+	active: Vec<bool>,
+	// tiles that were changed by something other than a topple, and need to be checked during the next advance
+	touched: Vec<bool>,
+}
+
+impl ActiveTiles {
+	pub fn new<T: Grains>(data: &[T], side_length: usize, margin: usize, threshold: T) -> Self {
+		let rows_per_chunk = margin * 4;
+		let num_bands = side_length / rows_per_chunk + side_length.saturating_sub(rows_per_chunk / 2) / rows_per_chunk;
+
+		// a tile has to be at least as wide as the margin, so that topples only reach adjacent tiles
+		let tile_width = max(TILE_WIDTH, margin * 4);
+		let num_tiles = (side_length + tile_width - 1) / tile_width;
+
+		let mut tiles = Self {
+			side_length,
+			margin,
+			tile_width,
+			num_bands,
+			num_tiles,
+			active: vec![true; num_bands * num_tiles],
+			touched: vec![false; num_bands * num_tiles],
+		};
+		tiles.active = tiles.scan(data, threshold, &tiles.active);
+		tiles
+	}
+
+	// calls `process` with the column range of each active tile in the given band, and returns the sum of the results
+	pub fn process_band<F: FnMut(usize, usize) -> u64>(&self, band: usize, mut process: F) -> u64 {
+		if band >= self.num_bands {
+			return 0;
+		}
+
+		let mut num_redistributions = 0;
+		for tile in 0..self.num_tiles {
+			if self.active[band * self.num_tiles + tile] {
+				let first_column = max(tile * self.tile_width, self.margin);
+				let last_column = min((tile + 1) * self.tile_width, self.side_length - self.margin);
+				if first_column < last_column {
+					num_redistributions += process(first_column, last_column);
+				}
+			}
+		}
+		num_redistributions
+	}
+
+	// after an iteration, copies every tile that might have changed from `src` to `dst`, and works out which tiles are active now.
+	// topples only reach as far as one margin, so the only tiles that can change are the active ones and their neighbors
+	pub fn advance<T: Grains>(&mut self, src: &[T], dst: &mut [T], threshold: T) {
+		let mut candidates = self.dilate();
+		for (candidate, touched) in candidates.iter_mut().zip(self.touched.iter_mut()) {
+			*candidate = *candidate || *touched;
+			*touched = false;
+		}
+
+		let mut src_bands = Vec::with_capacity(self.num_bands);
+		let mut dst_bands = Vec::with_capacity(self.num_bands);
+		let mut src_remaining = src;
+		let mut dst_remaining = &mut *dst;
+		for band in 0..self.num_bands {
+			let (first_row, last_row) = self.band_rows(band);
+			let band_size = (last_row - first_row) * self.side_length;
+
+			let (src_band, src_rest) = src_remaining.split_at(band_size);
+			let (dst_band, dst_rest) = dst_remaining.split_at_mut(band_size);
+			src_bands.push(src_band);
+			dst_bands.push(dst_band);
+			src_remaining = src_rest;
+			dst_remaining = dst_rest;
+		}
+
+		self.active = src_bands.into_par_iter().zip(dst_bands.into_par_iter()).enumerate().flat_map(|(band, (src_band, dst_band))| {
+			(0..self.num_tiles).map(|tile| {
+				if !candidates[band * self.num_tiles + tile] {
+					return false;
+				}
+
+				let (first_column, last_column) = self.tile_columns(tile);
+				let mut active = false;
+				for (src_row, dst_row) in src_band.chunks(self.side_length).zip(dst_band.chunks_mut(self.side_length)) {
+					let src_cells = &src_row[first_column..last_column];
+					dst_row[first_column..last_column].copy_from_slice(src_cells);
+					active = active || src_cells.iter().any(|&value| value >= threshold);
+				}
+				active
+			}).collect::<Vec<bool>>()
+		}).collect();
+	}
+
+	// marks every tile within `near` cells of the top or left edge, or within `far` cells of the bottom or right edge, as touched
+	pub fn touch_frame(&mut self, near: usize, far: usize) {
+		let far_start = self.side_length.saturating_sub(far);
+		let in_frame = |(first, last): (usize, usize)| first < near || last > far_start;
+
+		for band in 0..self.num_bands {
+			let band_in_frame = in_frame(self.band_rows(band));
+			for tile in 0..self.num_tiles {
+				if band_in_frame || in_frame(self.tile_columns(tile)) {
+					self.touched[band * self.num_tiles + tile] = true;
+				}
+			}
+		}
+	}
+
+	// marks a tile active if it's one of the candidates and has a cell at or above the threshold
+	fn scan<T: Grains>(&self, data: &[T], threshold: T, candidates: &[bool]) -> Vec<bool> {
+		(0..self.num_bands).into_par_iter().flat_map(|band| {
+			let (first_row, last_row) = self.band_rows(band);
+			let band_data = &data[first_row * self.side_length..last_row * self.side_length];
+
+			(0..self.num_tiles).map(|tile| {
+				let (first_column, last_column) = self.tile_columns(tile);
+				candidates[band * self.num_tiles + tile]
+					&& band_data.chunks(self.side_length).any(|row| row[first_column..last_column].iter().any(|&value| value >= threshold))
+			}).collect::<Vec<bool>>()
+		}).collect()
+	}
+
+	fn dilate(&self) -> Vec<bool> {
+		let mut result = vec![false; self.active.len()];
+		for band in 0..self.num_bands {
+			for tile in 0..self.num_tiles {
+				if self.active[band * self.num_tiles + tile] {
+					for neighbor_band in band.saturating_sub(1)..min(band + 2, self.num_bands) {
+						for neighbor_tile in tile.saturating_sub(1)..min(tile + 2, self.num_tiles) {
+							result[neighbor_band * self.num_tiles + neighbor_tile] = true;
+						}
+					}
+				}
+			}
+		}
+		result
+	}
+
+	fn band_rows(&self, band: usize) -> (usize, usize) {
+		let first_row = if band == 0 { 0 } else { self.margin + band * self.margin * 2 };
+		let last_row = if band + 1 == self.num_bands { self.side_length } else { self.margin + (band + 1) * self.margin * 2 };
+		(first_row, last_row)
+	}
+
+	fn tile_columns(&self, tile: usize) -> (usize, usize) {
+		(tile * self.tile_width, min((tile + 1) * self.tile_width, self.side_length))
+	}
+}
@@ -0,0 +1,179 @@
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+
+use bincode::{serialize_into, deserialize_from};
+use serde_derive::{Serialize, Deserialize};
+use crate::common::{Boundary, FractalResult, Model, Symmetry, ToppleRule};
+
+// when to take snapshots of the pile while it stabilizes. the initial and final configurations are always recorded
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Schedule {
+	// every so many iterations
+	Every(usize),
+	// a fixed number of snapshots each time the number of iterations doubles, so that the fast early growth is covered
+	// as well as the slow late stages
+	Logarithmic(usize),
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimelineHeader {
+	topple_rule: ToppleRule,
+	model: Model,
+	boundary: Boundary,
+	background_height: u32,
+}
+
+// a snapshot of the pile. every frame has its own extent, since the array grows and gets re-centered while computing
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Frame {
+	pub iteration: usize,
+	pub side_length: usize,
+	// world coordinates of the first cell
+	pub origin_x: i64,
+	pub origin_y: i64,
+	// run-length encoded cells, as (run length, value) pairs
+	runs: Vec<(u32, u8)>,
+}
+
+impl Frame {
+	pub fn cells(&self) -> Vec<u8> {
+		let mut cells = Vec::with_capacity(self.side_length * self.side_length);
+		for &(length, value) in &self.runs {
+			cells.extend(std::iter::repeat(value).take(length as usize));
+		}
+		cells
+	}
+}
+
+// writes frames to a timeline file as they're recorded, so that long runs don't have to keep them in memory
+pub struct TimelineRecorder {
+	file: BufWriter<fs::File>,
+	schedule: Schedule,
+	next_iteration: usize,
+	last_iteration: Option<usize>,
+}
+
+impl TimelineRecorder {
+	pub fn create(path: &str, schedule: Schedule, topple_rule: &ToppleRule, model: Model, boundary: Boundary, background_height: u32) -> io::Result<Self> {
+		let mut file = BufWriter::new(fs::File::create(path)?);
+		let header = TimelineHeader { topple_rule: topple_rule.clone(), model, boundary, background_height };
+		serialize_into(&mut file, &header).map_err(to_io_error)?;
+
+		Ok(Self { file, schedule, next_iteration: 0, last_iteration: None })
+	}
+
+	pub fn wants_frame(&self, iteration: usize) -> bool {
+		iteration >= self.next_iteration
+	}
+
+	pub fn record(&mut self, iteration: usize, cells: &[u8], side_length: usize, origin_x: i64, origin_y: i64) -> io::Result<()> {
+		if self.last_iteration == Some(iteration) {
+			return Ok(());
+		}
+
+		let mut runs: Vec<(u32, u8)> = Vec::new();
+		for &value in cells {
+			match runs.last_mut() {
+				Some((length, last_value)) if *last_value == value && *length < std::u32::MAX => *length += 1,
+				_ => runs.push((1, value)),
+			}
+		}
+
+		let frame = Frame { iteration, side_length, origin_x, origin_y, runs };
+		serialize_into(&mut self.file, &frame).map_err(to_io_error)?;
+
+		self.last_iteration = Some(iteration);
+		self.next_iteration = match self.schedule {
+			Schedule::Every(interval) => iteration + interval.max(1),
+			Schedule::Logarithmic(frames_per_doubling) => {
+				let factor = 2f64.powf(1.0 / frames_per_doubling.max(1) as f64);
+				(iteration as f64 * factor).ceil() as usize
+			},
+		}.max(iteration + 1);
+		Ok(())
+	}
+
+	pub fn finish(mut self) -> io::Result<()> {
+		self.file.flush()
+	}
+}
+
+pub struct Timeline {
+	pub topple_rule: ToppleRule,
+	pub model: Model,
+	pub boundary: Boundary,
+	pub background_height: u32,
+	pub frames: Vec<Frame>,
+}
+
+impl Timeline {
+	// turns a frame into a result that can be rendered. every frame is placed on the extent of the whole timeline,
+	// so that the frames of a video line up
+	pub fn frame_result(&self, index: usize) -> FractalResult {
+		let min_x = self.frames.iter().map(|frame| frame.origin_x).min().unwrap_or(0);
+		let min_y = self.frames.iter().map(|frame| frame.origin_y).min().unwrap_or(0);
+		let max_x = self.frames.iter().map(|frame| frame.origin_x + frame.side_length as i64).max().unwrap_or(0);
+		let max_y = self.frames.iter().map(|frame| frame.origin_y + frame.side_length as i64).max().unwrap_or(0);
+		let side_length = std::cmp::max(max_x - min_x, max_y - min_y) as usize;
+
+		let frame = &self.frames[index];
+		let background = std::cmp::min(self.background_height, 255) as u8;
+		let mut sand_data = vec![background; side_length * side_length];
+		for (frame_row, row) in frame.cells().chunks(frame.side_length).zip(sand_data.chunks_mut(side_length).skip((frame.origin_y - min_y) as usize)) {
+			let first_column = (frame.origin_x - min_x) as usize;
+			row[first_column..first_column + frame.side_length].copy_from_slice(frame_row);
+		}
+
+		FractalResult {
+			initial_configuration: Vec::new(),
+			topple_rule: self.topple_rule.clone(),
+			model: self.model,
+			symmetry: Symmetry::None,
+			boundary: self.boundary,
+			background_height: self.background_height,
+			sand_data: sand_data,
+			mass_data: Vec::new(),
+			rotor_data: Vec::new(),
+			count_data: vec![0; side_length * side_length],
+			odometer_data: vec![0; side_length * side_length],
+			odometer_mass: Vec::new(),
+			side_length: side_length,
+			origin_x: min_x,
+			origin_y: min_y,
+
+			total_redistributions: 0,
+			total_topples: 0,
+			absorbed_grains: 0,
+			absorbed_mass: 0.0,
+			total_iterations: frame.iteration,
+		}
+	}
+}
+
+pub fn load_timeline(path: &str) -> io::Result<Timeline> {
+	let mut file = BufReader::new(fs::File::open(path)?);
+	let header: TimelineHeader = deserialize_from(&mut file).map_err(to_io_error)?;
+
+	let mut frames = Vec::new();
+	loop {
+		match deserialize_from::<_, Frame>(&mut file) {
+			Ok(frame) => frames.push(frame),
+			Err(error) => match *error {
+				bincode::ErrorKind::Io(ref io_error) if io_error.kind() == io::ErrorKind::UnexpectedEof => break,
+				_ => return Err(to_io_error(error)),
+			},
+		}
+	}
+
+	Ok(Timeline {
+		topple_rule: header.topple_rule,
+		model: header.model,
+		boundary: header.boundary,
+		background_height: header.background_height,
+		frames,
+	})
+}
+
+fn to_io_error(error: bincode::Error) -> io::Error {
+	io::Error::new(io::ErrorKind::Other, error.to_string())
+}